@@ -0,0 +1,328 @@
+//! analysis looks for players getting help from an engine, by solving the positions of their
+//! archived games and measuring how often they played a best move compared to everyone else
+//!
+//! Positions where the best move is obvious (the opening, immediate wins, forced blocks, and
+//! positions where every move scores the same) say nothing about a player, so they are not judged
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{Bitboard, BoardEngine};
+
+use constants::solver::{Position, Solver};
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashMap, net::IpAddr};
+
+/// A game that was played on the server, as written to its game archive, one JSON object per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedGame {
+    pub lobby: String,
+    /// When the lobby ended, in seconds since the Unix epoch
+    pub ended_at: u64,
+    /// Addresses player 1 and player 2 last joined from
+    pub players: [Option<IpAddr>; 2],
+    /// Columns played, in order
+    pub moves: Vec<u8>,
+    pub result: GameResult,
+}
+
+/// How a game ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    Player1,
+    Player2,
+    Draw,
+    /// The lobby ended before the game did
+    Unfinished,
+}
+
+impl GameResult {
+    /// Returns how the game with the given columns played ended
+    pub fn of(moves: &[u8]) -> Self {
+        let mut board = Bitboard::default();
+        for (i, &col) in moves.iter().enumerate() {
+            let player_num = i as u8 % 2 + 1;
+            match board.drop_disk(player_num, col) {
+                Ok(true) if player_num == 1 => return GameResult::Player1,
+                Ok(true) => return GameResult::Player2,
+                Ok(false) => {}
+                Err(_) => return GameResult::Unfinished,
+            }
+        }
+        if board.is_full() {
+            GameResult::Draw
+        } else {
+            GameResult::Unfinished
+        }
+    }
+}
+
+/// How moves are judged and players are flagged
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Moves at the start of each game which are not judged, as openings are often memorised
+    pub skip_plies: usize,
+    /// Most positions the solver may search to judge a single move, moves needing more are not judged
+    pub node_budget: u64,
+    /// Fewest judged moves a player must have to be flagged
+    pub min_positions: usize,
+    /// Standard deviations a player's match rate must be above the baseline to be flagged
+    pub z_threshold: f64,
+    /// Expected rate of playing a best move, instead of the rate of every other player
+    pub baseline: Option<f64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            skip_plies: 8,
+            node_budget: 20_000_000,
+            min_positions: 30,
+            z_threshold: 3.0,
+            baseline: None,
+        }
+    }
+}
+
+/// A move compared with every other move in its position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JudgedMove {
+    /// Index of the move in the game
+    pub ply: usize,
+    pub player_num: u8,
+    pub col: u8,
+    /// Score of playing each column for the player (see Solver::solve), None for full columns
+    pub scores: Vec<Option<i32>>,
+}
+
+impl JudgedMove {
+    /// Returns the score of the best column
+    pub fn best(&self) -> i32 {
+        self.scores
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the score of the column played
+    pub fn played(&self) -> i32 {
+        self.scores[self.col as usize].unwrap_or_default()
+    }
+
+    /// Returns whether the move was one of the best
+    pub fn matched(&self) -> bool {
+        self.played() == self.best()
+    }
+
+    /// Returns whether the move kept the result best play would have got: a win, draw or loss
+    pub fn accurate(&self) -> bool {
+        self.played().signum() == self.best().signum()
+    }
+}
+
+/// Solves the position before each move of the game, and judges the moves whose positions are
+/// worth judging, in order
+/// Stops at the first illegal move, and once the game is won
+pub fn judge_game(moves: &[u8], settings: &Settings, solver: &mut Solver) -> Vec<JudgedMove> {
+    let mut judged = Vec::new();
+    let mut position = Position::default();
+    for (ply, &col) in moves.iter().enumerate() {
+        if !position.can_play(col) || position.is_winning_move(col) {
+            break;
+        }
+        if ply >= settings.skip_plies && !is_obvious(&position) {
+            let scores = solver.scores(&position);
+            if let Some(scores) = scores.filter(|scores| !all_equal(scores)) {
+                judged.push(JudgedMove {
+                    ply,
+                    player_num: ply as u8 % 2 + 1,
+                    col,
+                    scores: scores.to_vec(),
+                });
+            }
+        }
+        position.play(col);
+    }
+    judged
+}
+
+/// Returns whether the player can win at once, or has at most one move which does not let the
+/// opponent win at once
+fn is_obvious(position: &Position) -> bool {
+    position.can_win_next() || position.non_losing_moves().count_ones() <= 1
+}
+
+/// Returns whether every playable column scores the same
+fn all_equal(scores: &[Option<i32>]) -> bool {
+    let mut playable = scores.iter().flatten();
+    let first = playable.next();
+    playable.all(|score| Some(score) == first)
+}
+
+/// Counts of judged moves
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Tally {
+    pub positions: usize,
+    /// Moves which were one of the best
+    pub matches: usize,
+    /// Moves which kept the result best play would have got
+    pub accurate: usize,
+}
+
+impl Tally {
+    /// Counts the moves made by the player
+    pub fn of(judged: &[JudgedMove], player_num: u8) -> Self {
+        let mut tally = Self::default();
+        for judged in judged
+            .iter()
+            .filter(|judged| judged.player_num == player_num)
+        {
+            tally.positions += 1;
+            tally.matches += judged.matched() as usize;
+            tally.accurate += judged.accurate() as usize;
+        }
+        tally
+    }
+
+    /// Returns the fraction of moves which were one of the best
+    pub fn match_rate(&self) -> f64 {
+        ratio(self.matches, self.positions)
+    }
+
+    /// Returns the fraction of moves which kept the result best play would have got
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.accurate, self.positions)
+    }
+
+    fn add(&mut self, other: &Tally) {
+        self.positions += other.positions;
+        self.matches += other.matches;
+        self.accurate += other.accurate;
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// How a player did in one game
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerGame {
+    pub lobby: String,
+    pub ended_at: u64,
+    pub player_num: u8,
+    pub result: GameResult,
+    pub positions: usize,
+    pub match_rate: f64,
+    pub accuracy: f64,
+}
+
+/// How a player did across every game they played, and whether that looks like engine assistance
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerReport {
+    pub player: IpAddr,
+    pub positions: usize,
+    pub matches: usize,
+    pub match_rate: f64,
+    pub accuracy: f64,
+    /// Match rate expected of the player
+    pub baseline: f64,
+    /// Standard deviations the player's match rate is above the baseline
+    pub z_score: f64,
+    pub flagged: bool,
+    /// The player's games, oldest first, to see how their play changed over time
+    pub games: Vec<PlayerGame>,
+}
+
+/// Gathers the judged moves of each game by player, and flags players whose match rate is too
+/// far above the baseline to be chance
+/// Unless a baseline is given, each player is compared with the pooled rate of every other player
+/// Returns the players with the most suspicious first
+pub fn report(games: &[(ArchivedGame, Vec<JudgedMove>)], settings: &Settings) -> Vec<PlayerReport> {
+    let mut by_player: HashMap<IpAddr, (Tally, Vec<PlayerGame>)> = HashMap::new();
+    let mut total = Tally::default();
+    for (game, judged) in games {
+        for (player, player_num) in game.players.iter().zip(1..) {
+            let player = match player {
+                Some(player) => *player,
+                None => continue,
+            };
+            let tally = Tally::of(judged, player_num);
+            let (player_tally, player_games) = by_player.entry(player).or_default();
+            player_tally.add(&tally);
+            total.add(&tally);
+            player_games.push(PlayerGame {
+                lobby: game.lobby.clone(),
+                ended_at: game.ended_at,
+                player_num,
+                result: game.result,
+                positions: tally.positions,
+                match_rate: tally.match_rate(),
+                accuracy: tally.accuracy(),
+            });
+        }
+    }
+
+    let mut reports: Vec<PlayerReport> = by_player
+        .into_iter()
+        .map(|(player, (tally, mut games))| {
+            games.sort_by_key(|game| game.ended_at);
+            let baseline = settings.baseline.unwrap_or_else(|| {
+                ratio(
+                    total.matches - tally.matches,
+                    total.positions - tally.positions,
+                )
+            });
+            let z_score = z_score(tally.matches, tally.positions, baseline);
+            PlayerReport {
+                player,
+                positions: tally.positions,
+                matches: tally.matches,
+                match_rate: tally.match_rate(),
+                accuracy: tally.accuracy(),
+                baseline,
+                z_score,
+                flagged: tally.positions >= settings.min_positions
+                    && z_score >= settings.z_threshold,
+                games,
+            }
+        })
+        .collect();
+    reports.sort_by(|a, b| b.z_score.total_cmp(&a.z_score));
+    reports
+}
+
+/// Returns how many standard deviations the matches are above what the baseline rate would give,
+/// treating each position as an independent trial
+fn z_score(matches: usize, positions: usize, baseline: f64) -> f64 {
+    let expected = positions as f64 * baseline;
+    let variance = expected * (1.0 - baseline);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    (matches as f64 - expected) / variance.sqrt()
+}
@@ -0,0 +1,235 @@
+//! board-analyze reads the server's game archive and looks for players getting help from an engine,
+//! by comparing each of their moves with the moves a perfect solver would make
+//!
+//! Prints each player's match rate (moves which were one of the best) and accuracy (moves which
+//! kept the result best play would have got) against a baseline, with their games over time
+//! for any player flagged as suspicious
+//!
+//! Exits with 2 if the archive could not be read
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::analysis::{self, ArchivedGame, JudgedMove, PlayerReport, Settings};
+use constants::solver::Solver;
+
+use std::{
+    fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// game archive written by the server with --game-archive
+    #[argh(positional)]
+    archive: PathBuf,
+
+    /// moves at the start of each game not to judge (default 8)
+    #[argh(option, default = "8")]
+    skip_plies: usize,
+
+    /// most positions the solver may search to judge a move (default 20000000)
+    #[argh(option, default = "20_000_000")]
+    node_budget: u64,
+
+    /// fewest judged moves a player must have to be flagged (default 30)
+    #[argh(option, default = "30")]
+    min_positions: usize,
+
+    /// standard deviations above the baseline a player's match rate must be to be flagged (default 3)
+    #[argh(option, default = "3.0")]
+    z_threshold: f64,
+
+    /// expected match rate, between 0 and 1 (default the pooled rate of every other player)
+    #[argh(option)]
+    baseline: Option<f64>,
+
+    /// games to judge at once, each with its own solver (default the number of cores)
+    #[argh(option)]
+    jobs: Option<usize>,
+
+    /// only analyze games which ended at or after this time, in seconds since the Unix epoch
+    #[argh(option, default = "0")]
+    since: u64,
+
+    /// write the report as JSON
+    #[argh(switch)]
+    json: bool,
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    let settings = Settings {
+        skip_plies: cli_options.skip_plies,
+        node_budget: cli_options.node_budget,
+        min_positions: cli_options.min_positions,
+        z_threshold: cli_options.z_threshold,
+        baseline: cli_options.baseline,
+    };
+
+    let contents = match fs::read_to_string(&cli_options.archive) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", cli_options.archive.display(), e);
+            process::exit(2);
+        }
+    };
+    let mut games = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ArchivedGame>(line) {
+            Ok(game) if game.ended_at >= cli_options.since => games.push(game),
+            Ok(_) => {}
+            Err(e) => eprintln!("Skipped line {}: {}", i + 1, e),
+        }
+    }
+
+    let jobs = cli_options
+        .jobs
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .max(1);
+    let num_games = games.len();
+    let judged = judge_games(games, &settings, jobs);
+    let reports = analysis::report(&judged, &settings);
+
+    if cli_options.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&reports).unwrap_or_default()
+        );
+        return;
+    }
+
+    let num_judged: usize = judged.iter().map(|(_, judged)| judged.len()).sum();
+    println!(
+        "Judged {} moves in {} games from {}.",
+        num_judged,
+        num_games,
+        cli_options.archive.display()
+    );
+    println!();
+    println!(
+        "{:<40} {:>5} {:>6} {:>6} {:>8} {:>8} {:>6}",
+        "player", "games", "judged", "match", "accuracy", "baseline", "z"
+    );
+    for report in &reports {
+        println!(
+            "{:<40} {:>5} {:>6} {:>6} {:>8} {:>8} {:>6.2}{}",
+            report.player,
+            report.games.len(),
+            report.positions,
+            percent(report.match_rate),
+            percent(report.accuracy),
+            percent(report.baseline),
+            report.z_score,
+            if report.flagged { "  FLAGGED" } else { "" }
+        );
+    }
+
+    let flagged: Vec<&PlayerReport> = reports.iter().filter(|report| report.flagged).collect();
+    println!();
+    if flagged.is_empty() {
+        println!("No players flagged.");
+    }
+    for report in flagged {
+        println!("{} by game, oldest first:", report.player);
+        println!(
+            "  {:>10} {:<16} {:>6} {:>6} {:>8} {:>10}",
+            "ended at", "lobby", "player", "judged", "match", "accuracy"
+        );
+        for game in &report.games {
+            println!(
+                "  {:>10} {:<16} {:>6} {:>6} {:>8} {:>10}",
+                game.ended_at,
+                game.lobby,
+                game.player_num,
+                game.positions,
+                rate(game.match_rate, game.positions),
+                rate(game.accuracy, game.positions)
+            );
+        }
+        println!();
+    }
+}
+
+/// Judges the games on the given number of threads, each taking the next game not yet judged
+/// Returns the games in the order given, along with their judged moves
+fn judge_games(
+    games: Vec<ArchivedGame>,
+    settings: &Settings,
+    jobs: usize,
+) -> Vec<(ArchivedGame, Vec<JudgedMove>)> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let mut judged: Vec<(usize, Vec<JudgedMove>)> = thread::scope(|scope| {
+        let threads: Vec<_> = (0..jobs.min(games.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut solver = Solver::new(settings.node_budget, Solver::LARGE_TABLE);
+                    let mut judged = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let game = match games.get(i) {
+                            Some(game) => game,
+                            None => return judged,
+                        };
+                        judged.push((i, analysis::judge_game(&game.moves, settings, &mut solver)));
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        eprint!("\rJudged {} of {} games", done, games.len());
+                    }
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap_or_default())
+            .collect()
+    });
+    if !games.is_empty() {
+        eprintln!();
+    }
+    judged.sort_unstable_by_key(|(i, _)| *i);
+    games
+        .into_iter()
+        .zip(judged)
+        .map(|(game, (_, judged))| (game, judged))
+        .collect()
+}
+
+/// Formats a fraction as a percentage
+fn percent(fraction: f64) -> String {
+    format!("{:.1}%", fraction * 100.0)
+}
+
+/// Formats a rate over a number of judged moves as a percentage, or "-" if there were none
+fn rate(fraction: f64, positions: usize) -> String {
+    if positions == 0 {
+        "-".to_string()
+    } else {
+        percent(fraction)
+    }
+}
@@ -0,0 +1,196 @@
+//! board-book writes an opening book for the web app's AIs, which play second: every position up
+//! to a number of disks into the game which the first player can reach while the AI plays the
+//! book's moves
+//!
+//! For each position the AI moves in, the book holds its exact score and one move which reaches
+//! it, and every reply to that move is followed to the next position the AI moves in. The book is
+//! written again each time the AI's moves a number of disks in are done, so a long run keeps what
+//! it has solved if it is stopped
+//!
+//! Exits with 2 if the book could not be written
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use constants::{
+    book,
+    book::OpeningBook,
+    solver::{Position, Solver},
+    BOARD_WIDTH,
+};
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// file to write the opening book to
+    #[argh(positional)]
+    output: PathBuf,
+
+    /// most disks a position in the book can have (default 8)
+    #[argh(option, default = "8")]
+    max_ply: u8,
+
+    /// positions to solve at once, each with its own solver (default the number of cores)
+    #[argh(option)]
+    jobs: Option<usize>,
+
+    /// megabytes of transposition table, shared out between the solvers and kept for the whole
+    /// run (default 1024)
+    #[argh(option, default = "1024")]
+    table_mb: usize,
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    let started = Instant::now();
+
+    let jobs = cli_options
+        .jobs
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .max(1);
+    // Each entry of a table is 8 bytes, and an odd number of them spreads positions evenly
+    let table_size = ((cli_options.table_mb << 20) / 8 / jobs) | 1;
+    let mut solvers: Vec<Solver> = (0..jobs)
+        .map(|_| Solver::new(u64::MAX, table_size))
+        .collect();
+
+    // Writing an empty book first finds out whether the book can be written before solving
+    let mut entries = Vec::new();
+    let mut written = write(&cli_options.output, 0, &entries);
+    let mut positions = replies(&[Position::default()]);
+    let mut ply = 1;
+    while ply < cli_options.max_ply && !positions.is_empty() {
+        eprintln!("{} positions with {} disks", positions.len(), ply);
+        let mut moves_made = Vec::new();
+        for (position, score, col) in best_moves(&positions, &mut solvers) {
+            let (current, mask) = position.disks();
+            entries.push((book::key(current, mask), score as i8));
+            // The game ends with a winning move, so there is nothing more to follow
+            if !position.is_winning_move(col) {
+                let mut next = position;
+                next.play(col);
+                let (current, mask) = next.disks();
+                entries.push((book::key(current, mask), -score as i8));
+                moves_made.push(next);
+            }
+        }
+        written = write(&cli_options.output, ply + 1, &entries);
+        eprintln!(
+            "Wrote positions with up to {} disks after {:.1}s",
+            ply + 1,
+            started.elapsed().as_secs_f64()
+        );
+        positions = replies(&moves_made);
+        ply += 2;
+    }
+
+    let (len, bytes) = written;
+    println!(
+        "Wrote {} positions ({} bytes) to {} in {:.1}s.",
+        len,
+        bytes,
+        cli_options.output.display(),
+        started.elapsed().as_secs_f64()
+    );
+}
+
+/// Returns every position one of the positions leads to with a move which does not win the game,
+/// with only one of each position and its mirror image
+fn replies(positions: &[Position]) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    let mut replies = Vec::new();
+    for position in positions {
+        for col in 0..BOARD_WIDTH {
+            if !position.can_play(col) || position.is_winning_move(col) {
+                continue;
+            }
+            let mut reply = *position;
+            reply.play(col);
+            let (current, mask) = reply.disks();
+            if seen.insert(book::key(current, mask)) {
+                replies.push(reply);
+            }
+        }
+    }
+    replies
+}
+
+/// Solves the positions, each solver on its own thread taking the next position not yet solved,
+/// and returns each position with its score and the first of its best moves
+fn best_moves(positions: &[Position], solvers: &mut [Solver]) -> Vec<(Position, i32, u8)> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let solved = Mutex::new(Vec::with_capacity(positions.len()));
+    let started = Instant::now();
+    thread::scope(|scope| {
+        for solver in solvers.iter_mut() {
+            scope.spawn(|| {
+                while let Some(position) = positions.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    // The solver has no node budget, so it always finishes
+                    let (score, best) = solver.best_moves(position).unwrap_or_default();
+                    let col = best.first().copied().unwrap_or_default();
+                    solved
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((*position, score, col));
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    let elapsed = started.elapsed().as_secs_f64();
+                    eprint!(
+                        "\rSolved {} of {} positions, about {:.0}s left",
+                        done,
+                        positions.len(),
+                        elapsed / done as f64 * (positions.len() - done) as f64
+                    );
+                }
+            });
+        }
+    });
+    if !positions.is_empty() {
+        eprintln!();
+    }
+    solved.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Writes the book of positions with at most max_ply disks, and returns how many positions and
+/// bytes it has
+/// Exits if the book cannot be written
+fn write(path: &Path, max_ply: u8, entries: &[(u64, i8)]) -> (usize, usize) {
+    let book = OpeningBook::new(max_ply, entries.to_vec());
+    let bytes = book.to_bytes();
+    if let Err(e) = fs::write(path, &bytes) {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+        process::exit(2);
+    }
+    (book.len(), bytes.len())
+}
@@ -0,0 +1,145 @@
+//! board-conformance checks a C++ board plugin against the built-in board, reporting every scenario
+//! where the plugin behaves differently and the position where it first did
+//!
+//! With --json, writes each scenario's result as a line of JSON instead, as it finishes, for
+//! board-grade to read (see conformance::ScenarioResult)
+//!
+//! Exits with 0 if every scenario passed, 1 if any failed, and 2 if the library failed to load
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::{
+    conformance::{self, Divergence, ScenarioResult},
+    isolation, Plugin,
+};
+
+use std::{io::Write, path::PathBuf, process};
+
+/// Most failing scenarios listed after the first divergence
+const MAX_LISTED: usize = 20;
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// shared library implementing board.hpp
+    #[argh(positional)]
+    library: PathBuf,
+
+    /// random games to play (default 1000)
+    #[argh(option, default = "1000")]
+    random_games: usize,
+
+    /// seed for the random games (default 0)
+    #[argh(option, default = "0")]
+    seed: u64,
+
+    /// scenario to start from, counting from 0, to carry on after a board crashed (default 0)
+    #[argh(option, default = "0")]
+    start: usize,
+
+    /// write each scenario's result as a line of JSON, with anything the board prints sent to stderr
+    #[argh(switch)]
+    json: bool,
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    // Set up before the library is loaded, as loading it runs its code
+    let json_output = cli_options.json.then(isolation::protocol_output);
+
+    // Safety: the library is run to be checked, and a board which crashes ends this process with it
+    let plugin = match unsafe { Plugin::load(&cli_options.library) } {
+        Ok(plugin) => plugin,
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", cli_options.library.display(), e);
+            process::exit(2);
+        }
+    };
+
+    let mut scenarios = conformance::scenarios(cli_options.random_games, cli_options.seed);
+    scenarios.drain(..cli_options.start.min(scenarios.len()));
+    if let Some(mut out) = json_output {
+        let mut all_passed = true;
+        for scenario in &scenarios {
+            let result = match conformance::run(scenario, &mut plugin.new_board()) {
+                Some(divergence) => ScenarioResult::Failed(divergence),
+                None => ScenarioResult::Passed(scenario.name.clone()),
+            };
+            all_passed &= matches!(result, ScenarioResult::Passed(_));
+            let line = serde_json::to_string(&result).unwrap_or_default();
+            if writeln!(out, "{}", line).and_then(|_| out.flush()).is_err() {
+                process::exit(1);
+            }
+        }
+        process::exit(if all_passed { 0 } else { 1 });
+    }
+
+    println!(
+        "Checking {} against the built-in board, with {} random games (seed {}).",
+        cli_options.library.display(),
+        cli_options.random_games,
+        cli_options.seed
+    );
+    let report = conformance::check(&scenarios, || plugin.new_board());
+
+    if report.is_pass() {
+        println!("PASS: all {} scenarios passed.", report.scenarios);
+        return;
+    }
+    println!(
+        "FAIL: {} of {} scenarios passed.",
+        report.passed(),
+        report.scenarios
+    );
+
+    let first = &report.failures[0];
+    println!();
+    println!("First divergence: {}", describe(first));
+    println!("{:<10} {:<10} actual", "before", "expected");
+    let boards = [first.before, first.expected, first.actual].map(conformance::render);
+    for ((before, expected), actual) in boards[0].iter().zip(&boards[1]).zip(&boards[2]) {
+        println!("{:<10} {:<10} {}", before, expected, actual);
+    }
+
+    if report.failures.len() > 1 {
+        println!();
+        println!("Other failing scenarios:");
+        for divergence in report.failures[1..].iter().take(MAX_LISTED) {
+            println!("  {}", describe(divergence));
+        }
+        if report.failures.len() - 1 > MAX_LISTED {
+            println!("  ... and {} more", report.failures.len() - 1 - MAX_LISTED);
+        }
+    }
+    process::exit(1);
+}
+
+/// Describes the scenario and move where the board diverged, and how
+fn describe(divergence: &Divergence) -> String {
+    format!(
+        "\"{}\", move {} (player {} into column {}): {}",
+        divergence.scenario,
+        divergence.move_index + 1,
+        divergence.played.player_num,
+        divergence.played.col,
+        divergence.reason
+    )
+}
@@ -0,0 +1,524 @@
+//! board-grade grades a directory of student C++ boards, building each from source if needed, and
+//! checking each with board-conformance in a process of its own, so a board which crashes or hangs
+//! only fails its own submission
+//!
+//! Usage: board-grade <submissions> [--output report.csv | report.json]
+//!
+//! Each entry in the submissions directory is a student: either a shared library, or a directory
+//! holding board.cc or a built shared library
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::conformance::{self, ScenarioResult};
+use serde::Serialize;
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest compiler or board error kept in the report
+const MAX_ERROR_LENGTH: usize = 2000;
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// directory with a shared library, or a directory holding board.cc or a shared library, for each student
+    #[argh(positional)]
+    submissions: PathBuf,
+
+    /// file to write the report to, as JSON if it ends in .json and CSV otherwise (default CSV to stdout)
+    #[argh(option)]
+    output: Option<PathBuf>,
+
+    /// seconds each board has to finish every scenario (default 60)
+    #[argh(option, default = "60")]
+    timeout_secs: u64,
+
+    /// random games to play (default 1000)
+    #[argh(option, default = "1000")]
+    random_games: usize,
+
+    /// seed for the random games (default 0)
+    #[argh(option, default = "0")]
+    seed: u64,
+
+    /// compiler to build C++ boards with (default g++)
+    #[argh(option, default = "String::from(\"g++\")")]
+    cxx: String,
+
+    /// directory holding board.hpp and safe_board.cc (default server/cpplib in this repository)
+    #[argh(option)]
+    cpplib: Option<PathBuf>,
+
+    /// directory to build boards in (default board-grade in the temporary directory)
+    #[argh(option)]
+    build_dir: Option<PathBuf>,
+}
+
+/// How far grading a submission got
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// Every scenario was run
+    Graded,
+    /// No board.cc or shared library was found
+    Missing,
+    CompileError,
+    LoadError,
+    /// The board ended the process in some scenarios, which failed, and the rest were run again
+    Crashed,
+    /// The board did not finish every scenario in time
+    TimedOut,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Graded => "graded",
+            Status::Missing => "missing",
+            Status::CompileError => "compile_error",
+            Status::LoadError => "load_error",
+            Status::Crashed => "crashed",
+            Status::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// A scenario the board failed, and the move it first went wrong on
+#[derive(Serialize)]
+struct FailedCase {
+    scenario: String,
+    /// Move in the scenario, counting from 1, if the board diverged on a move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    move_number: Option<usize>,
+    reason: String,
+    /// Boards drawn from top to bottom, with '1' and '2' for each player's disks and '.' for empty spaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<Vec<String>>,
+}
+
+/// A student's grade
+#[derive(Serialize)]
+struct Grade {
+    student: String,
+    status: Status,
+    passed: usize,
+    scenarios: usize,
+    /// Percentage of scenarios passed
+    score: f64,
+    /// What went wrong, for submissions which could not be fully graded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    failed: Vec<FailedCase>,
+}
+
+impl Grade {
+    fn new(student: String, scenarios: usize) -> Self {
+        Self {
+            student,
+            status: Status::Graded,
+            passed: 0,
+            scenarios,
+            score: 0.0,
+            error: None,
+            failed: Vec::new(),
+        }
+    }
+
+    /// Marks the submission as not fully graded
+    fn fail(mut self, status: Status, error: String) -> Self {
+        self.status = status;
+        self.error = Some(truncate(error.trim()));
+        self
+    }
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+
+    let conformance = match env::current_exe() {
+        Ok(exe) => exe.with_file_name(format!("board-conformance{}", env::consts::EXE_SUFFIX)),
+        Err(e) => exit_with_error(&format!("Failed to find board-conformance: {}", e)),
+    };
+    let cpplib = cli_options
+        .cpplib
+        .clone()
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../server/cpplib"));
+    let build_dir = cli_options
+        .build_dir
+        .clone()
+        .unwrap_or_else(|| env::temp_dir().join("board-grade"));
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        exit_with_error(&format!("Failed to create {}: {}", build_dir.display(), e));
+    }
+
+    let mut students = match fs::read_dir(&cli_options.submissions) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<PathBuf>>(),
+        Err(e) => exit_with_error(&format!(
+            "Failed to read {}: {}",
+            cli_options.submissions.display(),
+            e
+        )),
+    };
+    students.sort();
+
+    // Names of the scenarios, to report the one a board crashed or hung on
+    let scenarios: Vec<String> = conformance::scenarios(cli_options.random_games, cli_options.seed)
+        .into_iter()
+        .map(|scenario| scenario.name)
+        .collect();
+
+    let mut grades = Vec::new();
+    // Other files, such as a class list, are not submissions
+    for path in students
+        .into_iter()
+        .filter(|path| path.is_dir() || is_library(path))
+    {
+        let student = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let grade = Grade::new(student.clone(), scenarios.len());
+        let grade = match find_library(&path, &student, &build_dir, &cpplib, &cli_options.cxx) {
+            Ok(library) => grade_library(grade, &library, &conformance, &scenarios, &cli_options),
+            Err((status, error)) => grade.fail(status, error),
+        };
+        eprintln!(
+            "{}: {} of {} scenarios passed ({})",
+            grade.student,
+            grade.passed,
+            grade.scenarios,
+            grade.status.name()
+        );
+        grades.push(grade);
+    }
+
+    let result = match &cli_options.output {
+        Some(path) => fs::File::create(path).and_then(|mut file| {
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                write_json(&mut file, &grades)
+            } else {
+                write_csv(&mut file, &grades)
+            }
+        }),
+        None => write_csv(&mut io::stdout().lock(), &grades),
+    };
+    if let Err(e) = result {
+        exit_with_error(&format!("Failed to write report: {}", e));
+    }
+}
+
+/// Returns the shared library for the submission at the path, building it from board.cc if needed
+fn find_library(
+    path: &Path,
+    student: &str,
+    build_dir: &Path,
+    cpplib: &Path,
+    cxx: &str,
+) -> Result<PathBuf, (Status, String)> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+
+    let source = path.join("board.cc");
+    if source.is_file() {
+        let library = build_dir.join(format!("{}{}", student, env::consts::DLL_SUFFIX));
+        let output = Command::new(cxx)
+            .args(["-std=c++17", "-shared", "-fPIC", "-I"])
+            .arg(cpplib)
+            .arg("-o")
+            .arg(&library)
+            .arg(&source)
+            .arg(cpplib.join("safe_board.cc"))
+            .output()
+            .map_err(|e| {
+                (
+                    Status::CompileError,
+                    format!("failed to run {}: {}", cxx, e),
+                )
+            })?;
+        return if output.status.success() {
+            Ok(library)
+        } else {
+            Err((
+                Status::CompileError,
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ))
+        };
+    }
+
+    fs::read_dir(path)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .find(|path| is_library(path))
+        })
+        .ok_or_else(|| (Status::Missing, "no board.cc or shared library".to_string()))
+}
+
+fn is_library(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension == env::consts::DLL_EXTENSION)
+}
+
+/// Runs board-conformance on the library, restarting it after the scenario a crash happened in,
+/// and giving up once the timeout has passed
+fn grade_library(
+    mut grade: Grade,
+    library: &Path,
+    conformance: &Path,
+    scenarios: &[String],
+    cli_options: &CLIOptions,
+) -> Grade {
+    let deadline = Instant::now() + Duration::from_secs(cli_options.timeout_secs);
+    let mut next = 0;
+    let mut crashes = Vec::new();
+    let mut timed_out = false;
+    while next < scenarios.len() {
+        let (run, end) = run_conformance(
+            &mut grade,
+            library,
+            conformance,
+            next,
+            deadline,
+            cli_options,
+        );
+        next += run;
+        // The scenario being run when the board crashed or hung failed
+        let unfinished = |reason: &str| FailedCase {
+            scenario: scenarios.get(next).cloned().unwrap_or_default(),
+            move_number: None,
+            reason: reason.to_string(),
+            before: None,
+            expected: None,
+            actual: None,
+        };
+        match end {
+            RunEnd::Finished => {}
+            RunEnd::LoadError(error) => return grade.fail(Status::LoadError, error),
+            RunEnd::Crashed(error) => {
+                grade.failed.push(unfinished("board crashed"));
+                crashes.push(error);
+                next += 1;
+            }
+            RunEnd::TimedOut => {
+                grade
+                    .failed
+                    .push(unfinished("board did not finish in time"));
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    grade.score = (grade.passed as f64 * 1000.0 / grade.scenarios.max(1) as f64).round() / 10.0;
+
+    if timed_out {
+        grade.fail(
+            Status::TimedOut,
+            format!(
+                "did not finish every scenario within {} seconds",
+                cli_options.timeout_secs
+            ),
+        )
+    } else if let Some(last) = crashes.last() {
+        let error = format!("crashed in {} scenarios, last with {}", crashes.len(), last);
+        grade.fail(Status::Crashed, error)
+    } else {
+        grade
+    }
+}
+
+/// How a run of board-conformance ended
+enum RunEnd {
+    /// Every scenario was run
+    Finished,
+    LoadError(String),
+    /// The process ended before every scenario was run, with the reason and what the board printed
+    Crashed(String),
+    TimedOut,
+}
+
+/// Runs board-conformance on the library from the scenario at start, adding each result to the grade
+/// Returns how many scenarios were run and how the run ended
+fn run_conformance(
+    grade: &mut Grade,
+    library: &Path,
+    conformance: &Path,
+    start: usize,
+    deadline: Instant,
+    cli_options: &CLIOptions,
+) -> (usize, RunEnd) {
+    let mut child = match Command::new(conformance)
+        .arg("--json")
+        .args(["--random-games", &cli_options.random_games.to_string()])
+        .args(["--seed", &cli_options.seed.to_string()])
+        .args(["--start", &start.to_string()])
+        .arg(library)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => exit_with_error(&format!("Failed to run {}: {}", conformance.display(), e)),
+    };
+
+    // Results are read on another thread, so the timeout can be kept while waiting for them
+    let (sender, receiver) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    // Keep the end of whatever the board printed, which holds any load error or crash message
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            stderr.read_to_end(&mut output).ok();
+            let output = String::from_utf8_lossy(&output).into_owned();
+            let mut start = output.len().saturating_sub(MAX_ERROR_LENGTH);
+            while !output.is_char_boundary(start) {
+                start += 1;
+            }
+            output[start..].to_string()
+        })
+    });
+
+    let mut run = 0;
+    let timed_out = loop {
+        let line = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) => break true,
+            Err(RecvTimeoutError::Disconnected) => break false,
+        };
+        match serde_json::from_str::<ScenarioResult>(&line) {
+            Ok(ScenarioResult::Passed(_)) => grade.passed += 1,
+            Ok(ScenarioResult::Failed(divergence)) => grade.failed.push(FailedCase {
+                scenario: divergence.scenario,
+                move_number: Some(divergence.move_index + 1),
+                reason: divergence.reason,
+                before: Some(conformance::render(divergence.before)),
+                expected: Some(conformance::render(divergence.expected)),
+                actual: Some(conformance::render(divergence.actual)),
+            }),
+            Err(_) => continue,
+        }
+        run += 1;
+    };
+    if timed_out {
+        child.kill().ok();
+    }
+    let status = child.wait();
+    let stderr = stderr
+        .and_then(|stderr| stderr.join().ok())
+        .unwrap_or_default();
+
+    let end = match status {
+        _ if timed_out => RunEnd::TimedOut,
+        Ok(status) if status.code() == Some(2) => RunEnd::LoadError(stderr),
+        Ok(status)
+            if (status.success() || status.code() == Some(1)) && start + run >= grade.scenarios =>
+        {
+            RunEnd::Finished
+        }
+        Ok(status) => RunEnd::Crashed(format!("{}\n{}", status, stderr)),
+        Err(e) => RunEnd::Crashed(e.to_string()),
+    };
+    (run, end)
+}
+
+fn write_json(out: &mut impl Write, grades: &[Grade]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, grades)?;
+    writeln!(out)
+}
+
+/// Writes a row per student, with each failing scenario and its reason in the last column
+fn write_csv(out: &mut impl Write, grades: &[Grade]) -> io::Result<()> {
+    writeln!(out, "student,status,passed,scenarios,score,error,failed")?;
+    for grade in grades {
+        let failed: Vec<String> = grade
+            .failed
+            .iter()
+            .map(|case| match case.move_number {
+                Some(move_number) => {
+                    format!("{} (move {}): {}", case.scenario, move_number, case.reason)
+                }
+                None => format!("{}: {}", case.scenario, case.reason),
+            })
+            .collect();
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            csv_field(&grade.student),
+            grade.status.name(),
+            grade.passed,
+            grade.scenarios,
+            grade.score,
+            csv_field(grade.error.as_deref().unwrap_or_default()),
+            csv_field(&failed.join("\n"))
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes the field if it holds a comma, quote or newline
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keeps the start of a long error, such as the first compiler errors
+fn truncate(error: &str) -> String {
+    match error.char_indices().nth(MAX_ERROR_LENGTH) {
+        Some((end, _)) => format!("{}...", &error[..end]),
+        None => error.to_string(),
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
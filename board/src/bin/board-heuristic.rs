@@ -0,0 +1,169 @@
+//! board-heuristic checks a set of heuristic weights against the perfect solver, so the weights
+//! the AIs guess with where they stop looking ahead can be tuned
+//!
+//! Plays random games a number of disks in, solves each position reached, and prints how often
+//! the heuristic favors the player who wins with best play, and how often the move it likes best
+//! is one of the best moves
+//!
+//! Weights not given default to the ones the AIs use
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::rng::Rng;
+use constants::{
+    heuristic::HeuristicWeights,
+    solver::{Position, Solver},
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+use std::process;
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// positions to judge (default 200)
+    #[argh(option, default = "200")]
+    positions: usize,
+
+    /// disks in each position, at most the cells of the board (default 16)
+    #[argh(option, default = "16")]
+    disks: usize,
+
+    /// seed for the random games, the same seed always judging the same positions (default 1)
+    #[argh(option, default = "1")]
+    seed: u64,
+
+    /// weight of a line of four with three disks and an empty cell
+    #[argh(option, default = "HeuristicWeights::DEFAULT.open_three")]
+    open_three: i32,
+
+    /// weight of a line of four with two disks and two empty cells
+    #[argh(option, default = "HeuristicWeights::DEFAULT.open_two")]
+    open_two: i32,
+
+    /// weight of a winning cell which can be played into right now
+    #[argh(option, default = "HeuristicWeights::DEFAULT.playable_threat")]
+    playable_threat: i32,
+
+    /// weight of a winning cell on a row which suits the player
+    #[argh(option, default = "HeuristicWeights::DEFAULT.parity_threat")]
+    parity_threat: i32,
+
+    /// weight of a disk in the center column
+    #[argh(option, default = "HeuristicWeights::DEFAULT.center")]
+    center: i32,
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    let cells = BOARD_WIDTH as usize * BOARD_HEIGHT as usize;
+    if cli_options.disks > cells {
+        eprintln!("A position can have at most {} disks", cells);
+        process::exit(2);
+    }
+    let weights = HeuristicWeights {
+        open_three: cli_options.open_three,
+        open_two: cli_options.open_two,
+        playable_threat: cli_options.playable_threat,
+        parity_threat: cli_options.parity_threat,
+        center: cli_options.center,
+    };
+    let mut rng = Rng(cli_options.seed);
+    let mut solver = Solver::new(u64::MAX, Solver::LARGE_TABLE);
+
+    let (mut decided, mut favored_winner, mut best_moves) = (0, 0, 0);
+    for judged in 0..cli_options.positions {
+        let position = random_position(cli_options.disks, &mut rng);
+        let scores = solver.scores(&position).unwrap_or_default();
+        let best = scores.iter().flatten().max().copied().unwrap_or_default();
+
+        // Whoever wins with best play should be the one the heuristic favors
+        if best != 0 {
+            decided += 1;
+            let (current, mask) = position.disks();
+            if weights.evaluate(current, mask).signum() == best.signum() {
+                favored_winner += 1;
+            }
+        }
+        let col = heuristic_move(&position, &weights);
+        if scores.get(col as usize).copied().flatten() == Some(best) {
+            best_moves += 1;
+        }
+        eprint!(
+            "\rJudged {} of {} positions",
+            judged + 1,
+            cli_options.positions
+        );
+    }
+    eprintln!();
+
+    println!("{:?}", weights);
+    println!(
+        "Favored the winner in {} of {} decided positions ({:.1}%)",
+        favored_winner,
+        decided,
+        percent(favored_winner, decided)
+    );
+    println!(
+        "Chose one of the best moves in {} of {} positions ({:.1}%)",
+        best_moves,
+        cli_options.positions,
+        percent(best_moves, cli_options.positions)
+    );
+}
+
+/// Returns the position a random game reaches after the given number of disks, starting over
+/// whenever a game is won or the player to move can win at once, as neither needs judging
+fn random_position(disks: usize, rng: &mut Rng) -> Position {
+    'game: loop {
+        let mut position = Position::default();
+        while position.moves() < disks {
+            let playable: Vec<u8> = (0..BOARD_WIDTH)
+                .filter(|&col| position.can_play(col))
+                .collect();
+            let col = playable[rng.below(playable.len() as u64) as usize];
+            if position.is_winning_move(col) {
+                continue 'game;
+            }
+            position.play(col);
+        }
+        if !position.can_win_next() {
+            return position;
+        }
+    }
+}
+
+/// Returns the column the heuristic likes best, looking one move ahead
+fn heuristic_move(position: &Position, weights: &HeuristicWeights) -> u8 {
+    (0..BOARD_WIDTH)
+        .filter(|&col| position.can_play(col))
+        .max_by_key(|&col| {
+            let mut next = *position;
+            next.play(col);
+            let (current, mask) = next.disks();
+            -weights.evaluate(current, mask)
+        })
+        .unwrap_or_default()
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
@@ -0,0 +1,91 @@
+//! board-host runs a board plugin in its own process, so a board that crashes, hangs or corrupts
+//! memory cannot take the server down with it
+//!
+//! Usage: board-host <path to shared library>
+//!
+//! Speaks one line per message over stdin / stdout:
+//!     on startup, writes "ready", or "error <reason>" and exits if the library fails to load
+//!     reads "drop <player num> <col>", drops a disk onto its board, and writes
+//!     "ok <1 if the player won, else 0> <player 1 disks> <player 2 disks>" (disks in hex), or "illegal"
+//! Anything the board itself prints goes to stderr
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use board::{isolation, BoardEngine, Plugin};
+
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    process,
+};
+
+fn main() {
+    let path = match env::args_os().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: board-host <path to shared library>");
+            process::exit(2);
+        }
+    };
+    let mut out = isolation::protocol_output();
+    let mut respond = |response: &str| {
+        writeln!(out, "{}", response)
+            .and_then(|_| out.flush())
+            .is_ok()
+    };
+
+    // Safety: the library only runs in this process, which the server treats as untrusted
+    let plugin = match unsafe { Plugin::load(&path) } {
+        Ok(plugin) => plugin,
+        Err(e) => {
+            respond(&format!("error {}", e));
+            process::exit(1);
+        }
+    };
+    let mut board = plugin.new_board();
+    if !respond("ready") {
+        return;
+    }
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let request: Vec<&str> = line.split_whitespace().collect();
+        let response = match request.as_slice() {
+            ["drop", player_num, col] => match (player_num.parse(), col.parse()) {
+                (Ok(player_num), Ok(col)) => match board.drop_disk(player_num, col) {
+                    Ok(game_won) => {
+                        let [p1, p2] = board.disks();
+                        format!("ok {} {:x} {:x}", game_won as u8, p1, p2)
+                    }
+                    Err(_) => "illegal".to_string(),
+                },
+                _ => "error malformed request".to_string(),
+            },
+            _ => "error unknown request".to_string(),
+        };
+        if !respond(&response) {
+            break;
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! board-tabular learns a value table by self-play with Q-learning or TD learning, on a board of
+//! any size, to show reinforcement learning where the state space is small enough for a table
+//!
+//! Every so many games it plays the table against a random player and against a player which
+//! takes wins and blocks threats, and writes a learning curve as CSV, to the curve file or else
+//! to standard output. The table is written in the format the web app's learned table AI loads
+//!
+//! Exits with 2 if the board size cannot be played or a file could not be written
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::{
+    rng::Rng,
+    tabular::{Learner, Method, SmallBoard},
+};
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    time::Instant,
+};
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// file to write the value table to
+    #[argh(positional)]
+    output: PathBuf,
+
+    /// columns of the board (default 5)
+    #[argh(option, default = "5")]
+    width: u8,
+
+    /// rows of the board (default 4)
+    #[argh(option, default = "4")]
+    height: u8,
+
+    /// q-learning, which learns the value of best play, or td, which learns the value of the
+    /// moves played (default q-learning)
+    #[argh(option, default = "Method::QLearning")]
+    method: Method,
+
+    /// self-play games to learn from (default 1000000)
+    #[argh(option, default = "1_000_000")]
+    games: usize,
+
+    /// how far each value moves towards what a game suggests (default 0.1)
+    #[argh(option, default = "0.1")]
+    learning_rate: f32,
+
+    /// how often to play a random move while learning (default 0.1)
+    #[argh(option, default = "0.1")]
+    exploration: f32,
+
+    /// self-play games between points of the learning curve (default 10000)
+    #[argh(option, default = "10_000")]
+    log_every: usize,
+
+    /// games against each opponent at each point of the learning curve, half of them first
+    /// (default 200)
+    #[argh(option, default = "200")]
+    eval_games: usize,
+
+    /// file to write the learning curve to, instead of standard output
+    #[argh(option)]
+    curve: Option<PathBuf>,
+
+    /// fewest times a position must have been learned from to be written (default 1)
+    #[argh(option, default = "1")]
+    min_visits: u32,
+
+    /// seed for the games, the same seed always learning the same table (default 1)
+    #[argh(option, default = "1")]
+    seed: u64,
+}
+
+/// Players the table is measured against
+#[derive(Clone, Copy)]
+enum Opponent {
+    /// Plays any column at random
+    Random,
+    /// Wins if it can, blocks the table's win if it must, and otherwise plays at random
+    Blocker,
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    let (width, height) = (cli_options.width, cli_options.height);
+    if !SmallBoard::fits(width, height) {
+        eprintln!("A board must be at least 4x4, and its width times its height plus 1 at most 64");
+        process::exit(2);
+    }
+    let mut curve: Box<dyn Write> = match &cli_options.curve {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path.display(), e);
+                process::exit(2);
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    let started = Instant::now();
+    let mut rng = Rng(cli_options.seed);
+    let mut learner = Learner::new(
+        cli_options.method,
+        cli_options.learning_rate,
+        cli_options.exploration,
+    );
+    let mut error = 0.0;
+    write_row(&mut curve, "games,positions,error,vs_random,vs_blocker");
+    for game in 1..=cli_options.games {
+        error += learner.play_game(width, height, &mut rng);
+        if game % cli_options.log_every.max(1) == 0 || game == cli_options.games {
+            let games_since = (game - 1) % cli_options.log_every.max(1) + 1;
+            let vs_random = evaluate(&learner, Opponent::Random, &cli_options, &mut rng);
+            let vs_blocker = evaluate(&learner, Opponent::Blocker, &cli_options, &mut rng);
+            write_row(
+                &mut curve,
+                &format!(
+                    "{},{},{:.4},{:.3},{:.3}",
+                    game,
+                    learner.len(),
+                    error / games_since as f32,
+                    vs_random,
+                    vs_blocker
+                ),
+            );
+            error = 0.0;
+            eprint!(
+                "\rPlayed {} of {} games, knowing {} positions",
+                game,
+                cli_options.games,
+                learner.len()
+            );
+        }
+    }
+    eprintln!();
+
+    let table = learner.table(width, height, cli_options.min_visits);
+    if let Err(e) = fs::write(&cli_options.output, table.to_bytes()) {
+        eprintln!("Failed to write {}: {}", cli_options.output.display(), e);
+        process::exit(2);
+    }
+    eprintln!(
+        "Wrote {} of {} positions to {} in {:.1}s.",
+        table.len(),
+        learner.len(),
+        cli_options.output.display(),
+        started.elapsed().as_secs_f64()
+    );
+}
+
+/// Plays the table's best moves against the opponent, and returns the table's share of the
+/// points: 1 for a win and 0.5 for a draw
+fn evaluate(learner: &Learner, opponent: Opponent, cli_options: &CLIOptions, rng: &mut Rng) -> f64 {
+    let mut score = 0.0;
+    for game in 0..cli_options.eval_games {
+        let mut board = SmallBoard::new(cli_options.width, cli_options.height);
+        let mut tables_turn = game % 2 == 0;
+        score += loop {
+            let col = if tables_turn {
+                learner.best_move(&board, rng)
+            } else {
+                opponent_move(&board, opponent, rng)
+            };
+            if board.is_winning_move(col) {
+                break if tables_turn { 1.0 } else { 0.0 };
+            }
+            if board.is_last_move() {
+                break 0.5;
+            }
+            board.play(col);
+            tables_turn = !tables_turn;
+        };
+    }
+    score / cli_options.eval_games.max(1) as f64
+}
+
+fn opponent_move(board: &SmallBoard, opponent: Opponent, rng: &mut Rng) -> u8 {
+    let playable = board.playable();
+    if let Opponent::Blocker = opponent {
+        if let Some(&col) = playable.iter().find(|&&col| board.is_winning_move(col)) {
+            return col;
+        }
+        // Dropping a disk where the table would win takes the cell from it
+        let mut skipped = *board;
+        skipped.pass();
+        if let Some(&col) = playable.iter().find(|&&col| skipped.is_winning_move(col)) {
+            return col;
+        }
+    }
+    playable[rng.below(playable.len() as u64) as usize]
+}
+
+fn write_row(curve: &mut impl Write, row: &str) {
+    if let Err(e) = writeln!(curve, "{}", row) {
+        eprintln!("Failed to write the learning curve: {}", e);
+        process::exit(2);
+    }
+}
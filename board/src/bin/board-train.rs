@@ -0,0 +1,377 @@
+//! board-train trains the policy and value network in constants
+//!
+//! Plays self-play games with the web app's brute force search, labels every position
+//! they reach with the perfect solver's verdict and best moves, and trains the network on those
+//! on the CPU. After each pass over the positions, the network plays a match against the brute
+//! force search, searching a few moves ahead with it, and the checkpoint which scores best is
+//! written out
+//!
+//! Exits with 2 if the network could not be written
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use argh::FromArgs;
+use board::{
+    rng::Rng,
+    training::{self, Sample, Trainer},
+};
+use constants::{
+    brute_force::{self, BruteForceSearch},
+    network::Network,
+    neural,
+    solver::{Position, Solver},
+    BOARD_HEIGHT, BOARD_WIDTH, LOOKUP_TABLE_SIZE,
+};
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Instant,
+};
+
+const WIDTH: usize = BOARD_WIDTH as usize;
+const CELLS: usize = WIDTH * BOARD_HEIGHT as usize;
+/// Hidden layers of the network when none are given
+const DEFAULT_HIDDEN: [usize; 2] = [64, 32];
+
+/// Command line options
+#[derive(FromArgs)]
+struct CLIOptions {
+    /// file to write the best network to
+    #[argh(positional)]
+    output: PathBuf,
+
+    /// self-play games to learn from (default 300)
+    #[argh(option, default = "300")]
+    games: usize,
+
+    /// moves the brute force search looks ahead in self-play games, at least 1 (default 4)
+    #[argh(option, default = "4", from_str_fn(parse_depth))]
+    depth: u8,
+
+    /// most random disks to open each self-play game with, so the games differ (default 8)
+    #[argh(option, default = "8")]
+    random_disks: u64,
+
+    /// most positions the solver may search to label a position, leaving out positions it
+    /// cannot label within it (default 2000000)
+    #[argh(option, default = "2_000_000")]
+    node_budget: u64,
+
+    /// positions to label at once, each with its own solver (default the number of cores)
+    #[argh(option)]
+    jobs: Option<usize>,
+
+    /// size of a hidden layer, repeated for each layer (default 64 then 32)
+    #[argh(option)]
+    hidden: Vec<usize>,
+
+    /// passes over the positions (default 20)
+    #[argh(option, default = "20")]
+    epochs: usize,
+
+    /// positions in each step of training (default 32)
+    #[argh(option, default = "32")]
+    batch_size: usize,
+
+    /// learning rate of Adam (default 0.001)
+    #[argh(option, default = "0.001")]
+    learning_rate: f32,
+
+    /// games each checkpoint plays against the brute force search, half of them first
+    /// (default 20)
+    #[argh(option, default = "20")]
+    match_games: usize,
+
+    /// moves the brute force search looks ahead in matches, at least 1 (default 4)
+    #[argh(option, default = "4", from_str_fn(parse_depth))]
+    opponent_depth: u8,
+
+    /// moves the network searches ahead in matches
+    /// (default 4)
+    #[argh(option, default = "4")]
+    search_depth: u8,
+
+    /// seed for the games and the network's first weights (default 1)
+    #[argh(option, default = "1")]
+    seed: u64,
+}
+
+/// Parses how many moves the brute force search looks ahead, which must be at least 1
+fn parse_depth(value: &str) -> Result<u8, String> {
+    match value.parse() {
+        Ok(0) => Err("the brute force search must look at least 1 move ahead".to_string()),
+        Ok(depth) => Ok(depth),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn main() {
+    let cli_options: CLIOptions = argh::from_env();
+    let started = Instant::now();
+    let mut rng = Rng(cli_options.seed);
+
+    let positions = self_play(&cli_options, &mut rng);
+    eprintln!(
+        "Played {} games, reaching {} positions",
+        cli_options.games,
+        positions.len()
+    );
+    let jobs = cli_options
+        .jobs
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .max(1);
+    let mut samples = label(&positions, cli_options.node_budget, jobs);
+    // Threads finish in any order, so sort the samples for the same seed to train the same network
+    samples.sort_by_key(|sample| (sample.mask, sample.current));
+    shuffle(&mut samples, &mut rng);
+    // Positions the network never trains on show whether it has learned or memorised
+    let validation = samples.split_off(samples.len() - samples.len() / 10);
+    eprintln!(
+        "Labelled {} positions for training and {} for validation",
+        samples.len(),
+        validation.len()
+    );
+
+    let hidden = if cli_options.hidden.is_empty() {
+        DEFAULT_HIDDEN.to_vec()
+    } else {
+        cli_options.hidden.clone()
+    };
+    let network = training::random_network(&hidden, &mut rng);
+    let mut trainer = Trainer::new(network, cli_options.learning_rate);
+    println!(
+        "Training a network of {} weights",
+        trainer.network.num_parameters()
+    );
+    println!(
+        "Without a network, the search scores {:.1} of {}",
+        play_match(None, &cli_options),
+        cli_options.match_games
+    );
+
+    let mut best: Option<(f64, f32)> = None;
+    let mut order: Vec<usize> = (0..samples.len()).collect();
+    for epoch in 1..=cli_options.epochs {
+        shuffle(&mut order, &mut rng);
+        for batch in order.chunks(cli_options.batch_size.max(1)) {
+            let batch: Vec<&Sample> = batch.iter().map(|&index| &samples[index]).collect();
+            trainer.step(&batch);
+        }
+        let train_loss = trainer.loss(&samples);
+        let loss = trainer.loss(&validation);
+        let score = play_match(Some(&trainer.network), &cli_options);
+        println!(
+            "Epoch {}: policy loss {:.3} ({:.3} training), value loss {:.3} ({:.3} training), \
+             best move {:.1}%, match score {:.1} of {}",
+            epoch,
+            loss.policy,
+            train_loss.policy,
+            loss.value,
+            train_loss.value,
+            100.0 * loss.accuracy,
+            score,
+            cli_options.match_games
+        );
+
+        // The match decides which checkpoint is best, and the validation loss breaks ties
+        let total_loss = loss.policy + loss.value;
+        if best.is_none_or(|(best_score, best_loss)| {
+            score > best_score || (score == best_score && total_loss < best_loss)
+        }) {
+            best = Some((score, total_loss));
+            let bytes = trainer.network.to_bytes();
+            if let Err(e) = fs::write(&cli_options.output, &bytes) {
+                eprintln!("Failed to write {}: {}", cli_options.output.display(), e);
+                process::exit(2);
+            }
+        }
+    }
+
+    if let Some((score, _)) = best {
+        println!(
+            "Wrote the best network, scoring {:.1} of {}, to {} in {:.1}s.",
+            score,
+            cli_options.match_games,
+            cli_options.output.display(),
+            started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+/// Plays the self-play games and returns every position they reach before the game ends, once
+fn self_play(cli_options: &CLIOptions, rng: &mut Rng) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    let mut positions = Vec::new();
+    for game in 0..cli_options.games {
+        let random_disks = rng.below(cli_options.random_disks + 1) as usize;
+        let mut search = BruteForceSearch::new(LOOKUP_TABLE_SIZE);
+        let mut position = Position::default();
+        loop {
+            if seen.insert(position.disks()) {
+                positions.push(position);
+            }
+            let col = if position.moves() < random_disks {
+                let playable: Vec<u8> = (0..BOARD_WIDTH)
+                    .filter(|&col| position.can_play(col))
+                    .collect();
+                playable[rng.below(playable.len() as u64) as usize]
+            } else {
+                brute_force_move(&mut search, &position, cli_options.depth, rng)
+            };
+            if position.is_winning_move(col) || position.moves() + 1 == CELLS {
+                break;
+            }
+            position.play(col);
+        }
+        eprint!("\rPlayed {} of {} games", game + 1, cli_options.games);
+    }
+    eprintln!();
+    positions
+}
+
+/// Solves the positions on the given number of threads, each with its own solver taking the next
+/// position not yet solved, and returns samples of those solved within the node budget and of
+/// their mirror images
+fn label(positions: &[Position], node_budget: u64, jobs: usize) -> Vec<Sample> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let started = Instant::now();
+    let samples = thread::scope(|scope| {
+        let threads: Vec<_> = (0..jobs.min(positions.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut solver = Solver::new(node_budget, Solver::LARGE_TABLE);
+                    let mut samples = Vec::new();
+                    loop {
+                        let position = match positions.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            Some(position) => position,
+                            None => return samples,
+                        };
+                        if let Some(sample) = solve(&mut solver, position) {
+                            let mirrored = training::mirror(&sample);
+                            if mirrored != sample {
+                                samples.push(mirrored);
+                            }
+                            samples.push(sample);
+                        }
+                        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        let elapsed = started.elapsed().as_secs_f64();
+                        eprint!(
+                            "\rLabelled {} of {} positions, about {:.0}s left",
+                            done,
+                            positions.len(),
+                            elapsed / done as f64 * (positions.len() - done) as f64
+                        );
+                    }
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap_or_default())
+            .collect()
+    });
+    if !positions.is_empty() {
+        eprintln!();
+    }
+    samples
+}
+
+/// Returns a sample of the position, which should choose between its best moves alike, or None
+/// if the solver could not solve it within its node budget
+fn solve(solver: &mut Solver, position: &Position) -> Option<Sample> {
+    let scores = solver.scores(position)?;
+    let best = scores.iter().flatten().max().copied().unwrap_or_default();
+    let num_best = scores.iter().filter(|&&score| score == Some(best)).count();
+    let mut policy = [0.0; WIDTH];
+    for (probability, score) in policy.iter_mut().zip(&scores) {
+        if *score == Some(best) {
+            *probability = 1.0 / num_best as f32;
+        }
+    }
+    let (current, mask) = position.disks();
+    Some(Sample {
+        current,
+        mask,
+        policy,
+        value: best.signum() as f32,
+    })
+}
+
+/// Plays the network against the brute force search, from the same openings every time so
+/// checkpoints can be compared, and returns the network's score: 1 for a win and 0.5 for a draw
+/// With no network, the search judges positions by the threat heuristic
+fn play_match(network: Option<&Network>, cli_options: &CLIOptions) -> f64 {
+    let mut score = 0.0;
+    for game in 0..cli_options.match_games {
+        // Each opening is played twice, with the network first and then second
+        let mut rng = Rng(cli_options.seed.wrapping_add((game / 2) as u64));
+        let network_player = game % 2;
+        let mut search = BruteForceSearch::new(LOOKUP_TABLE_SIZE);
+        let mut position = Position::default();
+        for _ in 0..2 {
+            position.play(rng.below(WIDTH as u64) as u8);
+        }
+        let mut player = 0;
+        score += loop {
+            let col = if player == network_player {
+                neural::best_move(network, &position, cli_options.search_depth)
+                    .map_or(0, |(col, _)| col)
+            } else {
+                brute_force_move(&mut search, &position, cli_options.opponent_depth, &mut rng)
+            };
+            if position.is_winning_move(col) {
+                break if player == network_player { 1.0 } else { 0.0 };
+            }
+            if position.moves() + 1 == CELLS {
+                break 0.5;
+            }
+            position.play(col);
+            player = 1 - player;
+        };
+    }
+    score
+}
+
+/// Returns the move the web app's brute force AI would make looking the given number of moves
+/// ahead with no time limit, choosing at random between the best ones
+fn brute_force_move(
+    search: &mut BruteForceSearch,
+    position: &Position,
+    depth: u8,
+    rng: &mut Rng,
+) -> u8 {
+    let (scores, _) = search.scores(position, depth, || false);
+    let best_cols = brute_force::best_columns(&scores);
+    best_cols[rng.below(best_cols.len() as u64) as usize]
+}
+
+/// Shuffles the items into a random order
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+}
@@ -0,0 +1,71 @@
+//! bitboard contains the built-in board, which keeps each player's disks in a u64
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{bit, BoardEngine, IllegalMove};
+
+use constants::{BOARD_HEIGHT, BOARD_WIDTH};
+
+/// Board used when no C++ board is selected, and to check C++ boards against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitboard {
+    disks: [u64; 2],
+    /// Number of disks in each column
+    heights: [u8; BOARD_WIDTH as usize],
+}
+
+impl Bitboard {
+    /// Returns whether a disk can be dropped into the column
+    pub fn can_play(&self, col: u8) -> bool {
+        col < BOARD_WIDTH && self.heights[col as usize] < BOARD_HEIGHT
+    }
+
+    /// Returns whether every column is full
+    pub fn is_full(&self) -> bool {
+        self.heights.iter().all(|&height| height == BOARD_HEIGHT)
+    }
+}
+
+impl BoardEngine for Bitboard {
+    fn drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, IllegalMove> {
+        if !self.can_play(col) || !(1..=2).contains(&player_num) {
+            return Err(IllegalMove);
+        }
+        let disks = &mut self.disks[player_num as usize - 1];
+        *disks |= bit(self.heights[col as usize], col);
+        self.heights[col as usize] += 1;
+        Ok(has_four_in_a_row(*disks))
+    }
+
+    fn disks(&self) -> [u64; 2] {
+        self.disks
+    }
+}
+
+/// Returns whether the disks contain four in a row in any direction
+/// The empty bit above each column keeps lines from wrapping between columns
+fn has_four_in_a_row(disks: u64) -> bool {
+    let height = BOARD_HEIGHT as u32;
+    // Vertical, horizontal, and both diagonals
+    [1, height + 1, height, height + 2].iter().any(|&shift| {
+        let pairs = disks & (disks >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
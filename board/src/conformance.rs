@@ -0,0 +1,411 @@
+//! conformance checks a C++ board against the built-in Bitboard move by move, over random games and
+//! scenarios built to catch the usual mistakes: out of range and full columns, wins in every
+//! direction and position, and lines which only connect by wrapping around the edge of the board
+//!
+//! Rows are counted up from the bottom, as in board.hpp
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{bit, rng::Rng, Bitboard, BoardEngine, IllegalMove, PluginBoard};
+
+use constants::{BOARD_HEIGHT, BOARD_WIDTH};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+const WIDTH: i32 = BOARD_WIDTH as i32;
+const HEIGHT: i32 = BOARD_HEIGHT as i32;
+
+/// Columns outside the board, tried by the out of range scenarios and random games
+const OUT_OF_RANGE: [i32; 7] = [-1, WIDTH, WIDTH + 1, -100, 100, i32::MIN, i32::MAX];
+
+/// Most moves tried in a random game, which usually ends well before by filling the board
+const MAX_RANDOM_MOVES: usize = 200;
+
+/// A disk dropped by a player into a column, which may be off the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub player_num: u8,
+    pub col: i32,
+}
+
+/// A named sequence of moves, each checked in turn
+pub struct Scenario {
+    pub name: String,
+    pub moves: Vec<Move>,
+}
+
+/// What a board did with a move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Illegal,
+    Played { won: bool },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Illegal => write!(f, "rejected the move"),
+            Outcome::Played { won: false } => write!(f, "accepted the move with no win"),
+            Outcome::Played { won: true } => write!(f, "accepted the move as a win"),
+        }
+    }
+}
+
+/// Where a board first behaved differently from the Bitboard in a scenario
+#[derive(Serialize, Deserialize)]
+pub struct Divergence {
+    pub scenario: String,
+    /// Index of the move in the scenario
+    pub move_index: usize,
+    pub played: Move,
+    pub reason: String,
+    /// Disks of player 1 and player 2 before the move
+    pub before: [u64; 2],
+    /// Disks of player 1 and player 2 after the move, on the Bitboard
+    pub expected: [u64; 2],
+    /// Disks of player 1 and player 2 after the move, on the board being checked
+    pub actual: [u64; 2],
+}
+
+/// Result of a single scenario, written one per line by board-conformance --json
+/// as {"passed": <scenario>} or {"failed": <divergence>}
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioResult {
+    Passed(String),
+    Failed(Divergence),
+}
+
+/// Result of checking a board against every scenario
+pub struct Report {
+    pub scenarios: usize,
+    /// First divergence of each failed scenario, in the order the scenarios were run
+    pub failures: Vec<Divergence>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.scenarios - self.failures.len()
+    }
+
+    pub fn is_pass(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs every scenario, each on a new board from new_board
+pub fn check(scenarios: &[Scenario], mut new_board: impl FnMut() -> PluginBoard) -> Report {
+    Report {
+        scenarios: scenarios.len(),
+        failures: scenarios
+            .iter()
+            .filter_map(|scenario| run(scenario, &mut new_board()))
+            .collect(),
+    }
+}
+
+/// Plays the scenario on the board and on a Bitboard, returning where they first differ
+pub fn run(scenario: &Scenario, board: &mut PluginBoard) -> Option<Divergence> {
+    let mut reference = Bitboard::default();
+    for (move_index, played) in scenario.moves.iter().enumerate() {
+        let before = reference.disks();
+        let expected = play(&mut reference, *played);
+        let actual = board
+            .drop_disk_into(played.player_num, played.col)
+            .map_or(Outcome::Illegal, |won| Outcome::Played { won });
+
+        let reason = if actual != expected {
+            format!("board {}, but should have {}", actual, expected)
+        } else if !board.is_well_formed() {
+            "board holds a value other than kPlayer1, kPlayer2 or kEmpty".to_string()
+        } else if board.disks() != reference.disks() {
+            "board's disks differ from the expected disks".to_string()
+        } else {
+            continue;
+        };
+        return Some(Divergence {
+            scenario: scenario.name.clone(),
+            move_index,
+            played: *played,
+            reason,
+            before,
+            expected: reference.disks(),
+            actual: board.disks(),
+        });
+    }
+    None
+}
+
+/// Plays the move on the Bitboard
+fn play(reference: &mut Bitboard, played: Move) -> Outcome {
+    u8::try_from(played.col)
+        .map_err(|_| IllegalMove)
+        .and_then(|col| reference.drop_disk(played.player_num, col))
+        .map_or(Outcome::Illegal, |won| Outcome::Played { won })
+}
+
+/// Draws the disks as rows from top to bottom, with '1' and '2' for each player's disks and '.' for empty spaces
+pub fn render([p1, p2]: [u64; 2]) -> Vec<String> {
+    (0..BOARD_HEIGHT)
+        .rev()
+        .map(|row| {
+            (0..BOARD_WIDTH)
+                .map(|col| {
+                    let bit = bit(row, col);
+                    if p1 & bit != 0 {
+                        '1'
+                    } else if p2 & bit != 0 {
+                        '2'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns every scenario: the built scenarios, then the given number of random games
+/// The same seed always gives the same random games
+pub fn scenarios(random_games: usize, seed: u64) -> Vec<Scenario> {
+    let mut scenarios = Vec::new();
+    scenarios.extend(out_of_range_scenarios());
+    scenarios.extend(full_column_scenarios());
+    scenarios.push(full_board_scenario());
+    scenarios.extend(win_scenarios());
+    scenarios.extend(wrap_scenarios());
+    let mut rng = Rng(seed);
+    scenarios.extend((0..random_games).map(|game| random_game(game, &mut rng)));
+    scenarios
+}
+
+/// Columns off the board, on an empty and a part filled board
+fn out_of_range_scenarios() -> Vec<Scenario> {
+    OUT_OF_RANGE
+        .iter()
+        .map(|&col| Scenario {
+            name: format!("column {}", col),
+            moves: [(1, col), (1, 3), (2, col), (2, 3), (1, col)]
+                .map(|(player_num, col)| Move { player_num, col })
+                .to_vec(),
+        })
+        .collect()
+}
+
+/// Each column filled, then played into by both players, then a move into the next column
+fn full_column_scenarios() -> Vec<Scenario> {
+    (0..WIDTH)
+        .map(|col| {
+            let mut moves: Vec<Move> = (0..HEIGHT + 2)
+                .map(|i| Move {
+                    player_num: 1 + (i % 2) as u8,
+                    col,
+                })
+                .collect();
+            moves.push(Move {
+                player_num: 1,
+                col: (col + 1) % WIDTH,
+            });
+            Scenario {
+                name: format!("full column {}", col),
+                moves,
+            }
+        })
+        .collect()
+}
+
+/// Every column filled without a win, then played into
+fn full_board_scenario() -> Scenario {
+    let mut moves = Vec::new();
+    for col in 0..WIDTH {
+        for row in 0..HEIGHT {
+            moves.push(Move {
+                player_num: filler(row, col, false),
+                col,
+            });
+        }
+    }
+    moves.extend((0..WIDTH).map(|col| Move { player_num: 1, col }));
+    Scenario {
+        name: "full board".to_string(),
+        moves,
+    }
+}
+
+/// Directions lines run in, as (row step, column step)
+const DIRECTIONS: [(&str, i32, i32); 4] = [
+    ("horizontal", 0, 1),
+    ("vertical", 1, 0),
+    ("rising diagonal", 1, 1),
+    ("falling diagonal", -1, 1),
+];
+
+/// Every four in a row for both players, completed by each disk which can be played last
+fn win_scenarios() -> Vec<Scenario> {
+    let mut scenarios = Vec::new();
+    for player_num in 1..=2 {
+        for (direction, row_step, col_step) in DIRECTIONS {
+            for row in 0..HEIGHT {
+                for col in 0..WIDTH {
+                    let line: Vec<(i32, i32)> = (0..4)
+                        .map(|i| (row + i * row_step, col + i * col_step))
+                        .collect();
+                    if !line.iter().all(|&(row, col)| in_bounds(row, col)) {
+                        continue;
+                    }
+                    for &last in &line {
+                        // Only the top disk of a vertical line can be played last
+                        if direction == "vertical" && last != line[3] {
+                            continue;
+                        }
+                        let name = format!(
+                            "player {} {} win from row {} column {}, completed at row {} column {}",
+                            player_num, direction, row, col, last.0, last.1
+                        );
+                        scenarios.extend(build(name, player_num, &line, last, true));
+                    }
+                }
+            }
+        }
+    }
+    scenarios
+}
+
+/// Four disks evenly spaced in board.hpp's array, read row after row, which are not four in a row
+/// on the board because the line runs off one side and back on the other, as when indexes are not checked
+fn wrap_scenarios() -> Vec<Scenario> {
+    let mut scenarios = Vec::new();
+    for player_num in 1..=2 {
+        // Each direction as (name, column step, array index step)
+        for (direction, col_step, index_step) in [
+            ("horizontal", 1, 1),
+            ("rising diagonal", 1, WIDTH + 1),
+            ("falling diagonal", -1, WIDTH - 1),
+        ] {
+            for start in 0..WIDTH * HEIGHT {
+                let line: Vec<(i32, i32)> = (0..4)
+                    .map(|i| start + i * index_step)
+                    .map(|index| (index / WIDTH, index % WIDTH))
+                    .collect();
+                let wraps = (0..4).any(|i| !(0..WIDTH).contains(&(start % WIDTH + i * col_step)));
+                if !wraps || line.iter().any(|&(row, _)| row >= HEIGHT) {
+                    continue;
+                }
+                let name = format!(
+                    "player {} no win for a {} line wrapping from row {} column {} to row {} column {}",
+                    player_num, direction, line[0].0, line[0].1, line[3].0, line[3].1
+                );
+                scenarios.extend(build(name, player_num, &line, line[3], false));
+            }
+        }
+    }
+    scenarios
+}
+
+/// Builds a position with the player's disks on the line, and other spaces below filled so
+/// no one has four in a row, then plays the last disk of the line
+/// Returns None if the position would have four in a row before the last disk, or
+/// after it unless is_win
+fn build(
+    name: String,
+    player_num: u8,
+    line: &[(i32, i32)],
+    last: (i32, i32),
+    is_win: bool,
+) -> Option<Scenario> {
+    [false, true].into_iter().find_map(|swapped| {
+        let mut moves = Vec::new();
+        for col in 0..WIDTH {
+            let height = line
+                .iter()
+                .filter(|&&(_, line_col)| line_col == col)
+                .map(|&(row, _)| row + 1)
+                .max()
+                .unwrap_or(0);
+            for row in 0..height {
+                if (row, col) == last {
+                    continue;
+                }
+                let player_num = if line.contains(&(row, col)) {
+                    player_num
+                } else {
+                    filler(row, col, swapped)
+                };
+                moves.push(Move { player_num, col });
+            }
+        }
+        moves.push(Move {
+            player_num,
+            col: last.1,
+        });
+
+        let mut reference = Bitboard::default();
+        let outcomes: Vec<Outcome> = moves.iter().map(|&m| play(&mut reference, m)).collect();
+        let (last_outcome, rest) = outcomes.split_last()?;
+        (rest
+            .iter()
+            .all(|&outcome| outcome == Outcome::Played { won: false })
+            && *last_outcome == Outcome::Played { won: is_win })
+        .then(|| Scenario {
+            name: name.clone(),
+            moves,
+        })
+    })
+}
+
+/// Player whose disk fills a space not on the line being built
+/// Pairs of columns alternate each row, which never makes four in a row
+fn filler(row: i32, col: i32, swapped: bool) -> u8 {
+    if ((col / 2 + row) % 2 == 0) != swapped {
+        1
+    } else {
+        2
+    }
+}
+
+fn in_bounds(row: i32, col: i32) -> bool {
+    (0..HEIGHT).contains(&row) && (0..WIDTH).contains(&col)
+}
+
+/// A game of random moves by alternating players until the board is full, some into full or
+/// out of range columns, continuing after wins
+fn random_game(game: usize, rng: &mut Rng) -> Scenario {
+    let mut reference = Bitboard::default();
+    let mut player_num = 1;
+    let mut moves = Vec::new();
+    while !reference.is_full() && moves.len() < MAX_RANDOM_MOVES {
+        let col = if rng.below(10) == 0 {
+            OUT_OF_RANGE[rng.below(OUT_OF_RANGE.len() as u64) as usize]
+        } else {
+            rng.below(WIDTH as u64) as i32
+        };
+        let played = Move { player_num, col };
+        moves.push(played);
+        if play(&mut reference, played) != Outcome::Illegal {
+            player_num = 3 - player_num;
+        }
+    }
+    Scenario {
+        name: format!("random game {}", game),
+        moves,
+    }
+}
@@ -0,0 +1,56 @@
+//! isolation helps run a board in a process of its own, so whatever it prints or however much
+//! memory it leaks cannot disturb the process reading its results
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(not(unix))]
+use std::io;
+
+/// Most memory the process may use, so a leaking board cannot starve the machine
+#[cfg(unix)]
+const MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Returns where to write results to the parent process
+/// On unix, stdout is then pointed at stderr, so anything the board prints cannot be mistaken for a result,
+/// and the memory the process may use is limited
+/// Must be called before any board code runs
+#[cfg(unix)]
+pub fn protocol_output() -> File {
+    use std::os::fd::FromRawFd;
+    // Safety: only duplicates and replaces standard file descriptors, before any board code runs
+    unsafe {
+        let limit = libc::rlimit {
+            rlim_cur: MEMORY_LIMIT as libc::rlim_t,
+            rlim_max: MEMORY_LIMIT as libc::rlim_t,
+        };
+        libc::setrlimit(libc::RLIMIT_AS, &limit);
+        let protocol = libc::dup(libc::STDOUT_FILENO);
+        libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO);
+        File::from_raw_fd(protocol)
+    }
+}
+
+/// Returns where to write results to the parent process
+#[cfg(not(unix))]
+pub fn protocol_output() -> io::Stdout {
+    io::stdout()
+}
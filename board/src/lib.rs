@@ -0,0 +1,79 @@
+//! board contains the Connect Four boards the server can check moves with: the built-in
+//! Bitboard, and boards written in C++ loaded at runtime from shared libraries
+//!
+//! It also contains a perfect solver, used to look for players getting help from an engine
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use constants::{ConnectionProtocol, GameUpdate};
+
+use std::{error::Error, fmt};
+
+pub mod analysis;
+pub mod bitboard;
+pub mod conformance;
+pub mod isolation;
+pub mod plugin;
+pub mod rng;
+pub mod tabular;
+pub mod training;
+
+pub use bitboard::Bitboard;
+pub use constants::bitboard::bit;
+pub use plugin::{Plugin, PluginBoard};
+
+/// A move that the board did not allow, such as into a full or nonexistent column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IllegalMove;
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "illegal move")
+    }
+}
+
+impl Error for IllegalMove {}
+
+/// A Connect Four board which checks moves are allowed and detects wins
+pub trait BoardEngine: Send {
+    /// Drops a disk for the player (1 or 2) into the column
+    /// Returns whether the player has now won
+    fn drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, IllegalMove>;
+
+    /// Returns the disks of player 1 and player 2, with the disk at a row (counting up from
+    /// the bottom) and column at bit row + col * (BOARD_HEIGHT + 1)
+    fn disks(&self) -> [u64; 2];
+
+    /// Encodes the board as a message to be sent to clients
+    fn to_game_update_binary(&self, is_p1_turn: bool, game_won: bool) -> Vec<u8> {
+        encode_disks(self.disks(), is_p1_turn, game_won)
+    }
+}
+
+/// Encodes the disks of player 1 and player 2 as a message to be sent to clients
+pub fn encode_disks([p1, p2]: [u64; 2], is_p1_turn: bool, game_won: bool) -> Vec<u8> {
+    ConnectionProtocol::encode_message(GameUpdate {
+        position: if is_p1_turn { p1 } else { p2 },
+        mask: p1 | p2,
+        is_p1_turn,
+        game_won,
+    })
+}
+
@@ -0,0 +1,139 @@
+//! plugin contains boards written in C++ (see server/cpplib/board.hpp), loaded at runtime from
+//! shared libraries exporting DropDiskToBoardSucceeded and CheckForWinner
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{bit, BoardEngine, IllegalMove};
+
+use constants::{BOARD_HEIGHT, BOARD_WIDTH};
+
+use libloading::Library;
+
+use std::{os::raw::c_int, path::Path, sync::Arc};
+
+/// Values of DiskType in board.hpp
+const PLAYER_1: c_int = 82;
+const PLAYER_2: c_int = 66;
+const EMPTY: c_int = 32;
+
+/// Board struct from board.hpp
+/// Disks are kept as c_int rather than an enum, as the C++ code may write any value
+#[repr(C)]
+struct RawBoard {
+    board: [[c_int; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize],
+}
+
+type DropDiskToBoardSucceeded = unsafe extern "C" fn(*mut RawBoard, c_int, c_int) -> bool;
+type CheckForWinner = unsafe extern "C" fn(*mut RawBoard, c_int) -> bool;
+
+/// A shared library implementing a board, cheap to clone
+#[derive(Clone)]
+pub struct Plugin {
+    drop_disk_to_board_succeeded: DropDiskToBoardSucceeded,
+    check_for_winner: CheckForWinner,
+    /// Keeps the functions above loaded
+    _library: Arc<Library>,
+}
+
+impl Plugin {
+    /// Loads the shared library at the given path, e.g. built with
+    /// g++ -shared -fPIC -o board.so board.cc safe_board.cc
+    ///
+    /// # Safety
+    /// Loading a library runs its initialization code, and every move made on its boards runs its
+    /// board code, so the library must be trusted to follow board.hpp and not corrupt memory
+    pub unsafe fn load(path: &Path) -> Result<Self, libloading::Error> {
+        // A bare file name would be searched for in the system library paths instead
+        let library = if path.parent() == Some(Path::new("")) {
+            Library::new(Path::new(".").join(path))?
+        } else {
+            Library::new(path)?
+        };
+        Ok(Self {
+            drop_disk_to_board_succeeded: *library
+                .get::<DropDiskToBoardSucceeded>(b"DropDiskToBoardSucceeded\0")?,
+            check_for_winner: *library.get::<CheckForWinner>(b"CheckForWinner\0")?,
+            _library: Arc::new(library),
+        })
+    }
+
+    /// Returns a new empty board using this library
+    pub fn new_board(&self) -> PluginBoard {
+        PluginBoard {
+            plugin: self.clone(),
+            board: Box::new(RawBoard {
+                board: [[EMPTY; BOARD_WIDTH as usize]; BOARD_HEIGHT as usize],
+            }),
+        }
+    }
+}
+
+/// A board whose moves are made by a Plugin
+pub struct PluginBoard {
+    plugin: Plugin,
+    board: Box<RawBoard>,
+}
+
+impl PluginBoard {
+    /// Drops a disk for the player into the column, which may be any int the C++ code accepts,
+    /// including negative and out of range columns
+    /// Returns whether the player has now won
+    pub fn drop_disk_into(&mut self, player_num: u8, col: i32) -> Result<bool, IllegalMove> {
+        let disk = if player_num == 1 { PLAYER_1 } else { PLAYER_2 };
+        let board: *mut RawBoard = &mut *self.board;
+        // Safety: the board matches board.hpp, and the library was trusted when it was loaded
+        unsafe {
+            if (self.plugin.drop_disk_to_board_succeeded)(board, disk, col as c_int) {
+                Ok((self.plugin.check_for_winner)(board, disk))
+            } else {
+                Err(IllegalMove)
+            }
+        }
+    }
+
+    /// Returns whether every space holds a DiskType, rather than a value written by mistake
+    pub fn is_well_formed(&self) -> bool {
+        self.board
+            .board
+            .iter()
+            .flatten()
+            .all(|cell| [PLAYER_1, PLAYER_2, EMPTY].contains(cell))
+    }
+}
+
+impl BoardEngine for PluginBoard {
+    fn drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, IllegalMove> {
+        self.drop_disk_into(player_num, col as i32)
+    }
+
+    fn disks(&self) -> [u64; 2] {
+        let mut disks = [0; 2];
+        for (row, cells) in self.board.board.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                match *cell {
+                    PLAYER_1 => disks[0] |= bit(row as u8, col as u8),
+                    PLAYER_2 => disks[1] |= bit(row as u8, col as u8),
+                    _ => {}
+                }
+            }
+        }
+        disks
+    }
+}
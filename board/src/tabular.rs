@@ -0,0 +1,267 @@
+//! tabular learns a value table for Connect Four by self-play, with Q-learning or TD learning,
+//! for board-tabular to demonstrate reinforcement learning on boards small enough for a table to
+//! hold every position
+//!
+//! Boards of any size from 4x4 up are played, as long as a column of each fits in the bits of a
+//! u64 with the same layout as the standard board's
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{rng::Rng, training};
+use constants::{
+    bitboard::has_won,
+    table::{self, ValueTable},
+};
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// A position on a board of any size, from the point of view of the player about to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmallBoard {
+    width: u8,
+    height: u8,
+    /// Disks of the player about to move
+    current: u64,
+    /// Disks of both players
+    mask: u64,
+    moves: usize,
+}
+
+impl SmallBoard {
+    /// Returns whether a board of the given size can be played
+    pub fn fits(width: u8, height: u8) -> bool {
+        width >= 4 && height >= 4 && width as u32 * (height as u32 + 1) <= u64::BITS
+    }
+
+    /// Creates an empty board of the given size, which must fit (see fits)
+    pub fn new(width: u8, height: u8) -> Self {
+        Self {
+            width,
+            height,
+            current: 0,
+            mask: 0,
+            moves: 0,
+        }
+    }
+
+    /// Returns the number of columns
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// Returns whether a disk can be dropped into the column
+    pub fn can_play(&self, col: u8) -> bool {
+        col < self.width && self.mask & (1 << (self.height - 1 + col * (self.height + 1))) == 0
+    }
+
+    /// Drops a disk for the player about to move into the column, which must be playable
+    pub fn play(&mut self, col: u8) {
+        self.current ^= self.mask;
+        self.mask |= self.mask + (1 << (col * (self.height + 1)));
+        self.moves += 1;
+    }
+
+    /// Returns whether dropping a disk into the column wins the game for the player about to move
+    pub fn is_winning_move(&self, col: u8) -> bool {
+        if !self.can_play(col) {
+            return false;
+        }
+        let mut next = *self;
+        next.play(col);
+        has_won(next.current ^ next.mask, self.height)
+    }
+
+    /// Lets the player about to move skip their turn, to see what the opponent could do
+    pub fn pass(&mut self) {
+        self.current ^= self.mask;
+    }
+
+    /// Returns whether the next disk fills the board
+    pub fn is_last_move(&self) -> bool {
+        self.moves + 1 == self.width as usize * self.height as usize
+    }
+
+    /// Returns the columns a disk can be dropped into
+    pub fn playable(&self) -> Vec<u8> {
+        (0..self.width).filter(|&col| self.can_play(col)).collect()
+    }
+
+    /// Returns the key the position is stored under in a value table
+    pub fn key(&self) -> u64 {
+        table::key(self.current, self.mask, self.width, self.height)
+    }
+}
+
+/// How a Learner learns from the moves it plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Learns the value of best play, whichever move was actually played
+    QLearning,
+    /// Learns the value of the moves actually played, exploring included
+    TD,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "q-learning" => Ok(Self::QLearning),
+            "td" => Ok(Self::TD),
+            _ => Err(format!("unknown method {}, expected q-learning or td", s)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QLearning => write!(f, "q-learning"),
+            Self::TD => write!(f, "td"),
+        }
+    }
+}
+
+/// What a Learner has learned about a position
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    value: f32,
+    visits: u32,
+}
+
+/// Learns the value of positions for the player about to move by playing against itself, from
+/// -1 if they lose to 1 if they win
+pub struct Learner {
+    pub method: Method,
+    /// How far each value moves towards what a game suggests it should be
+    pub learning_rate: f32,
+    /// How often to play a random move rather than the best known one
+    pub exploration: f32,
+    values: HashMap<u64, Entry>,
+}
+
+impl Learner {
+    /// Creates a learner which knows nothing yet, taking every position as worth 0
+    pub fn new(method: Method, learning_rate: f32, exploration: f32) -> Self {
+        Self {
+            method,
+            learning_rate,
+            exploration,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Returns how many positions the learner has a value for
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the score of playing each playable column for the player about to move: 1 for a
+    /// win, 0 for filling the board, and otherwise what the position it leads to is worth to them
+    pub fn move_scores(&self, board: &SmallBoard) -> Vec<(u8, f32)> {
+        board
+            .playable()
+            .into_iter()
+            .map(|col| {
+                let score = if board.is_winning_move(col) {
+                    1.0
+                } else if board.is_last_move() {
+                    0.0
+                } else {
+                    let mut next = *board;
+                    next.play(col);
+                    -self
+                        .values
+                        .get(&next.key())
+                        .map_or(0.0, |entry| entry.value)
+                };
+                (col, score)
+            })
+            .collect()
+    }
+
+    /// Returns one of the columns with the best score, chosen at random
+    pub fn best_move(&self, board: &SmallBoard, rng: &mut Rng) -> u8 {
+        let scores = self.move_scores(board);
+        let (col, _) = pick_best(&scores, rng);
+        col
+    }
+
+    /// Plays a game against itself on an empty board of the given size, learning from every
+    /// move, and returns how far off the values were on average
+    pub fn play_game(&mut self, width: u8, height: u8, rng: &mut Rng) -> f32 {
+        let mut board = SmallBoard::new(width, height);
+        let (mut error, mut updates) = (0.0, 0);
+        loop {
+            let scores = self.move_scores(&board);
+            let (best_col, best) = pick_best(&scores, rng);
+            let (col, score) = if training::uniform(rng) < self.exploration {
+                scores[rng.below(scores.len() as u64) as usize]
+            } else {
+                (best_col, best)
+            };
+            let target = match self.method {
+                Method::QLearning => best,
+                Method::TD => score,
+            };
+            let entry = self.values.entry(board.key()).or_default();
+            error += (target - entry.value).abs();
+            updates += 1;
+            entry.value += self.learning_rate * (target - entry.value);
+            entry.visits += 1;
+
+            if board.is_winning_move(col) || board.is_last_move() {
+                return error / updates as f32;
+            }
+            board.play(col);
+        }
+    }
+
+    /// Returns a table of the values of positions visited at least min_visits times, for a board
+    /// of the given size
+    pub fn table(&self, width: u8, height: u8, min_visits: u32) -> ValueTable {
+        ValueTable::new(
+            width,
+            height,
+            self.values
+                .iter()
+                .filter(|(_, entry)| entry.visits >= min_visits)
+                .map(|(&key, entry)| (key, entry.value)),
+        )
+    }
+}
+
+/// Returns one of the columns with the best score, chosen at random, and its score
+fn pick_best(scores: &[(u8, f32)], rng: &mut Rng) -> (u8, f32) {
+    let best = scores
+        .iter()
+        .map(|&(_, score)| score)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let best_cols: Vec<u8> = scores
+        .iter()
+        .filter(|&&(_, score)| score == best)
+        .map(|&(col, _)| col)
+        .collect();
+    (best_cols[rng.below(best_cols.len() as u64) as usize], best)
+}
@@ -0,0 +1,299 @@
+//! training fits the policy and value network to labelled positions on the CPU, for
+//! board-train to turn self-play games into network weights
+//!
+//! The gradients are worked out by hand for the network's shape: ReLU hidden layers, a softmax
+//! policy over the playable columns and a tanh value, and followed with Adam
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::rng::Rng;
+use constants::{
+    network::{Layer, Network},
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+const WIDTH: usize = BOARD_WIDTH as usize;
+
+/// A position with what the network should make of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Disks of the player about to move
+    pub current: u64,
+    /// Disks of both players
+    pub mask: u64,
+    /// How likely each column should be to be chosen, adding up to 1
+    pub policy: [f32; WIDTH],
+    /// 1 if the player about to move wins with best play, -1 if they lose and 0 for a draw
+    pub value: f32,
+}
+
+/// How far the network is from a set of samples
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Loss {
+    /// Cross entropy of the policy with the samples' policies
+    pub policy: f32,
+    /// Squared error of the value
+    pub value: f32,
+    /// Fraction of samples where the column the policy likes best is one they would choose
+    pub accuracy: f32,
+}
+
+/// Everything worked out on the way through the network, which the gradients need
+struct Pass {
+    /// Inputs, then the output of each hidden layer after ReLU
+    activations: Vec<Vec<f32>>,
+    policy: [f32; WIDTH],
+    value: f32,
+}
+
+/// Trains a network with Adam, keeping its running averages of the gradients between batches
+pub struct Trainer {
+    pub network: Network,
+    pub learning_rate: f32,
+    /// Running averages of the gradients and of their squares, laid out like the network
+    first_moments: Network,
+    second_moments: Network,
+    steps: i32,
+}
+
+impl Trainer {
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    /// Creates a trainer for the network
+    pub fn new(network: Network, learning_rate: f32) -> Self {
+        let sizes = hidden_sizes(&network);
+        Self {
+            network,
+            learning_rate,
+            first_moments: Network::new(&sizes),
+            second_moments: Network::new(&sizes),
+            steps: 0,
+        }
+    }
+
+    /// Takes one step down the gradient of the loss over the batch, and returns the loss
+    /// before the step
+    pub fn step(&mut self, batch: &[&Sample]) -> Loss {
+        let mut gradients = Network::new(&hidden_sizes(&self.network));
+        let mut loss = Loss::default();
+        for sample in batch {
+            let pass = self.forward(sample);
+            add_loss(&mut loss, &pass, sample);
+            self.backward(&pass, sample, &mut gradients);
+        }
+        let scale = 1.0 / batch.len().max(1) as f32;
+
+        self.steps += 1;
+        let correction1 = 1.0 - Self::BETA1.powi(self.steps);
+        let correction2 = 1.0 - Self::BETA2.powi(self.steps);
+        let layers = self
+            .network
+            .layers_mut()
+            .zip(gradients.layers())
+            .zip(self.first_moments.layers_mut())
+            .zip(self.second_moments.layers_mut());
+        for (((layer, gradient), first), second) in layers {
+            let parameters = layer.weights.iter_mut().chain(layer.biases.iter_mut());
+            let gradient = gradient.weights.iter().chain(&gradient.biases);
+            let first = first.weights.iter_mut().chain(first.biases.iter_mut());
+            let second = second.weights.iter_mut().chain(second.biases.iter_mut());
+            for (((parameter, gradient), first), second) in
+                parameters.zip(gradient).zip(first).zip(second)
+            {
+                let gradient = gradient * scale;
+                *first = Self::BETA1 * *first + (1.0 - Self::BETA1) * gradient;
+                *second = Self::BETA2 * *second + (1.0 - Self::BETA2) * gradient * gradient;
+                *parameter -= self.learning_rate * (*first / correction1)
+                    / ((*second / correction2).sqrt() + Self::EPSILON);
+            }
+        }
+        average(loss, batch.len())
+    }
+
+    /// Returns how far the network is from the samples, without training it
+    pub fn loss(&self, samples: &[Sample]) -> Loss {
+        let mut loss = Loss::default();
+        for sample in samples {
+            add_loss(&mut loss, &self.forward(sample), sample);
+        }
+        average(loss, samples.len())
+    }
+
+    ///// PRIVATE METHODS /////
+
+    fn forward(&self, sample: &Sample) -> Pass {
+        let mut activations = vec![Network::inputs(sample.current, sample.mask)];
+        for layer in &self.network.hidden {
+            let mut outputs = layer.forward(&activations[activations.len() - 1]);
+            outputs.iter_mut().for_each(|a| *a = a.max(0.0));
+            activations.push(outputs);
+        }
+        let evaluation = self
+            .network
+            .evaluate_features(&activations[activations.len() - 1], sample.mask);
+        Pass {
+            activations,
+            policy: evaluation.policy,
+            value: evaluation.value,
+        }
+    }
+
+    /// Adds the gradient of the sample's loss to the gradients
+    fn backward(&self, pass: &Pass, sample: &Sample, gradients: &mut Network) {
+        let features = &pass.activations[pass.activations.len() - 1];
+        // Cross entropy through a softmax, and squared error through a tanh
+        let policy_errors: Vec<f32> = (0..WIDTH)
+            .map(|col| pass.policy[col] - sample.policy[col])
+            .collect();
+        let value_errors = [2.0 * (pass.value - sample.value) * (1.0 - pass.value * pass.value)];
+
+        let mut errors = vec![0.0; features.len()];
+        let heads = [
+            (
+                &self.network.policy,
+                &mut gradients.policy,
+                &policy_errors[..],
+            ),
+            (&self.network.value, &mut gradients.value, &value_errors[..]),
+        ];
+        for (layer, gradient, outputs) in heads {
+            add_layer_gradient(layer, gradient, features, outputs, &mut errors);
+        }
+
+        for (index, layer) in self.network.hidden.iter().enumerate().rev() {
+            // ReLU passes the error back only where it let the sum through
+            for (error, activation) in errors.iter_mut().zip(&pass.activations[index + 1]) {
+                if *activation <= 0.0 {
+                    *error = 0.0;
+                }
+            }
+            let inputs = &pass.activations[index];
+            let mut input_errors = vec![0.0; inputs.len()];
+            add_layer_gradient(
+                layer,
+                &mut gradients.hidden[index],
+                inputs,
+                &errors,
+                &mut input_errors,
+            );
+            errors = input_errors;
+        }
+    }
+}
+
+/// Creates a network with hidden layers of the given sizes, with random weights suited to ReLU
+/// and biases of 0
+pub fn random_network(hidden_sizes: &[usize], rng: &mut Rng) -> Network {
+    let mut network = Network::new(hidden_sizes);
+    for layer in network.layers_mut() {
+        let limit = (6.0 / layer.inputs as f32).sqrt();
+        for weight in &mut layer.weights {
+            *weight = limit * (2.0 * uniform(rng) - 1.0);
+        }
+    }
+    network
+}
+
+/// Returns a number from 0 up to but not including 1
+pub fn uniform(rng: &mut Rng) -> f32 {
+    const STEPS: u64 = 1 << 24;
+    rng.below(STEPS) as f32 / STEPS as f32
+}
+
+/// Returns the sample with the board flipped left to right, which is just as good a position
+pub fn mirror(sample: &Sample) -> Sample {
+    let mut policy = sample.policy;
+    policy.reverse();
+    Sample {
+        current: mirror_disks(sample.current),
+        mask: mirror_disks(sample.mask),
+        policy,
+        value: sample.value,
+    }
+}
+
+fn mirror_disks(disks: u64) -> u64 {
+    let column = (1 << (BOARD_HEIGHT + 1)) - 1;
+    (0..WIDTH).fold(0, |mirrored, col| {
+        let cells = (disks >> (col * (BOARD_HEIGHT as usize + 1))) & column;
+        mirrored | cells << ((WIDTH - 1 - col) * (BOARD_HEIGHT as usize + 1))
+    })
+}
+
+fn hidden_sizes(network: &Network) -> Vec<usize> {
+    network.hidden.iter().map(|layer| layer.outputs).collect()
+}
+
+/// Adds the gradient of a layer's weights and biases, given its inputs and the error in each of
+/// its outputs, and adds the error that passes back to each input
+fn add_layer_gradient(
+    layer: &Layer,
+    gradient: &mut Layer,
+    inputs: &[f32],
+    output_errors: &[f32],
+    input_errors: &mut [f32],
+) {
+    let rows = layer
+        .weights
+        .chunks_exact(layer.inputs)
+        .zip(gradient.weights.chunks_exact_mut(layer.inputs));
+    for ((weights, gradients), (bias, error)) in
+        rows.zip(gradient.biases.iter_mut().zip(output_errors))
+    {
+        if *error == 0.0 {
+            continue;
+        }
+        *bias += error;
+        for (((weight, gradient), input), input_error) in weights
+            .iter()
+            .zip(gradients.iter_mut())
+            .zip(inputs)
+            .zip(input_errors.iter_mut())
+        {
+            *gradient += error * input;
+            *input_error += error * weight;
+        }
+    }
+}
+
+fn add_loss(loss: &mut Loss, pass: &Pass, sample: &Sample) {
+    loss.policy -= (0..WIDTH)
+        .filter(|&col| sample.policy[col] > 0.0)
+        .map(|col| sample.policy[col] * pass.policy[col].max(f32::MIN_POSITIVE).ln())
+        .sum::<f32>();
+    loss.value += (pass.value - sample.value).powi(2);
+    let favorite = (0..WIDTH)
+        .max_by(|&a, &b| pass.policy[a].total_cmp(&pass.policy[b]))
+        .unwrap_or_default();
+    if sample.policy[favorite] > 0.0 {
+        loss.accuracy += 1.0;
+    }
+}
+
+fn average(loss: Loss, count: usize) -> Loss {
+    let count = count.max(1) as f32;
+    Loss {
+        policy: loss.policy / count,
+        value: loss.value / count,
+        accuracy: loss.accuracy / count,
+    }
+}
@@ -0,0 +1,75 @@
+//! bitboard holds the bit tricks shared by everything that reads positions as bitboards: the
+//! solver, the heuristic, the opening book and the learned value tables
+//!
+//! A position is a pair of u64s, one bit per cell, with each column taking BOARD_HEIGHT + 1 bits
+//! counting up from the bottom, so a column never carries into the next
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{BOARD_HEIGHT, BOARD_WIDTH};
+
+/// A bit at the bottom of every column
+pub const BOTTOM: u64 = {
+    let mut bottom = 0;
+    let mut col = 0;
+    while col < BOARD_WIDTH {
+        bottom |= 1 << (col * (BOARD_HEIGHT + 1));
+        col += 1;
+    }
+    bottom
+};
+/// Every playable cell of the board
+pub const BOARD: u64 = BOTTOM * ((1 << BOARD_HEIGHT) - 1);
+
+/// Returns the bit for the given row (counting up from the bottom) and column
+pub fn bit(row: u8, col: u8) -> u64 {
+    1 << (row + col * (BOARD_HEIGHT + 1))
+}
+
+/// Returns every cell of the column
+pub fn column(col: u8) -> u64 {
+    ((1 << BOARD_HEIGHT) - 1) << (col * (BOARD_HEIGHT + 1))
+}
+
+/// Returns the empty cells which would complete four in a row for the disks
+pub fn winning_cells(disks: u64, mask: u64) -> u64 {
+    let height = BOARD_HEIGHT as u64;
+    // Vertical
+    let mut cells = (disks << 1) & (disks << 2) & (disks << 3);
+    // Horizontal, and both diagonals
+    for shift in [height + 1, height, height + 2] {
+        let pairs = (disks << shift) & (disks << (2 * shift));
+        cells |= pairs & (disks << (3 * shift));
+        cells |= pairs & (disks >> shift);
+        let pairs = (disks >> shift) & (disks >> (2 * shift));
+        cells |= pairs & (disks << shift);
+        cells |= pairs & (disks >> (3 * shift));
+    }
+    cells & (BOARD ^ mask)
+}
+
+/// Returns whether the disks have four in a row on a board of the given height
+pub fn has_won(disks: u64, height: u8) -> bool {
+    let height = height as u64;
+    [1, height, height + 1, height + 2].iter().any(|&shift| {
+        let pairs = disks & (disks >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
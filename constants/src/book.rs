@@ -0,0 +1,158 @@
+//! book holds an opening book: exact scores of positions up to some number of moves into the
+//! game, worked out ahead of time by board-book so the AIs do not have to search the opening
+//!
+//! A book need not hold every position. For the positions the AI moves in, it holds one move
+//! which reaches the best score and the position that move leads to, so it can be followed move
+//! by move. A position and its mirror image score the same, so only one of them is stored
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{bitboard::has_won, table, BOARD_HEIGHT, BOARD_WIDTH};
+
+/// Starts every opening book file
+const MAGIC: &[u8; 4] = b"C4OB";
+const VERSION: u8 = 1;
+/// Magic, version, board width and height, most moves into the game, and number of entries
+const HEADER_SIZE: usize = 12;
+/// Each entry is a position's key in 7 bytes followed by its score
+const ENTRY_SIZE: usize = 8;
+const KEY_SIZE: usize = ENTRY_SIZE - 1;
+const CELLS: i8 = (BOARD_WIDTH * BOARD_HEIGHT) as i8;
+
+/// Exact scores of positions, for the player about to move: positive if they win with best play,
+/// larger the sooner they do, negative if they lose and 0 for a draw
+/// The score is the number of their disks left unplayed when the game is won
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpeningBook {
+    /// Most disks a position in the book can have
+    max_ply: u8,
+    /// Scores by key, sorted by key
+    entries: Vec<(u64, i8)>,
+}
+
+impl OpeningBook {
+    /// Creates a book of positions with at most max_ply disks from their keys (see key) and scores
+    pub fn new(max_ply: u8, mut entries: Vec<(u64, i8)>) -> Self {
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        entries.dedup_by_key(|(key, _)| *key);
+        Self { max_ply, entries }
+    }
+
+    /// Returns the most disks a position in the book can have
+    pub fn max_ply(&self) -> u8 {
+        self.max_ply
+    }
+
+    /// Returns how many positions are in the book
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the score of the position, given the disks of the player about to move and the
+    /// disks of both players, or None if it is not in the book
+    pub fn get(&self, current: u64, mask: u64) -> Option<i8> {
+        let key = key(current, mask);
+        self.entries
+            .binary_search_by_key(&key, |(key, _)| *key)
+            .ok()
+            .map(|i| self.entries[i].1)
+    }
+
+    /// Returns the columns of the position which the book shows reach its score, for the player
+    /// about to move, given the disks of the player about to move and the disks of both players
+    /// Returns None unless the position and at least one such column are in the book
+    pub fn best_moves(&self, current: u64, mask: u64) -> Option<Vec<u8>> {
+        let score = self.get(current, mask)?;
+        let moves = mask.count_ones() as i8;
+        let best_cols: Vec<u8> = (0..BOARD_WIDTH)
+            .filter(|&col| {
+                let column = ((1 << BOARD_HEIGHT) - 1) << (col * (BOARD_HEIGHT + 1));
+                let cell = (mask + (1 << (col * (BOARD_HEIGHT + 1)))) & column;
+                if cell == 0 {
+                    false
+                } else if has_won(current | cell, BOARD_HEIGHT) {
+                    (CELLS + 1 - moves) / 2 == score
+                } else {
+                    self.get(current ^ mask, mask | cell) == Some(-score)
+                }
+            })
+            .collect();
+        (!best_cols.is_empty()).then_some(best_cols)
+    }
+
+    /// Turns the book into the bytes of an opening book file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[VERSION, BOARD_WIDTH, BOARD_HEIGHT, self.max_ply]);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for &(key, score) in &self.entries {
+            bytes.extend_from_slice(&key.to_le_bytes()[..KEY_SIZE]);
+            bytes.push(score as u8);
+        }
+        bytes
+    }
+
+    /// Turns the bytes of an opening book file made by to_bytes back into the book
+    /// Fails if the bytes are not an opening book for this size of board
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err("not an opening book".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported opening book version {}", bytes[4]));
+        }
+        if (bytes[5], bytes[6]) != (BOARD_WIDTH, BOARD_HEIGHT) {
+            return Err(format!(
+                "opening book is for a {}x{} board, not {}x{}",
+                bytes[5], bytes[6], BOARD_WIDTH, BOARD_HEIGHT
+            ));
+        }
+        let max_ply = bytes[7];
+        let len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        let body = &bytes[HEADER_SIZE..];
+        if body.len() != len * ENTRY_SIZE {
+            return Err(format!(
+                "opening book should have {} entries, but has {} bytes of them",
+                len,
+                body.len()
+            ));
+        }
+        let entries = body
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let mut key = [0; 8];
+                key[..KEY_SIZE].copy_from_slice(&entry[..KEY_SIZE]);
+                (u64::from_le_bytes(key), entry[KEY_SIZE] as i8)
+            })
+            .collect();
+        Ok(Self::new(max_ply, entries))
+    }
+}
+
+/// Returns the key a position is stored under, given the disks of the player about to move and
+/// the disks of both players: the smaller of its key and its mirror image's
+pub fn key(current: u64, mask: u64) -> u64 {
+    table::key(current, mask, BOARD_WIDTH, BOARD_HEIGHT)
+}
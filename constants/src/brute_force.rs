@@ -0,0 +1,278 @@
+//! brute_force contains the search behind the brute force AI, shared by the web app and by
+//! board-train, which plays networks against it
+//! At a high level, it finds the best move(s) by looking at all possible moves until the end of
+//! the game (or however far it is allowed), then prefers the move that guarantees the soonest
+//! win, or avoids a loss for as long as possible.
+//! It looks one move further ahead at a time until it is told to stop, so it can think for about
+//! as long early in the game as late in it.
+//! Where it stops looking ahead, it guesses who is winning from the threats on the board.
+
+/*
+ * This file is part of Rust-Connect-Four
+ *
+ * File derived from Connect4 Game Solver <https://github.com/PascalPons/connect4>
+ * Copyright (C) 2017-2019 Pascal Pons <contact@gamesolver.org>
+ *
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    heuristic::HeuristicWeights,
+    position_lookup_table::{Bound, PositionLookupTable},
+    solver::{Position, COLUMN_ORDER},
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+/// Guessed scores are kept between -HEURISTIC_LIMIT and HEURISTIC_LIMIT, and win scores are
+/// moved up past them, so a guess is never mistaken for a win or a loss
+pub const HEURISTIC_LIMIT: i8 = 25;
+const WIN_SCORE_OFFSET: i8 = HEURISTIC_LIMIT + 1;
+/// Score of a column which cannot be played, worse than losing
+pub const UNPLAYABLE: i8 = -100;
+/// Positions to search between asking whether to stop
+const NODES_PER_STOP_CHECK: u64 = 1024;
+
+/// Searches positions with alpha-beta negamax, remembering what it learns between them
+pub struct BruteForceSearch {
+    // Stores a fixed-size table of recently calculated board states, to avoid recalculating
+    pub position_lookup_table: PositionLookupTable,
+    // How much each threat on the board is worth, to guess the score where the search stops
+    pub weights: HeuristicWeights,
+    // Positions searched for the current move, to only ask whether to stop every so often
+    nodes: u64,
+    // Whether the search was told to stop, making the search under way worthless
+    stopped: bool,
+}
+
+impl BruteForceSearch {
+    /// Creates a BruteForceSearch whose lookup table holds the given number of entries
+    pub fn new(table_size: usize) -> Self {
+        Self {
+            position_lookup_table: PositionLookupTable::new(table_size),
+            weights: HeuristicWeights::DEFAULT,
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    /// Returns the score of each column for the player about to move, UNPLAYABLE for full ones,
+    /// and how many moves ahead they were found by looking.
+    /// Looks one move further ahead at a time, up to max_depth moves, until a forced win or loss
+    /// is found or should_stop returns true, which it is asked every so often.
+    /// A search cut short is thrown away, so the scores are those of the deepest search finished
+    pub fn scores(
+        &mut self,
+        position: &Position,
+        max_depth: u8,
+        mut should_stop: impl FnMut() -> bool,
+    ) -> ([i8; BOARD_WIDTH as usize], u8) {
+        self.nodes = 0;
+        self.stopped = false;
+        let moves_left = BOARD_WIDTH * BOARD_HEIGHT - position.moves() as u8;
+        let max_depth = max_depth.min(moves_left).max(1);
+
+        let mut scores = [UNPLAYABLE; BOARD_WIDTH as usize];
+        let mut depth = 0;
+        for num_moves_look_ahead in 1..=max_depth {
+            match self.scores_at_depth(position, num_moves_look_ahead, &mut should_stop) {
+                Some(found) => scores = found,
+                None => break, // the search was cut short, so its scores mean nothing
+            }
+            depth = num_moves_look_ahead;
+            // a forced win or loss was found, and looking further ahead cannot change it
+            if scores
+                .iter()
+                .max()
+                .is_some_and(|&max| max.abs() > HEURISTIC_LIMIT)
+            {
+                break;
+            }
+        }
+        (scores, depth)
+    }
+
+    ///// PRIVATE METHODS /////
+
+    /// Returns the score of each column, looking the given number of moves ahead, or None if
+    /// the search was told to stop first
+    fn scores_at_depth(
+        &mut self,
+        position: &Position,
+        num_moves_look_ahead: u8,
+        should_stop: &mut impl FnMut() -> bool,
+    ) -> Option<[i8; BOARD_WIDTH as usize]> {
+        let mut scores = [UNPLAYABLE; BOARD_WIDTH as usize];
+        for col in 0..BOARD_WIDTH {
+            if !position.can_play(col) {
+                continue;
+            }
+            // if going in one column results in a win, set the score to the best possible score.
+            scores[col as usize] = if position.is_winning_move(col) {
+                win_score(position.moves())
+            } else {
+                // otherwise, calculate the score of the board state after the move
+                let mut child = *position;
+                child.play(col);
+                let score = -self.score(
+                    &child,
+                    num_moves_look_ahead,
+                    UNPLAYABLE,
+                    -UNPLAYABLE,
+                    should_stop,
+                );
+                if self.stopped {
+                    return None;
+                }
+                score
+            };
+        }
+        Some(scores)
+    }
+
+    /// Guesses the score of a position for the player about to move, from the threats on the board
+    fn heuristic_score(&self, position: &Position) -> i8 {
+        let (current, mask) = position.disks();
+        let limit = HEURISTIC_LIMIT as i32;
+        self.weights.evaluate(current, mask).clamp(-limit, limit) as i8
+    }
+
+    /// Get the score of some board state for the player about to move.
+    /// Score = win_score of the winning move, or 0 for draw. If the player cannot win, score = -score.
+    /// Where the search stops before the game ends, the score is guessed by heuristic_score.
+    /// Recursive alpha-beta pruning algorithm, taking advantage of the fact
+    /// that the opponent's score is the opposite of the player's score to
+    /// avoid checking paths that could not be better than a previous path.
+    fn score(
+        &mut self,
+        position: &Position,
+        num_moves_look_ahead: u8,
+        mut min_self_score: i8,
+        mut min_opponent_score: i8,
+        should_stop: &mut impl FnMut() -> bool,
+    ) -> i8 {
+        // check if the current player can win on this move
+        if position.can_win_next() {
+            return win_score(position.moves());
+        }
+
+        // if the board is full and the game is a draw, stop
+        if position.moves() == (BOARD_WIDTH * BOARD_HEIGHT) as usize {
+            return 0;
+        }
+        // if we've already searched deep enough, guess the score instead
+        if num_moves_look_ahead == 1 {
+            return self.heuristic_score(position);
+        }
+
+        // the player can't win on this move, so the score can be at most winning with their next disk
+        let max_possible_score = win_score(position.moves() + 2);
+        if max_possible_score < min_opponent_score {
+            min_opponent_score = max_possible_score;
+            // prune; we want to minimize the opponent's score, so if we can't do any better,
+            // we can stop searching this path
+            if min_self_score >= min_opponent_score {
+                return min_opponent_score;
+            }
+        }
+
+        // give up once told to; a score found in part is not to be trusted or remembered
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODES_PER_STOP_CHECK) && should_stop() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+
+        // use what an earlier search that looked at least as far ahead found out about this position
+        if let Some(entry) = self
+            .position_lookup_table
+            .get(position, num_moves_look_ahead)
+        {
+            match entry.bound {
+                Bound::Exact => return entry.score,
+                Bound::Lower => min_self_score = min_self_score.max(entry.score),
+                Bound::Upper => min_opponent_score = min_opponent_score.min(entry.score),
+            }
+            if min_self_score >= min_opponent_score {
+                return entry.score;
+            }
+        }
+        let original_min_self_score = min_self_score;
+
+        // calculate the score of each possible move
+        for col in COLUMN_ORDER {
+            if !position.can_play(col) {
+                continue;
+            }
+            let mut child = *position;
+            child.play(col);
+            let score = -self.score(
+                &child,
+                num_moves_look_ahead - 1,
+                -min_opponent_score,
+                -min_self_score,
+                should_stop,
+            );
+            if self.stopped {
+                return 0;
+            }
+
+            if score >= min_opponent_score {
+                // the opponent won't allow this position, so the score is only known to be at least this
+                self.position_lookup_table.insert(
+                    position,
+                    score,
+                    Bound::Lower,
+                    num_moves_look_ahead,
+                );
+                return score;
+            }
+            if score > min_self_score {
+                min_self_score = score; // No reason to go with worse options
+            }
+        }
+
+        // if no move did better than we already had, the score is only known to be at most this
+        let bound = if min_self_score > original_min_self_score {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.position_lookup_table
+            .insert(position, min_self_score, bound, num_moves_look_ahead); // Store this position for future use
+        min_self_score
+    }
+}
+
+/// Returns the score of winning with the disk dropped after the given number of disks:
+/// larger the sooner the win, and always larger than a guessed score
+pub fn win_score(disks: usize) -> i8 {
+    (BOARD_HEIGHT * BOARD_WIDTH + 1) as i8 - disks as i8 + WIN_SCORE_OFFSET
+}
+
+/// Returns the columns with the highest score, to choose between at random, or none if no
+/// column can be played
+pub fn best_columns(scores: &[i8; BOARD_WIDTH as usize]) -> Vec<u8> {
+    let max = scores.iter().copied().max().unwrap_or(UNPLAYABLE);
+    if max == UNPLAYABLE {
+        return Vec::new();
+    }
+    (0..BOARD_WIDTH)
+        .filter(|&col| scores[col as usize] == max)
+        .collect()
+}
@@ -0,0 +1,205 @@
+//! heuristic guesses how good a position is without searching it, for the AIs to use where they
+//! stop looking ahead, and for board-heuristic to tune against a perfect solver
+//!
+//! Each feature is counted for both players and weighed by HeuristicWeights, so the weights can
+//! be changed without touching the searches that use them
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    bitboard::{winning_cells, BOARD, BOTTOM},
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+const WIDTH: usize = BOARD_WIDTH as usize;
+const HEIGHT: usize = BOARD_HEIGHT as usize;
+/// Every line of four cells on the board
+const NUM_WINDOWS: usize =
+    (WIDTH - 3) * HEIGHT + WIDTH * (HEIGHT - 3) + 2 * (WIDTH - 3) * (HEIGHT - 3);
+const WINDOWS: [u64; NUM_WINDOWS] = windows();
+
+/// The first, third and fifth rows from the bottom, where the first player's threats are worth
+/// the most, as the second player ends up filling the cell below them when the board fills up
+const ODD_ROWS: u64 = BOTTOM * 0b010101;
+/// The second, fourth and sixth rows from the bottom, where the second player's threats are
+/// worth the most
+const EVEN_ROWS: u64 = BOTTOM * 0b101010;
+const CENTER_COLUMN: u64 = ((1 << BOARD_HEIGHT) - 1) << (WIDTH / 2 * (HEIGHT + 1));
+
+/// How much each feature of a position is worth to the player it belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeuristicWeights {
+    /// Per line of four with three of the player's disks and an empty cell
+    pub open_three: i32,
+    /// Per line of four with two of the player's disks and two empty cells
+    pub open_two: i32,
+    /// Per cell which would win for the player and can be played into right now
+    pub playable_threat: i32,
+    /// Per cell which would win for the player on a row which suits them (see ODD_ROWS)
+    pub parity_threat: i32,
+    /// Per disk of the player in the center column
+    pub center: i32,
+}
+
+impl HeuristicWeights {
+    /// Weights found by board-heuristic to agree most often with the perfect solver
+    pub const DEFAULT: HeuristicWeights = HeuristicWeights {
+        open_three: 5,
+        open_two: 2,
+        playable_threat: 8,
+        parity_threat: 4,
+        center: 1,
+    };
+
+    /// Returns how good the position is for the player about to move, given their disks and
+    /// the disks of both players: positive if it favors them, negative if it favors the opponent
+    pub fn evaluate(&self, current: u64, mask: u64) -> i32 {
+        let opponent = current ^ mask;
+        // The first player is to move whenever an even number of disks has been played
+        let current_is_first = mask.count_ones().is_multiple_of(2);
+        self.evaluate_player(current, opponent, mask, current_is_first)
+            - self.evaluate_player(opponent, current, mask, !current_is_first)
+    }
+
+    /// Returns how much the features of one player's disks are worth to them
+    fn evaluate_player(&self, own: u64, other: u64, mask: u64, is_first: bool) -> i32 {
+        let mut score = 0;
+        for window in WINDOWS {
+            if window & other != 0 {
+                continue;
+            }
+            match (window & own).count_ones() {
+                3 => score += self.open_three,
+                2 => score += self.open_two,
+                _ => {}
+            }
+        }
+        let threats = winning_cells(own, mask);
+        let possible = (mask + BOTTOM) & BOARD;
+        let parity_rows = if is_first { ODD_ROWS } else { EVEN_ROWS };
+        score += self.playable_threat * (threats & possible).count_ones() as i32;
+        score += self.parity_threat * (threats & parity_rows).count_ones() as i32;
+        score += self.center * (own & CENTER_COLUMN).count_ones() as i32;
+        score
+    }
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Returns the cells of every line of four on the board
+const fn windows() -> [u64; NUM_WINDOWS] {
+    let mut windows = [0; NUM_WINDOWS];
+    let mut count = 0;
+    // Up, right, up and right, and down and right
+    let directions: [(i32, i32); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+    let mut direction = 0;
+    while direction < directions.len() {
+        let (dcol, drow) = directions[direction];
+        let mut col = 0;
+        while col < WIDTH as i32 {
+            let mut row = 0;
+            while row < HEIGHT as i32 {
+                let (end_col, end_row) = (col + 3 * dcol, row + 3 * drow);
+                if end_col < WIDTH as i32 && end_row >= 0 && end_row < HEIGHT as i32 {
+                    let mut window = 0;
+                    let mut i = 0;
+                    while i < 4 {
+                        window |= 1 << ((col + i * dcol) * (HEIGHT as i32 + 1) + row + i * drow);
+                        i += 1;
+                    }
+                    windows[count] = window;
+                    count += 1;
+                }
+                row += 1;
+            }
+            col += 1;
+        }
+        direction += 1;
+    }
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Position;
+
+    /// Games to check the evaluation on, as the columns played
+    const GAMES: [&[u8]; 4] = [
+        &[3, 3, 2, 4, 1],
+        &[3, 2, 3, 2, 4, 4, 0],
+        &[0, 1, 2, 3, 4, 5, 6, 6, 5, 4],
+        &[3, 3, 3, 3, 2, 4, 2, 4, 1, 5, 6],
+    ];
+
+    fn evaluate(weights: &HeuristicWeights, moves: &[u8]) -> i32 {
+        let (current, mask) = Position::from_moves(moves).unwrap().disks();
+        weights.evaluate(current, mask)
+    }
+
+    #[test]
+    fn empty_board_is_even() {
+        assert_eq!(HeuristicWeights::DEFAULT.evaluate(0, 0), 0);
+    }
+
+    #[test]
+    fn mirror_image_scores_the_same() {
+        for moves in GAMES {
+            let mirrored: Vec<u8> = moves.iter().map(|col| BOARD_WIDTH - 1 - col).collect();
+            assert_eq!(
+                evaluate(&HeuristicWeights::DEFAULT, moves),
+                evaluate(&HeuristicWeights::DEFAULT, &mirrored),
+                "{:?}",
+                moves
+            );
+        }
+    }
+
+    #[test]
+    fn swapping_the_players_negates_the_score() {
+        // Which rows suit a player depends on who moved first, so parity is left out
+        let weights = HeuristicWeights {
+            parity_threat: 0,
+            ..HeuristicWeights::DEFAULT
+        };
+        for moves in GAMES {
+            let (current, mask) = Position::from_moves(moves).unwrap().disks();
+            assert_eq!(
+                weights.evaluate(current ^ mask, mask),
+                -weights.evaluate(current, mask),
+                "{:?}",
+                moves
+            );
+        }
+    }
+
+    #[test]
+    fn favors_the_player_with_more_threats() {
+        // The first player has three in a row on the bottom with both ends open
+        let moves = [2, 2, 3, 3, 4, 2];
+        assert!(evaluate(&HeuristicWeights::DEFAULT, &moves) > 0);
+        // The second player faces the same threat after one more move
+        assert!(evaluate(&HeuristicWeights::DEFAULT, &[2, 2, 3, 3, 4]) < 0);
+    }
+}
@@ -19,15 +19,25 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+pub mod bitboard;
+pub mod book;
+pub mod brute_force;
+pub mod heuristic;
+pub mod network;
+pub mod neural;
+pub mod position_lookup_table;
+pub mod solver;
+pub mod table;
+
 pub const BOARD_HEIGHT: u8 = 6; // number of rows in the board
 pub const BOARD_WIDTH: u8 = 7; // number of columns in the board
-pub const LOOKUP_TABLE_SIZE: usize = 1000; // 1000 should be slightly more than 64 MB
+pub const LOOKUP_TABLE_SIZE: usize = 1 << 20; // entries in the AI's position lookup table, 8 bytes each (8 MB)
 
 /// Helper enum like struct to provide some communication standards between the client and server
 pub struct ConnectionProtocol;
 
 /// Helper struct to represent a game update to be sent between the client and server
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GameUpdate {
     pub position: u64,
     pub mask: u64,
@@ -35,6 +45,88 @@ pub struct GameUpdate {
     pub game_won: bool,
 }
 
+/// An empty board, with player 1 to move
+impl Default for GameUpdate {
+    fn default() -> Self {
+        Self {
+            position: 0,
+            mask: 0,
+            is_p1_turn: true,
+            game_won: false,
+        }
+    }
+}
+
+impl GameUpdate {
+    /// Plays the move on the board, or takes back the top disk of the column for an undo
+    /// Fails if the column is full, or empty for an undo
+    pub fn apply(&mut self, delta: MoveDelta) -> Result<(), String> {
+        if delta.col >= BOARD_WIDTH {
+            return Err(format!("No column {}", delta.col));
+        }
+        let column = ((1 << BOARD_HEIGHT) - 1) << (delta.col * (BOARD_HEIGHT + 1));
+        if delta.undo {
+            let disks = self.mask & column;
+            if disks == 0 {
+                return Err("Cannot undo move in empty column".to_string());
+            }
+            // The top disk belongs to the player who moved last, who is about to move again
+            let top = 1 << (63 - disks.leading_zeros());
+            self.position = (self.position ^ self.mask) & !top;
+            self.mask &= !top;
+        } else {
+            let cell = (self.mask + (1 << (delta.col * (BOARD_HEIGHT + 1)))) & column;
+            if cell == 0 {
+                return Err("Cannot drop disk in full column".to_string());
+            }
+            // The disks of the player who moved are now those of the player about to move next
+            self.position ^= self.mask;
+            self.mask |= cell;
+        }
+        self.is_p1_turn = !self.is_p1_turn;
+        self.game_won = delta.game_won;
+        Ok(())
+    }
+
+    /// Returns the move which changes this board into the next one, if a single move or undo does
+    pub fn delta_to(&self, next: &GameUpdate) -> Option<MoveDelta> {
+        let added = next.mask & !self.mask;
+        let removed = self.mask & !next.mask;
+        let (cell, undo) = match (added.count_ones(), removed.count_ones()) {
+            (1, 0) => (added, false),
+            (0, 1) => (removed, true),
+            _ => return None,
+        };
+        let delta = MoveDelta {
+            col: (cell.trailing_zeros() / (BOARD_HEIGHT as u32 + 1)) as u8,
+            undo,
+            game_won: next.game_won,
+        };
+        let mut board = self.clone();
+        board.apply(delta).ok()?;
+        (board == *next).then_some(delta)
+    }
+}
+
+/// A single move, or the undo of one, sent instead of the whole board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveDelta {
+    pub col: u8,
+    pub undo: bool,
+    /// Whether the move won the game
+    pub game_won: bool,
+}
+
+/// A game update sent by the server with a sequence number, so clients can tell when one was missed
+/// Sequence numbers count up from 1 for each client, wrapping around
+#[derive(Debug, Clone)]
+pub enum SequencedUpdate {
+    /// A move to play on the board the client has
+    Move { seq: u16, delta: MoveDelta },
+    /// The whole board, which replaces the board the client has
+    Snapshot { seq: u16, update: GameUpdate },
+}
+
 impl ConnectionProtocol {
     pub const KILL_CONNECTION: u8 = 255;
     pub const CONNECTION_SUCCESS: u8 = 100;
@@ -44,6 +136,11 @@ impl ConnectionProtocol {
     pub const IS_PLAYER_2: u8 = 253;
     pub const IS_SPECTATOR: u8 = 252;
     pub const SECOND_PLAYER_CONNECTED: u8 = 251;
+    pub const SEAT_TOKEN: u8 = 250;
+    pub const MOVE: u8 = 249;
+    pub const SNAPSHOT: u8 = 248;
+    /// Sent by a client that missed a game update, asking for a snapshot of the board
+    pub const RESYNC: u8 = 247;
 
     pub const COL_0: u8 = 0;
     pub const COL_1: u8 = 1;
@@ -57,12 +154,28 @@ impl ConnectionProtocol {
 
     /// Number of bytes in a message representing a GameUpdate to be sent over a websocket
     pub const MESSAGE_SIZE: usize = 14;
+    /// Number of bytes in a message representing a sequenced move
+    pub const MOVE_MESSAGE_SIZE: usize = 4;
+    /// Number of bytes in a message representing a sequenced, checksummed snapshot of the board
+    pub const SNAPSHOT_MESSAGE_SIZE: usize = Self::MESSAGE_SIZE + 5;
+    /// Number of bytes in a message giving a player the token for their seat
+    pub const SEAT_TOKEN_MESSAGE_SIZE: usize = 9;
+    /// Separates the lobby name from the seat token when a client asks to join a lobby
+    pub const SEAT_TOKEN_SEPARATOR: char = '\n';
+    /// Longest lobby name the server accepts
+    pub const MAX_LOBBY_NAME_LENGTH: usize = 16;
+    /// Number of bytes in the longest message a client sends to join a lobby: the lobby name,
+    /// the separator and a seat token of up to 16 hex digits
+    pub const MAX_JOIN_REQUEST_SIZE: usize = Self::MAX_LOBBY_NAME_LENGTH + 1 + 16;
 
     /// Bitfield masks for encoding and decoding messages
     const IS_NOT_P1_TURN: u64 = 1 << (2 * BOARD_HEIGHT + 1);
     const GAME_WON: u64 = 1 << (3 * BOARD_HEIGHT + 2);
     const UNDO_MOVE_OFFSET: u64 = 4 * BOARD_HEIGHT as u64 + 3;
     const UNDO_MOVE: u64 = 1 << Self::UNDO_MOVE_OFFSET;
+    /// Bitfield masks for the last byte of a sequenced move, the rest of which is the column
+    const DELTA_UNDO: u8 = 1 << 6;
+    const DELTA_GAME_WON: u8 = 1 << 7;
 
     /// Turns a vector of bytes, sent over a websocket, into an easily usable GameUpdate object
     /// Fails if bytes.len() != ConnectionProtocol::MESSAGE_SIZE
@@ -141,4 +254,218 @@ impl ConnectionProtocol {
             & (1 << Self::UNDO_MOVE_OFFSET % 8)
             != 0
     }
+
+    /// Turns a move into a vector of bytes, which can be sent over a websocket
+    /// The returned Vec has a length of ConnectionProtocol::MOVE_MESSAGE_SIZE
+    pub fn encode_move(seq: u16, delta: MoveDelta) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::MOVE_MESSAGE_SIZE);
+        bytes.push(Self::MOVE);
+        bytes.extend_from_slice(&seq.to_le_bytes());
+        let mut last = delta.col;
+        if delta.undo {
+            last |= Self::DELTA_UNDO;
+        }
+        if delta.game_won {
+            last |= Self::DELTA_GAME_WON;
+        }
+        bytes.push(last);
+        bytes
+    }
+
+    /// Turns a board state made by encode_message into a snapshot, which can be sent over a websocket
+    /// The returned Vec has a length of ConnectionProtocol::SNAPSHOT_MESSAGE_SIZE, ending in a checksum of the rest
+    pub fn encode_snapshot(seq: u16, board: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SNAPSHOT_MESSAGE_SIZE);
+        bytes.push(Self::SNAPSHOT);
+        bytes.extend_from_slice(&seq.to_le_bytes());
+        bytes.extend_from_slice(board);
+        let checksum = Self::checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Turns a vector of bytes made by encode_move or encode_snapshot back into the update
+    /// Returns None if the bytes do not encode either, or a snapshot's checksum does not match
+    pub fn decode_sequenced(bytes: &[u8]) -> Option<SequencedUpdate> {
+        let seq = u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]);
+        match (bytes[0], bytes.len()) {
+            (Self::MOVE, Self::MOVE_MESSAGE_SIZE) => {
+                let last = bytes[3];
+                Some(SequencedUpdate::Move {
+                    seq,
+                    delta: MoveDelta {
+                        col: last & !(Self::DELTA_UNDO | Self::DELTA_GAME_WON),
+                        undo: last & Self::DELTA_UNDO != 0,
+                        game_won: last & Self::DELTA_GAME_WON != 0,
+                    },
+                })
+            }
+            (Self::SNAPSHOT, Self::SNAPSHOT_MESSAGE_SIZE) => {
+                let (rest, checksum) = bytes.split_at(Self::SNAPSHOT_MESSAGE_SIZE - 2);
+                if Self::checksum(rest).to_le_bytes() != checksum {
+                    return None;
+                }
+                let update = Self::decode_message(rest[3..].to_vec()).ok()?;
+                Some(SequencedUpdate::Snapshot { seq, update })
+            }
+            _ => None,
+        }
+    }
+
+    /// Fletcher-16 checksum of the bytes
+    fn checksum(bytes: &[u8]) -> u16 {
+        let (mut sum1, mut sum2) = (0u16, 0u16);
+        for &byte in bytes {
+            sum1 = (sum1 + byte as u16) % 255;
+            sum2 = (sum2 + sum1) % 255;
+        }
+        (sum2 << 8) | sum1
+    }
+
+    /// Turns a seat token into a vector of bytes, which can be sent over a websocket
+    /// The returned Vec has a length of ConnectionProtocol::SEAT_TOKEN_MESSAGE_SIZE
+    pub fn encode_seat_token(token: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SEAT_TOKEN_MESSAGE_SIZE);
+        bytes.push(Self::SEAT_TOKEN);
+        bytes.extend_from_slice(&token.to_le_bytes());
+        bytes
+    }
+
+    /// Turns a vector of bytes made by encode_seat_token back into the seat token
+    /// Returns None if the bytes do not encode a seat token
+    pub fn decode_seat_token(bytes: &[u8]) -> Option<u64> {
+        if bytes.len() != Self::SEAT_TOKEN_MESSAGE_SIZE || bytes[0] != Self::SEAT_TOKEN {
+            return None;
+        }
+        let mut token = [0; 8];
+        token.copy_from_slice(&bytes[1..]);
+        Some(u64::from_le_bytes(token))
+    }
+
+    /// Creates the message a client sends to join a lobby, optionally with the token
+    /// of the seat it held in that lobby before being disconnected
+    pub fn encode_join_request(lobby: &str, seat_token: Option<u64>) -> String {
+        match seat_token {
+            Some(token) => format!("{}{}{:x}", lobby, Self::SEAT_TOKEN_SEPARATOR, token),
+            None => lobby.to_string(),
+        }
+    }
+
+    /// Returns whether the server accepts the lobby name: at most MAX_LOBBY_NAME_LENGTH
+    /// letters, digits, '-' and '_', or empty for the default lobby
+    pub fn is_valid_lobby_name(lobby: &str) -> bool {
+        lobby.len() <= Self::MAX_LOBBY_NAME_LENGTH
+            && lobby
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// Splits a message made by encode_join_request into the lobby name and seat token
+    pub fn decode_join_request(request: &str) -> (String, Option<u64>) {
+        if let Some((lobby, token)) = request.rsplit_once(Self::SEAT_TOKEN_SEPARATOR) {
+            if let Ok(token) = u64::from_str_radix(token, 16) {
+                return (lobby.to_string(), Some(token));
+            }
+        }
+        (request.to_string(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_round_trip() {
+        for seq in [0, 1, 0x1234, u16::MAX] {
+            for col in 0..BOARD_WIDTH {
+                for (undo, game_won) in [(false, false), (true, false), (false, true), (true, true)]
+                {
+                    let delta = MoveDelta {
+                        col,
+                        undo,
+                        game_won,
+                    };
+                    let bytes = ConnectionProtocol::encode_move(seq, delta);
+                    assert_eq!(bytes.len(), ConnectionProtocol::MOVE_MESSAGE_SIZE);
+                    match ConnectionProtocol::decode_sequenced(&bytes) {
+                        Some(SequencedUpdate::Move {
+                            seq: decoded_seq,
+                            delta: decoded,
+                        }) => {
+                            assert_eq!(decoded_seq, seq);
+                            assert_eq!(decoded, delta);
+                        }
+                        other => panic!("{:?} decoded as {:?}", delta, other),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut update = GameUpdate::default();
+        for col in [3, 3, 2, 4, 0, 6] {
+            let delta = MoveDelta {
+                col,
+                undo: false,
+                game_won: false,
+            };
+            update.apply(delta).unwrap();
+            let board = ConnectionProtocol::encode_message(update.clone());
+            let bytes = ConnectionProtocol::encode_snapshot(col as u16 + 300, &board);
+            assert_eq!(bytes.len(), ConnectionProtocol::SNAPSHOT_MESSAGE_SIZE);
+            match ConnectionProtocol::decode_sequenced(&bytes) {
+                Some(SequencedUpdate::Snapshot {
+                    seq,
+                    update: decoded,
+                }) => {
+                    assert_eq!(seq, col as u16 + 300);
+                    assert_eq!(decoded, update);
+                }
+                other => panic!("{:?} decoded as {:?}", update, other),
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_rejected() {
+        let mut update = GameUpdate::default();
+        update
+            .apply(MoveDelta {
+                col: 3,
+                undo: false,
+                game_won: false,
+            })
+            .unwrap();
+        let bytes =
+            ConnectionProtocol::encode_snapshot(1, &ConnectionProtocol::encode_message(update));
+        for i in 1..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0x10;
+            assert!(
+                ConnectionProtocol::decode_sequenced(&corrupted).is_none(),
+                "byte {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn other_messages_are_not_sequenced() {
+        let delta = MoveDelta {
+            col: 2,
+            undo: false,
+            game_won: false,
+        };
+        let bytes = ConnectionProtocol::encode_move(7, delta);
+        assert!(ConnectionProtocol::decode_sequenced(&[]).is_none());
+        assert!(ConnectionProtocol::decode_sequenced(&bytes[..3]).is_none());
+        assert!(ConnectionProtocol::decode_sequenced(&[bytes.clone(), vec![0]].concat()).is_none());
+        assert!(
+            ConnectionProtocol::decode_sequenced(&ConnectionProtocol::encode_seat_token(7))
+                .is_none()
+        );
+    }
 }
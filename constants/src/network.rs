@@ -0,0 +1,273 @@
+//! network holds a small policy and value network: given a position, it guesses how likely each
+//! move is to be the best one, and how likely the player about to move is to win
+//!
+//! It runs on the CPU in plain Rust, so the web app can run it in the browser, and reads and writes
+//! the same weights file as the trainer which makes it
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{BOARD_HEIGHT, BOARD_WIDTH};
+
+/// Starts every network file
+const MAGIC: &[u8; 4] = b"C4NN";
+const VERSION: u8 = 1;
+/// Magic, version, board width and height, and number of hidden layers, followed by the size of
+/// each hidden layer in 2 bytes
+const HEADER_SIZE: usize = 8;
+const CELLS: usize = BOARD_WIDTH as usize * BOARD_HEIGHT as usize;
+
+/// Layer of a network, each output being a weighted sum of the inputs plus a bias
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub inputs: usize,
+    pub outputs: usize,
+    /// Weights by output, then by input
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+impl Layer {
+    /// Creates a layer with every weight and bias 0
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Self {
+            inputs,
+            outputs,
+            weights: vec![0.0; inputs * outputs],
+            biases: vec![0.0; outputs],
+        }
+    }
+
+    /// Returns the outputs of the layer for the inputs, before any activation
+    pub fn forward(&self, inputs: &[f32]) -> Vec<f32> {
+        self.biases
+            .iter()
+            .zip(self.weights.chunks_exact(self.inputs))
+            .map(|(bias, weights)| {
+                bias + weights
+                    .iter()
+                    .zip(inputs)
+                    .map(|(weight, input)| weight * input)
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+}
+
+/// What the network makes of a position, for the player about to move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Evaluation {
+    /// How likely each column is to be the best move, 0 for full columns, adding up to 1
+    pub policy: [f32; BOARD_WIDTH as usize],
+    /// From -1 if the player about to move is sure to lose, to 1 if they are sure to win
+    pub value: f32,
+}
+
+/// Policy and value network: hidden layers with ReLU activations, then a policy head giving a
+/// score for each column and a value head giving a single score
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    pub hidden: Vec<Layer>,
+    pub policy: Layer,
+    pub value: Layer,
+}
+
+impl Network {
+    /// Inputs to the network: a cell of the board for each of the player about to move's disks,
+    /// then one for each of the opponent's disks
+    pub const INPUTS: usize = 2 * CELLS;
+
+    /// Creates a network with hidden layers of the given sizes, with every weight and bias 0
+    pub fn new(hidden_sizes: &[usize]) -> Self {
+        let mut inputs = Self::INPUTS;
+        let mut hidden = Vec::with_capacity(hidden_sizes.len());
+        for &size in hidden_sizes {
+            hidden.push(Layer::new(inputs, size));
+            inputs = size;
+        }
+        Self {
+            hidden,
+            policy: Layer::new(inputs, BOARD_WIDTH as usize),
+            value: Layer::new(inputs, 1),
+        }
+    }
+
+    /// Returns the inputs for a position, given the disks of the player about to move and the
+    /// disks of both players
+    pub fn inputs(current: u64, mask: u64) -> Vec<f32> {
+        let opponent = current ^ mask;
+        let mut inputs = vec![0.0; Self::INPUTS];
+        for col in 0..BOARD_WIDTH as usize {
+            for row in 0..BOARD_HEIGHT as usize {
+                let bit = 1 << (col * (BOARD_HEIGHT as usize + 1) + row);
+                let cell = col * BOARD_HEIGHT as usize + row;
+                if current & bit != 0 {
+                    inputs[cell] = 1.0;
+                } else if opponent & bit != 0 {
+                    inputs[CELLS + cell] = 1.0;
+                }
+            }
+        }
+        inputs
+    }
+
+    /// Returns the output of the last hidden layer for the inputs
+    pub fn features(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut activations = inputs.to_vec();
+        for layer in &self.hidden {
+            activations = layer.forward(&activations);
+            activations.iter_mut().for_each(|a| *a = a.max(0.0));
+        }
+        activations
+    }
+
+    /// Returns what the network makes of the position, given the disks of the player about to
+    /// move and the disks of both players
+    pub fn evaluate(&self, current: u64, mask: u64) -> Evaluation {
+        self.evaluate_features(&self.features(&Self::inputs(current, mask)), mask)
+    }
+
+    /// Returns what the network makes of the position from the output of its last hidden layer,
+    /// given the disks of both players
+    pub fn evaluate_features(&self, features: &[f32], mask: u64) -> Evaluation {
+        let logits = self.policy.forward(features);
+        let value = self.value.forward(features)[0].tanh();
+
+        // Only the columns which are not full can be played
+        let top_row = 1 << (BOARD_HEIGHT - 1);
+        let playable = |col: usize| mask & (top_row << (col * (BOARD_HEIGHT as usize + 1))) == 0;
+        let max = (0..BOARD_WIDTH as usize)
+            .filter(|&col| playable(col))
+            .map(|col| logits[col])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let mut policy = [0.0; BOARD_WIDTH as usize];
+        for (col, probability) in policy.iter_mut().enumerate() {
+            if playable(col) {
+                *probability = (logits[col] - max).exp();
+            }
+        }
+        let total: f32 = policy.iter().sum();
+        if total > 0.0 {
+            policy
+                .iter_mut()
+                .for_each(|probability| *probability /= total);
+        }
+        Evaluation { policy, value }
+    }
+
+    /// Turns the network into the bytes of a network file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[VERSION, BOARD_WIDTH, BOARD_HEIGHT, self.hidden.len() as u8]);
+        for layer in &self.hidden {
+            bytes.extend_from_slice(&(layer.outputs as u16).to_le_bytes());
+        }
+        for layer in self.layers() {
+            for value in layer.weights.iter().chain(&layer.biases) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Turns the bytes of a network file made by to_bytes back into the network
+    /// Fails if the bytes are not a network for this size of board
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err("not a network".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported network version {}", bytes[4]));
+        }
+        if (bytes[5], bytes[6]) != (BOARD_WIDTH, BOARD_HEIGHT) {
+            return Err(format!(
+                "network is for a {}x{} board, not {}x{}",
+                bytes[5], bytes[6], BOARD_WIDTH, BOARD_HEIGHT
+            ));
+        }
+        let num_hidden = bytes[7] as usize;
+        let sizes_end = HEADER_SIZE + 2 * num_hidden;
+        if bytes.len() < sizes_end {
+            return Err("network ends before its layer sizes".to_string());
+        }
+        let hidden_sizes: Vec<usize> = bytes[HEADER_SIZE..sizes_end]
+            .chunks_exact(2)
+            .map(|size| u16::from_le_bytes([size[0], size[1]]) as usize)
+            .collect();
+        if hidden_sizes.contains(&0) {
+            return Err("network has an empty hidden layer".to_string());
+        }
+
+        // Checked before the network is made, so a bad file cannot ask for a huge one
+        let expected = num_parameters(&hidden_sizes);
+        if bytes.len() != sizes_end + 4 * expected {
+            return Err(format!(
+                "network should have {} weights, but has {} bytes of them",
+                expected,
+                bytes.len() - sizes_end
+            ));
+        }
+        let mut network = Self::new(&hidden_sizes);
+        let mut values = bytes[sizes_end..]
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]));
+        for layer in network.layers_mut() {
+            for (value, stored) in layer
+                .weights
+                .iter_mut()
+                .chain(layer.biases.iter_mut())
+                .zip(&mut values)
+            {
+                *value = stored;
+            }
+        }
+        Ok(network)
+    }
+
+    /// Returns how many weights and biases the network has
+    pub fn num_parameters(&self) -> usize {
+        self.layers()
+            .map(|layer| layer.weights.len() + layer.biases.len())
+            .sum()
+    }
+
+    /// Returns every layer, in the order they are stored
+    pub fn layers(&self) -> impl Iterator<Item = &Layer> {
+        self.hidden.iter().chain([&self.policy, &self.value])
+    }
+
+    /// Returns every layer, in the order they are stored
+    pub fn layers_mut(&mut self) -> impl Iterator<Item = &mut Layer> {
+        self.hidden
+            .iter_mut()
+            .chain([&mut self.policy, &mut self.value])
+    }
+}
+
+/// Returns how many weights and biases a network with hidden layers of the given sizes has
+fn num_parameters(hidden_sizes: &[usize]) -> usize {
+    let mut inputs = Network::INPUTS;
+    let mut total = 0;
+    for &size in hidden_sizes {
+        total += (inputs + 1) * size;
+        inputs = size;
+    }
+    total + (inputs + 1) * (BOARD_WIDTH as usize + 1)
+}
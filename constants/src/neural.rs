@@ -0,0 +1,134 @@
+//! neural contains the search guided by the policy and value network, which board-train
+//! measures the networks it trains with
+//! At a high level, it asks a small neural network which moves look best and who is winning,
+//! and searches a few moves ahead, trying the moves the network likes first and judging the
+//! positions where it stops by the network's guess of who wins.
+//! Without a network, it judges positions with the threat heuristic instead.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    heuristic::HeuristicWeights,
+    network::{Evaluation, Network},
+    solver::Position,
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+/// The heuristic's score which counts as being about as sure to win as the network can be
+const HEURISTIC_SCALE: f32 = 32.0;
+const CELLS: usize = BOARD_WIDTH as usize * BOARD_HEIGHT as usize;
+
+/// Returns the column to play and its score for the player about to move, searching the given
+/// number of moves ahead, or None if no column can be played
+/// With a search depth of 0, only the network's choice counts, unless a move wins at once
+pub fn best_move(network: Option<&Network>, position: &Position, depth: u8) -> Option<(u8, f32)> {
+    // Try the moves the network likes best first, so it picks them if nothing is better
+    let mut best: Option<(u8, f32)> = None;
+    for col in ordered_moves(position, &evaluate(network, position).policy) {
+        let score = if position.is_winning_move(col) {
+            win_score(position)
+        } else if depth == 0 {
+            -1.0 // every move but a win scores the same, so the first one is chosen
+        } else {
+            let mut child = *position;
+            child.play(col);
+            let alpha = best.map_or(-f32::INFINITY, |(_, score)| score);
+            -score(network, &child, depth - 1, -f32::INFINITY, -alpha)
+        };
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((col, score));
+        }
+    }
+    best
+}
+
+/// Returns what the network makes of the position, or what the heuristic does if there is no
+/// network: every open column alike, and a value from the heuristic's score
+pub fn evaluate(network: Option<&Network>, position: &Position) -> Evaluation {
+    let (current, mask) = position.disks();
+    if let Some(network) = network {
+        return network.evaluate(current, mask);
+    }
+    let open_cols = (0..BOARD_WIDTH)
+        .filter(|&col| position.can_play(col))
+        .count()
+        .max(1) as f32;
+    let mut policy = [0.0; BOARD_WIDTH as usize];
+    for (col, probability) in policy.iter_mut().enumerate() {
+        if position.can_play(col as u8) {
+            *probability = 1.0 / open_cols;
+        }
+    }
+    let heuristic = HeuristicWeights::DEFAULT.evaluate(current, mask);
+    Evaluation {
+        policy,
+        value: (heuristic as f32 / HEURISTIC_SCALE).tanh(),
+    }
+}
+
+/// Get the score of some board state for the player about to move, searching the given
+/// number of moves ahead and judging the positions there by the network.
+/// Recursive alpha-beta pruning algorithm, as in the brute force search.
+fn score(
+    network: Option<&Network>,
+    position: &Position,
+    depth: u8,
+    mut alpha: f32,
+    beta: f32,
+) -> f32 {
+    if position.can_win_next() {
+        return win_score(position);
+    }
+    if position.moves() == CELLS {
+        return 0.0; // the board is full, so the game is a draw
+    }
+    let evaluation = evaluate(network, position);
+    if depth == 0 {
+        return evaluation.value;
+    }
+
+    for col in ordered_moves(position, &evaluation.policy) {
+        let mut child = *position;
+        child.play(col);
+        let score = -score(network, &child, depth - 1, -beta, -alpha);
+        if score >= beta {
+            return score;
+        }
+        if score > alpha {
+            alpha = score;
+        }
+    }
+    alpha
+}
+
+/// Returns the columns which can be played, the ones the policy likes best first
+fn ordered_moves(position: &Position, policy: &[f32; BOARD_WIDTH as usize]) -> Vec<u8> {
+    let mut cols: Vec<u8> = (0..BOARD_WIDTH)
+        .filter(|&col| position.can_play(col))
+        .collect();
+    cols.sort_by(|&a, &b| policy[b as usize].total_cmp(&policy[a as usize]));
+    cols
+}
+
+/// Returns the score of winning with the next disk, for the player about to move: more than any
+/// guess of the network, and larger the sooner the win
+fn win_score(position: &Position) -> f32 {
+    1.0 + (CELLS - position.moves() - 1) as f32 / CELLS as f32
+}
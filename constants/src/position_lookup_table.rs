@@ -0,0 +1,216 @@
+//! The position lookup table stores calculated board states for given
+//! positions so that it doesn't need to recalculate them every time.
+//! It has a fixed number of entries, so its memory use is known up front, and
+//! remembers how far ahead each score was searched and whether the score is exact
+//! or only a bound, so scores stay valid when the AI starts looking further ahead.
+
+/*
+ * This file is part of Rust-Connect-Four
+ *
+ * File derived from Connect4 Game Solver <https://github.com/PascalPons/connect4>
+ * Copyright (C) 2017-2019 Pascal Pons <contact@gamesolver.org>
+ *
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{solver::Position, BOARD_HEIGHT, BOARD_WIDTH};
+
+/// Bits a position's key takes up, one more than the cells of the board
+const KEY_BITS: u32 = BOARD_WIDTH as u32 * (BOARD_HEIGHT as u32 + 1);
+const KEY_MASK: u64 = (1 << KEY_BITS) - 1;
+/// Added to scores so they are stored as positive numbers
+const SCORE_OFFSET: i8 = 64;
+const SCORE_SHIFT: u32 = KEY_BITS;
+const BOUND_SHIFT: u32 = SCORE_SHIFT + 7;
+const DEPTH_SHIFT: u32 = BOUND_SHIFT + 2;
+
+/// What a stored score says about the true score of a position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    /// The score is the true score
+    Exact = 1,
+    /// The true score is at least the score
+    Lower = 2,
+    /// The true score is at most the score
+    Upper = 3,
+}
+
+/// A score stored for a position
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Entry {
+    pub score: i8,
+    pub bound: Bound,
+    /// How many moves ahead the score was searched
+    pub depth: u8,
+}
+
+/// Struct to store previously calculated positions
+/// Every entry is packed into a u64: the position's key, then its score, bound and depth.
+/// Entries are kept in pairs, the first kept for whichever position was searched deepest
+/// and the second for whichever other score was stored most recently.
+pub struct PositionLookupTable {
+    table: Vec<[u64; 2]>,
+}
+
+impl PositionLookupTable {
+    /// Create a new PositionLookupTable which holds the specified number of entries
+    pub fn new(size: usize) -> Self {
+        Self {
+            table: vec![[0; 2]; (size / 2).max(1)],
+        }
+    }
+
+    /// Insert the score of a position searched the given number of moves ahead, replacing
+    /// the score stored for another position if there is no room for both.
+    pub fn insert(&mut self, position: &Position, score: i8, bound: Bound, depth: u8) {
+        let key = position.key();
+        let depth = Self::useful_depth(position, depth);
+        let entry = key
+            | ((score.clamp(1 - SCORE_OFFSET, SCORE_OFFSET - 1) + SCORE_OFFSET) as u64)
+                << SCORE_SHIFT
+            | (bound as u64) << BOUND_SHIFT
+            | (depth as u64) << DEPTH_SHIFT;
+        let index = self.index(key);
+        let pair = &mut self.table[index];
+        if pair[0] == 0 || Self::unpack(pair[0]).depth <= depth {
+            // Keep the position it replaces, if it is another one, as the most recent
+            if pair[0] != 0 && pair[0] & KEY_MASK != key {
+                pair[1] = pair[0];
+            }
+            pair[0] = entry;
+        } else {
+            pair[1] = entry;
+        }
+    }
+
+    /// Returns the score stored for a position, or None if the position is not stored
+    /// or its score was not searched at least the given number of moves ahead.
+    pub fn get(&self, position: &Position, depth: u8) -> Option<Entry> {
+        let key = position.key();
+        let depth = Self::useful_depth(position, depth);
+        self.table[self.index(key)]
+            .iter()
+            .filter(|&&entry| entry != 0 && entry & KEY_MASK == key)
+            .map(|&entry| Self::unpack(entry))
+            .find(|entry| entry.depth >= depth)
+    }
+
+    ///// PRIVATE METHODS /////
+
+    /// Searching further ahead than the end of the game gives the same score, so depths are
+    /// capped there, which lets scores searched to the end be used by any deeper search
+    fn useful_depth(position: &Position, depth: u8) -> u8 {
+        depth.min(BOARD_WIDTH * BOARD_HEIGHT - position.moves() as u8 + 1)
+    }
+
+    /// Returns the pair of entries a key is stored in, mixing the key's bits so that
+    /// positions differing only in their last columns spread across the table
+    fn index(&self, key: u64) -> usize {
+        ((key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32) % self.table.len() as u64) as usize
+    }
+
+    fn unpack(entry: u64) -> Entry {
+        Entry {
+            score: ((entry >> SCORE_SHIFT) & 0x7f) as i8 - SCORE_OFFSET,
+            bound: match (entry >> BOUND_SHIFT) & 0x3 {
+                1 => Bound::Exact,
+                2 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            depth: (entry >> DEPTH_SHIFT) as u8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(moves: &[u8]) -> Position {
+        Position::from_moves(moves).unwrap()
+    }
+
+    #[test]
+    fn entries_unpack_as_packed() {
+        let position = position(&[3, 3, 2]);
+        for score in [-63, -26, -1, 0, 1, 26, 63] {
+            for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+                for depth in [1, 7, 40] {
+                    let mut table = PositionLookupTable::new(1024);
+                    table.insert(&position, score, bound, depth);
+                    assert_eq!(
+                        table.get(&position, depth),
+                        Some(Entry {
+                            score,
+                            bound,
+                            depth
+                        })
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scores_are_clamped_to_what_fits() {
+        let mut table = PositionLookupTable::new(1024);
+        let position = position(&[3]);
+        table.insert(&position, i8::MAX, Bound::Exact, 1);
+        assert_eq!(table.get(&position, 1).unwrap().score, SCORE_OFFSET - 1);
+        table.insert(&position, i8::MIN, Bound::Exact, 1);
+        assert_eq!(table.get(&position, 1).unwrap().score, 1 - SCORE_OFFSET);
+    }
+
+    #[test]
+    fn shallower_searches_are_not_used_for_deeper_ones() {
+        let mut table = PositionLookupTable::new(1024);
+        let position = position(&[3, 3]);
+        table.insert(&position, 5, Bound::Exact, 3);
+        assert!(table.get(&position, 3).is_some());
+        assert!(table.get(&position, 2).is_some());
+        assert_eq!(table.get(&position, 4), None);
+        assert_eq!(table.get(&self::position(&[3, 4]), 1), None);
+    }
+
+    #[test]
+    fn deepest_and_most_recent_entries_are_kept() {
+        // A single pair, so every position competes for the same entries
+        let mut table = PositionLookupTable::new(2);
+        let (deep, shallow, recent, deeper) = (
+            position(&[0]),
+            position(&[1]),
+            position(&[2]),
+            position(&[3]),
+        );
+        table.insert(&deep, 1, Bound::Exact, 10);
+        table.insert(&shallow, 2, Bound::Exact, 2);
+        assert!(table.get(&deep, 10).is_some());
+        assert!(table.get(&shallow, 2).is_some());
+
+        // A shallow search replaces the most recent entry, not the deepest one
+        table.insert(&recent, 3, Bound::Exact, 2);
+        assert!(table.get(&deep, 10).is_some());
+        assert_eq!(table.get(&shallow, 1), None);
+        assert!(table.get(&recent, 2).is_some());
+
+        // A deeper search takes the first entry, keeping the one it replaced as the most recent
+        table.insert(&deeper, 4, Bound::Exact, 12);
+        assert!(table.get(&deeper, 12).is_some());
+        assert!(table.get(&deep, 10).is_some());
+        assert_eq!(table.get(&recent, 1), None);
+    }
+}
@@ -0,0 +1,516 @@
+//! solver contains a perfect Connect Four solver, which scores positions by how many moves it takes
+//! to win or lose them with best play, for the perfect AI to play by and for the tools in board to
+//! judge the moves players made
+//!
+//! It searches with alpha-beta negamax over bitboards laid out like the built-in board's,
+//! narrowed with null windows and remembered in a transposition table of a size chosen by whoever
+//! creates the solver, so the browser can keep its memory use down
+
+/*
+ * This file is part of Rust-Connect-Four
+ *
+ * File derived from Connect4 Game Solver <https://github.com/PascalPons/connect4>
+ * Copyright (C) 2017-2019 Pascal Pons <contact@gamesolver.org>
+ *
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    bitboard::{bit, column, winning_cells, BOARD, BOTTOM},
+    BOARD_HEIGHT, BOARD_WIDTH,
+};
+
+const WIDTH: i32 = BOARD_WIDTH as i32;
+const HEIGHT: i32 = BOARD_HEIGHT as i32;
+/// Most moves a game can last
+const CELLS: i32 = WIDTH * HEIGHT;
+/// Lowest score a position can have, for losing to the opponent's first move
+const MIN_SCORE: i32 = -(CELLS / 2) + 3;
+/// Highest score a position can have, for winning with the player's fourth disk
+const MAX_SCORE: i32 = (CELLS + 1) / 2 - 3;
+/// Stored lower bounds are moved up past every stored upper bound, so the two can be told apart
+const LOWER_BOUND_OFFSET: i32 = MAX_SCORE - 2 * MIN_SCORE + 2;
+
+/// Bits a position's key takes up, one more than the cells of the board
+const KEY_BITS: u32 = BOARD_WIDTH as u32 * (BOARD_HEIGHT as u32 + 1);
+const KEY_MASK: u64 = (1 << KEY_BITS) - 1;
+
+/// A position, from the point of view of the player about to move
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Disks of the player about to move
+    current: u64,
+    /// Disks of both players
+    mask: u64,
+    moves: i32,
+}
+
+impl Position {
+    /// Creates a position from the disks of the player about to move and the disks of both players
+    pub fn new(current: u64, mask: u64) -> Self {
+        Self {
+            current,
+            mask,
+            moves: mask.count_ones() as i32,
+        }
+    }
+
+    /// Returns the position after the columns were played in order from an empty board,
+    /// or None if a move was illegal or won the game, as there is nothing left to solve
+    pub fn from_moves(cols: &[u8]) -> Option<Self> {
+        let mut position = Self::default();
+        for &col in cols {
+            if !position.can_play(col) || position.is_winning_move(col) {
+                return None;
+            }
+            position.play(col);
+        }
+        Some(position)
+    }
+
+    /// Returns whether a disk can be dropped into the column
+    pub fn can_play(&self, col: u8) -> bool {
+        col < BOARD_WIDTH && self.mask & bit(BOARD_HEIGHT - 1, col) == 0
+    }
+
+    /// Drops a disk for the player about to move into the column, which must be playable
+    pub fn play(&mut self, col: u8) {
+        self.play_bit((self.mask + bit(0, col)) & column(col));
+    }
+
+    /// Returns whether dropping a disk into the column wins the game for the player about to move
+    pub fn is_winning_move(&self, col: u8) -> bool {
+        self.can_play(col) && self.winning_cells() & self.possible() & column(col) != 0
+    }
+
+    /// Returns whether the player about to move can win with their next disk
+    pub fn can_win_next(&self) -> bool {
+        self.winning_cells() & self.possible() != 0
+    }
+
+    /// Returns how many disks have been played
+    pub fn moves(&self) -> usize {
+        self.moves as usize
+    }
+
+    /// Returns the disks of the player about to move, and the disks of both players
+    pub fn disks(&self) -> (u64, u64) {
+        (self.current, self.mask)
+    }
+
+    /// Uniquely identifies the position
+    pub fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    /// Returns the columns the player about to move can play without letting the opponent win
+    /// on the next move, as bits of the cells they would fill
+    pub fn non_losing_moves(&self) -> u64 {
+        let mut possible = self.possible();
+        let opponent_wins = winning_cells(self.current ^ self.mask, self.mask);
+        let forced = possible & opponent_wins;
+        if forced != 0 {
+            // Two threats cannot both be blocked
+            if forced & (forced - 1) != 0 {
+                return 0;
+            }
+            possible = forced;
+        }
+        // Playing below an opponent's winning cell lets them play it
+        possible & !(opponent_wins >> 1)
+    }
+
+    /// Returns how many winning cells the player about to move would have after playing the column
+    pub fn threats_after(&self, col: u8) -> u32 {
+        self.threats_after_cell((self.mask + bit(0, col)) & column(col))
+    }
+
+    /// Score of winning with the next disk
+    pub fn win_score(&self) -> i32 {
+        (CELLS + 1 - self.moves) / 2
+    }
+
+    fn play_bit(&mut self, cell: u64) {
+        self.current ^= self.mask;
+        self.mask |= cell;
+        self.moves += 1;
+    }
+
+    /// Cells the next disk can go in
+    fn possible(&self) -> u64 {
+        (self.mask + BOTTOM) & BOARD
+    }
+
+    /// Empty cells which would complete four in a row for the player about to move
+    fn winning_cells(&self) -> u64 {
+        winning_cells(self.current, self.mask)
+    }
+
+    /// Counts the cells the player about to move could win with after filling the cell
+    fn threats_after_cell(&self, cell: u64) -> u32 {
+        winning_cells(self.current | cell, self.mask).count_ones()
+    }
+}
+
+/// Columns ordered from the centre out, since central disks are part of more lines
+pub const COLUMN_ORDER: [u8; BOARD_WIDTH as usize] = {
+    let mut order = [0; BOARD_WIDTH as usize];
+    let mut i = 0;
+    while i < WIDTH {
+        order[i as usize] = (WIDTH / 2 + (1 - 2 * (i % 2)) * (i + 1) / 2) as u8;
+        i += 1;
+    }
+    order
+};
+
+/// Solves positions, remembering what it learns between them
+/// Gives up on any request that needs more than its node budget
+pub struct Solver {
+    /// Bounds on the scores of positions, stored in the bits above their keys: upper bounds
+    /// from 1 up, and lower bounds from above MAX_SCORE - MIN_SCORE + 1
+    table: Vec<u64>,
+    nodes: u64,
+    node_budget: u64,
+}
+
+impl Solver {
+    /// Transposition table entries for the tools in board, a prime so positions spread evenly
+    /// (32 MB)
+    pub const LARGE_TABLE: usize = 4_194_301;
+
+    /// Creates a solver which searches at most node_budget positions for each request, with a
+    /// transposition table of table_size entries of 8 bytes each
+    /// A prime table size spreads positions most evenly
+    pub fn new(node_budget: u64, table_size: usize) -> Self {
+        Self {
+            table: vec![0; table_size.max(1)],
+            nodes: 0,
+            node_budget,
+        }
+    }
+
+    /// Returns the score of the position for the player about to move: positive if they win with
+    /// best play, larger the sooner they do, negative if they lose and 0 for a draw
+    /// The score is the number of their disks left unplayed when the game is won
+    /// Returns None if the position could not be solved within the node budget
+    pub fn solve(&mut self, position: &Position) -> Option<i32> {
+        self.nodes = 0;
+        self.search(position)
+    }
+
+    /// Returns the score of playing each column of the position, for the player about to move,
+    /// with None for full columns
+    /// Returns None if the position could not be solved within the node budget
+    pub fn scores(&mut self, position: &Position) -> Option<[Option<i32>; BOARD_WIDTH as usize]> {
+        let scores = self.partial_scores(position);
+        (0..BOARD_WIDTH)
+            .all(|col| !position.can_play(col) || scores[col as usize].is_some())
+            .then_some(scores)
+    }
+
+    /// Returns the score of playing each column of the position, for the player about to move,
+    /// with None for full columns and for columns not solved before the node budget ran out
+    /// Columns are solved from the centre out, sharing the node budget
+    fn partial_scores(&mut self, position: &Position) -> [Option<i32>; BOARD_WIDTH as usize] {
+        self.nodes = 0;
+        let mut scores = [None; BOARD_WIDTH as usize];
+        for col in COLUMN_ORDER {
+            if !position.can_play(col) {
+                continue;
+            }
+            scores[col as usize] = if position.is_winning_move(col) {
+                Some(position.win_score())
+            } else {
+                let mut next = *position;
+                next.play(col);
+                self.search(&next).map(|score| -score)
+            };
+        }
+        scores
+    }
+
+    /// Returns the score of the position for the player about to move (see solve), and the
+    /// columns which reach it, from the centre out
+    /// Each column is only proven to be no worse than the score rather than solved, which takes
+    /// far less searching than scores
+    /// Returns None if the position could not be solved within the node budget
+    pub fn best_moves(&mut self, position: &Position) -> Option<(i32, Vec<u8>)> {
+        let score = self.solve(position)?;
+        let mut best = Vec::new();
+        for col in COLUMN_ORDER {
+            if !position.can_play(col) {
+                continue;
+            }
+            let reaches_score = if position.is_winning_move(col) {
+                position.win_score() == score
+            } else {
+                let mut next = *position;
+                next.play(col);
+                !self.is_above(&next, -score)?
+            };
+            if reaches_score {
+                best.push(col);
+            }
+        }
+        Some((score, best))
+    }
+
+    /// Returns whether the score of the position is above the guess, with a single null window
+    /// search, or None if the node budget ran out
+    fn is_above(&mut self, position: &Position, guess: i32) -> Option<bool> {
+        if position.can_win_next() {
+            return Some(position.win_score() > guess);
+        }
+        let score = self.negamax(position, guess, guess + 1);
+        (self.nodes <= self.node_budget).then_some(score > guess)
+    }
+
+    /// Narrows the score down with null window searches, each of which only finds out whether
+    /// the score is above a guess
+    fn search(&mut self, position: &Position) -> Option<i32> {
+        if position.can_win_next() {
+            return Some(position.win_score());
+        }
+        let mut min = -(CELLS - position.moves) / 2;
+        let mut max = (CELLS + 1 - position.moves) / 2;
+        while min < max {
+            // Guess close to 0 first, as most positions are decided late
+            let mut guess = min + (max - min) / 2;
+            if guess <= 0 && min / 2 < guess {
+                guess = min / 2;
+            } else if guess >= 0 && max / 2 > guess {
+                guess = max / 2;
+            }
+            let score = self.negamax(position, guess, guess + 1);
+            if self.nodes > self.node_budget {
+                return None;
+            }
+            if score <= guess {
+                max = score;
+            } else {
+                min = score;
+            }
+        }
+        Some(min)
+    }
+
+    /// Returns the score of the position if it is between alpha and beta, or a bound beyond
+    /// whichever it is outside of
+    /// The player about to move must not be able to win with their next disk
+    fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.nodes > self.node_budget {
+            return alpha;
+        }
+
+        let next = position.non_losing_moves();
+        if next == 0 {
+            return -(CELLS - position.moves) / 2;
+        }
+        if position.moves >= CELLS - 2 {
+            return 0;
+        }
+
+        // The opponent cannot win on their next move, so the score is at least this
+        let min = -(CELLS - 2 - position.moves) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        // Nor can the player win on this move, so the score is at most this
+        let mut max = (CELLS - 1 - position.moves) / 2;
+        let key = position.key();
+        let index = (key % self.table.len() as u64) as usize;
+        let entry = self.table[index];
+        if entry != 0 && entry & KEY_MASK == key {
+            let value = (entry >> KEY_BITS) as i32;
+            if value > MAX_SCORE - MIN_SCORE + 1 {
+                let min = value - LOWER_BOUND_OFFSET;
+                if alpha < min {
+                    alpha = min;
+                    if alpha >= beta {
+                        return alpha;
+                    }
+                }
+            } else {
+                max = value + MIN_SCORE - 1;
+            }
+        }
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        // Try the moves which leave the most threats first, then the central ones
+        let mut moves = [(0, 0); BOARD_WIDTH as usize];
+        let mut num_moves = 0;
+        for &col in COLUMN_ORDER.iter().rev() {
+            let cell = next & column(col);
+            if cell != 0 {
+                let threats = position.threats_after_cell(cell);
+                let mut index = num_moves;
+                while index > 0 && moves[index - 1].1 <= threats {
+                    moves[index] = moves[index - 1];
+                    index -= 1;
+                }
+                moves[index] = (cell, threats);
+                num_moves += 1;
+            }
+        }
+        for &(cell, _) in &moves[..num_moves] {
+            let mut child = *position;
+            child.play_bit(cell);
+            let score = -self.negamax(&child, -beta, -alpha);
+            if self.nodes > self.node_budget {
+                // Scores from a search cut short are not to be trusted or remembered
+                return alpha;
+            }
+            if score >= beta {
+                // The score is only known to be at least this, as the rest of the moves were skipped
+                self.table[index] = key | ((score + LOWER_BOUND_OFFSET) as u64) << KEY_BITS;
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        self.table[index] = key | ((alpha - MIN_SCORE + 1) as u64) << KEY_BITS;
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scores the position by trying every move to the end of the game
+    fn minimax(position: &Position) -> i32 {
+        if position.can_win_next() {
+            return position.win_score();
+        }
+        if position.moves() == CELLS as usize {
+            return 0;
+        }
+        (0..BOARD_WIDTH)
+            .filter(|&col| position.can_play(col))
+            .map(|col| {
+                let mut child = *position;
+                child.play(col);
+                -minimax(&child)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Plays pseudo-random moves until the position has the given number of disks, or None if
+    /// the game was won first
+    fn random_position(seed: &mut u64, disks: usize) -> Option<Position> {
+        let mut position = Position::default();
+        while position.moves() < disks {
+            let cols: Vec<u8> = (0..BOARD_WIDTH)
+                .filter(|&col| position.can_play(col) && !position.is_winning_move(col))
+                .collect();
+            if cols.is_empty() {
+                return None;
+            }
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            position.play(cols[(*seed % cols.len() as u64) as usize]);
+        }
+        Some(position)
+    }
+
+    #[test]
+    fn winning_at_once_scores_the_disks_left() {
+        // The first player has three disks in the first column
+        let position = Position::from_moves(&[0, 1, 0, 1, 0, 1]).unwrap();
+        let mut solver = Solver::new(u64::MAX, 1024);
+        assert_eq!(solver.solve(&position), Some(18));
+    }
+
+    #[test]
+    fn two_threats_lose() {
+        // The first player has three disks along the bottom with both ends open
+        let position = Position::from_moves(&[2, 2, 3, 3, 4]).unwrap();
+        let mut solver = Solver::new(u64::MAX, 1024);
+        assert_eq!(solver.solve(&position), Some(-18));
+        // Every move but blocking an end loses at once, and blocking one end loses to the other
+        let scores = solver.scores(&position).unwrap();
+        assert!(scores.iter().flatten().all(|&score| score == -18));
+    }
+
+    #[test]
+    fn late_positions_match_a_full_search() {
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        let mut solver = Solver::new(u64::MAX, 1 << 16);
+        let mut checked = 0;
+        while checked < 20 {
+            let Some(position) = random_position(&mut seed, CELLS as usize - 12) else {
+                continue;
+            };
+            assert_eq!(solver.solve(&position), Some(minimax(&position)));
+            let scores = solver.scores(&position).unwrap();
+            for col in 0..BOARD_WIDTH {
+                let expected = position.can_play(col).then(|| {
+                    let mut child = position;
+                    child.play(col);
+                    if position.is_winning_move(col) {
+                        position.win_score()
+                    } else {
+                        -minimax(&child)
+                    }
+                });
+                assert_eq!(scores[col as usize], expected);
+            }
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn best_moves_are_the_best_scoring_columns() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        let mut solver = Solver::new(u64::MAX, 1 << 16);
+        let mut checked = 0;
+        while checked < 20 {
+            let Some(position) = random_position(&mut seed, CELLS as usize - 16) else {
+                continue;
+            };
+            let scores = Solver::new(u64::MAX, 1 << 16).scores(&position).unwrap();
+            let best = scores.iter().flatten().copied().max().unwrap();
+            let best_cols: Vec<u8> = COLUMN_ORDER
+                .into_iter()
+                .filter(|&col| scores[col as usize] == Some(best))
+                .collect();
+            assert_eq!(solver.best_moves(&position), Some((best, best_cols)));
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn gives_up_past_the_node_budget() {
+        let mut solver = Solver::new(100, 1024);
+        assert_eq!(solver.solve(&Position::default()), None);
+        assert_eq!(solver.scores(&Position::default()), None);
+        assert_eq!(solver.best_moves(&Position::default()), None);
+    }
+}
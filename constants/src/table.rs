@@ -0,0 +1,151 @@
+//! table holds a value table learned by reinforcement learning: how good each position it has
+//! seen is for the player about to move, learned by board-tabular from self-play games alone
+//!
+//! Unlike the opening book, a table can be for a smaller board than the app's, so it records its
+//! own size, and positions are keyed for that size. A position and its mirror image are worth the
+//! same, so only one of them is stored
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Starts every value table file
+const MAGIC: &[u8; 4] = b"C4VT";
+const VERSION: u8 = 2;
+/// Magic, version, board width and height, and number of entries
+const HEADER_SIZE: usize = 11;
+/// Each entry is a position's key followed by its value as a float, as the values of good moves
+/// often differ by less than a rounded value could tell apart
+const ENTRY_SIZE: usize = 12;
+const KEY_SIZE: usize = 8;
+
+/// Values of positions on a board of some size, for the player about to move: from -1 if they
+/// are sure to lose, to 1 if they are sure to win
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueTable {
+    width: u8,
+    height: u8,
+    /// Values by key, sorted by key
+    entries: Vec<(u64, f32)>,
+}
+
+impl ValueTable {
+    /// Creates a table for a board of the given size from the keys (see key) and values of
+    /// positions
+    pub fn new(width: u8, height: u8, entries: impl IntoIterator<Item = (u64, f32)>) -> Self {
+        let mut entries: Vec<(u64, f32)> = entries
+            .into_iter()
+            .map(|(key, value)| (key, value.clamp(-1.0, 1.0)))
+            .collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        entries.dedup_by_key(|(key, _)| *key);
+        Self {
+            width,
+            height,
+            entries,
+        }
+    }
+
+    /// Returns the width and height of the board the table is for
+    pub fn size(&self) -> (u8, u8) {
+        (self.width, self.height)
+    }
+
+    /// Returns how many positions are in the table
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the value of the position, given the disks of the player about to move and the
+    /// disks of both players, or None if it is not in the table
+    pub fn get(&self, current: u64, mask: u64) -> Option<f32> {
+        let key = key(current, mask, self.width, self.height);
+        self.entries
+            .binary_search_by_key(&key, |(key, _)| *key)
+            .ok()
+            .map(|i| self.entries[i].1)
+    }
+
+    /// Turns the table into the bytes of a value table file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[VERSION, self.width, self.height]);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for &(key, value) in &self.entries {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Turns the bytes of a value table file made by to_bytes back into the table
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err("not a value table".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported value table version {}", bytes[4]));
+        }
+        let (width, height) = (bytes[5], bytes[6]);
+        let len = u32::from_le_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]) as usize;
+        let body = &bytes[HEADER_SIZE..];
+        if body.len() != len * ENTRY_SIZE {
+            return Err(format!(
+                "value table should have {} entries, but has {} bytes of them",
+                len,
+                body.len()
+            ));
+        }
+        let mut entries: Vec<(u64, f32)> = body
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let mut key = [0; KEY_SIZE];
+                key.copy_from_slice(&entry[..KEY_SIZE]);
+                let mut value = [0; ENTRY_SIZE - KEY_SIZE];
+                value.copy_from_slice(&entry[KEY_SIZE..]);
+                (u64::from_le_bytes(key), f32::from_le_bytes(value))
+            })
+            .collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        Ok(Self {
+            width,
+            height,
+            entries,
+        })
+    }
+}
+
+/// Returns the key a position on a board of the given size is stored under, given the disks of
+/// the player about to move and the disks of both players: the smaller of its key and its mirror
+/// image's
+pub fn key(current: u64, mask: u64, width: u8, height: u8) -> u64 {
+    let key = current + mask;
+    // Each column of the key only takes up its own bits, so the columns can be swapped around
+    let column_bits = height as u32 + 1;
+    let mut mirrored = 0;
+    for col in 0..width as u32 {
+        let column = (key >> (col * column_bits)) & ((1 << column_bits) - 1);
+        mirrored |= column << ((width as u32 - 1 - col) * column_bits);
+    }
+    key.min(mirrored)
+}
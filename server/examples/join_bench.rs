@@ -0,0 +1,163 @@
+//! join_bench measures how quickly a running server can place clients into lobbies
+//!
+//! Usage: `cargo run --release --example join_bench -- [address] [clients] [concurrency]`
+//! Start the server separately first. Two scenarios are run:
+//!     paired: every two clients join their own lobby (lobby creation heavy)
+//!     shared: every client spectates the same lobby (lobby membership heavy)
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use constants::ConnectionProtocol;
+use futures::{SinkExt, StreamExt};
+use tokio::{net::TcpStream, sync::Semaphore};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::Message::{Binary, Text},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+type BenchClient = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Connects a single client and joins the given lobby
+/// Returns the client once the server has assigned it a role
+async fn connect(address: &str, lobby: String) -> Result<BenchClient, String> {
+    let (mut client, _) = connect_async(format!("ws://{}", address))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match client.next().await {
+        Some(Ok(Binary(bytes))) if bytes.first() == Some(&ConnectionProtocol::CONNECTION_SUCCESS) => {}
+        _ => return Err("server did not confirm the connection".to_string()),
+    }
+    client.send(Text(lobby)).await.map_err(|e| e.to_string())?;
+
+    // The first single byte message sent by the lobby is the role of the client
+    loop {
+        match client.next().await {
+            Some(Ok(Binary(bytes))) if bytes.len() == 1 => match bytes[0] {
+                ConnectionProtocol::IS_PLAYER_1
+                | ConnectionProtocol::IS_PLAYER_2
+                | ConnectionProtocol::IS_SPECTATOR => return Ok(client),
+                _ => continue,
+            },
+            Some(Ok(_)) => continue,
+            _ => return Err("connection closed before joining".to_string()),
+        }
+    }
+}
+
+/// Joins the given lobby, then leaves it
+/// Returns the time taken from opening the connection to receiving a role
+async fn join(address: String, lobby: String) -> Result<Duration, String> {
+    let start = Instant::now();
+    let mut client = connect(&address, lobby).await?;
+    let elapsed = start.elapsed();
+    client
+        .send(Binary(vec![ConnectionProtocol::KILL_CONNECTION]))
+        .await
+        .unwrap_or_default();
+    Ok(elapsed)
+}
+
+/// Runs one scenario, where lobby_for maps the index of a client to the lobby it should join
+async fn run_scenario(
+    name: &str,
+    address: &str,
+    clients: usize,
+    concurrency: usize,
+    lobby_for: fn(usize) -> String,
+) {
+    let limit = Arc::new(Semaphore::new(concurrency));
+    let start = Instant::now();
+
+    let mut handles = Vec::with_capacity(clients);
+    for i in 0..clients {
+        let permit = Arc::clone(&limit).acquire_owned().await.unwrap();
+        let address = address.to_string();
+        handles.push(tokio::spawn(async move {
+            let res = join(address, lobby_for(i)).await;
+            drop(permit);
+            res
+        }));
+    }
+
+    let mut latencies = Vec::with_capacity(clients);
+    let mut failures = 0;
+    for handle in handles {
+        match handle.await {
+            Ok(Ok(latency)) => latencies.push(latency),
+            _ => failures += 1,
+        }
+    }
+    let elapsed = start.elapsed();
+
+    latencies.sort();
+    let percentile = |p: usize| {
+        latencies
+            .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    println!(
+        "{:>7}: {} joins in {:.2?} ({:.0} joins/s), p50 {:.2?}, p99 {:.2?}, {} failed",
+        name,
+        latencies.len(),
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        percentile(50),
+        percentile(99),
+        failures
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8081".to_string());
+    let clients = args.next().and_then(|s| s.parse().ok()).unwrap_or(4000);
+    let concurrency = args.next().and_then(|s| s.parse().ok()).unwrap_or(128);
+
+    println!(
+        "Joining {} clients to {} with {} in flight.",
+        clients, address, concurrency
+    );
+    run_scenario("paired", &address, clients, concurrency, |i| {
+        format!("bench-{}", i / 2)
+    })
+    .await;
+    // Keep both players connected, otherwise the shared lobby closes as soon as one leaves
+    let players = (
+        connect(&address, "bench-shared".to_string()).await,
+        connect(&address, "bench-shared".to_string()).await,
+    );
+    if let (Ok(_), Ok(_)) = &players {
+        run_scenario("shared", &address, clients, concurrency, |_| {
+            "bench-shared".to_string()
+        })
+        .await;
+    } else {
+        println!("Failed to seat the players of the shared lobby.");
+    }
+}
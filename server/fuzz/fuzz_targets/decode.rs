@@ -0,0 +1,70 @@
+//! decode feeds arbitrary bytes, as a client could send or a broken server could, to every
+//! ConnectionProtocol decoder, checking none of them panic and that whatever decodes encodes again
+//! into bytes which decode the same
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+#![no_main]
+
+use constants::{ConnectionProtocol, SequencedUpdate};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    ConnectionProtocol::is_undo_move(&bytes.to_vec());
+
+    if let Ok(update) = ConnectionProtocol::decode_message(bytes.to_vec()) {
+        let encoded = ConnectionProtocol::encode_message(update.clone());
+        assert_eq!(encoded.len(), ConnectionProtocol::MESSAGE_SIZE);
+        assert_eq!(ConnectionProtocol::decode_message(encoded), Ok(update.clone()));
+        let undo = ConnectionProtocol::encode_undo_message(update.clone());
+        assert!(ConnectionProtocol::is_undo_move(&undo));
+        assert_eq!(ConnectionProtocol::decode_message(undo), Ok(update));
+    }
+
+    match ConnectionProtocol::decode_sequenced(bytes) {
+        Some(SequencedUpdate::Move { seq, delta }) => {
+            assert_eq!(ConnectionProtocol::encode_move(seq, delta), bytes);
+        }
+        Some(SequencedUpdate::Snapshot { seq, update }) => {
+            let board = ConnectionProtocol::encode_message(update.clone());
+            let encoded = ConnectionProtocol::encode_snapshot(seq, &board);
+            assert_eq!(encoded.len(), ConnectionProtocol::SNAPSHOT_MESSAGE_SIZE);
+            match ConnectionProtocol::decode_sequenced(&encoded) {
+                Some(SequencedUpdate::Snapshot {
+                    seq: decoded_seq,
+                    update: decoded,
+                }) => assert!(decoded_seq == seq && decoded == update),
+                other => panic!("snapshot decoded as {:?}", other),
+            }
+        }
+        None => {}
+    }
+
+    if let Some(token) = ConnectionProtocol::decode_seat_token(bytes) {
+        assert_eq!(ConnectionProtocol::encode_seat_token(token), bytes);
+    }
+
+    if let Ok(request) = std::str::from_utf8(bytes) {
+        let (lobby, token) = ConnectionProtocol::decode_join_request(request);
+        let encoded = ConnectionProtocol::encode_join_request(&lobby, token);
+        assert_eq!(ConnectionProtocol::decode_join_request(&encoded), (lobby, token));
+    }
+});
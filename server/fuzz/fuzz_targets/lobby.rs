@@ -0,0 +1,179 @@
+//! lobby plays arbitrary messages from both players into a lobby, the way their listeners would,
+//! then checks every board state the lobby sent out:
+//!     turns alternate, and an undo only comes from the player who moved last
+//!     with moves checked, every board is legal and is the board the recorded moves make
+//!     the messages each client is sent rebuild the same boards on the client
+//!
+//! Without moves being checked the server trusts the board states players send, so they are not checked for legality
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use board::{Bitboard, BoardEngine};
+use constants::{ConnectionProtocol, GameUpdate, SequencedUpdate, BOARD_HEIGHT, BOARD_WIDTH};
+use libfuzzer_sys::fuzz_target;
+use server::{
+    boards::LobbyBoard,
+    lobby::{
+        lobby::run_lobby,
+        updates::UpdateEncoder,
+        util::{
+            LobbyState,
+            Message::{BoardState, SpecialMessage},
+            MessageFromClient, PlayerRequest,
+        },
+    },
+};
+use tokio::{
+    runtime,
+    sync::{broadcast, mpsc, watch},
+};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    validate_moves: bool,
+    /// Messages sent by player 1 (false) or player 2 (true)
+    messages: Vec<(bool, Vec<u8>)>,
+}
+
+fuzz_target!(|input: Input| {
+    let runtime = runtime::Builder::new_current_thread().build().unwrap();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let (game_update_sender, mut game_updates) = broadcast::channel(input.messages.len() + 1);
+    let (state_sender, state) = watch::channel(LobbyState::new("fuzz".to_string()));
+    let (_player_addresses, player_addresses) = watch::channel([None; 2]);
+    let board = input
+        .validate_moves
+        .then(|| LobbyBoard::InProcess(Box::new(Bitboard::default())));
+
+    // Both players have joined, as the client handler tells the lobby
+    sender
+        .send(BoardState(MessageFromClient {
+            binary: vec![ConnectionProtocol::SECOND_PLAYER_CONNECTED],
+            player_num: 2,
+        }))
+        .unwrap();
+    for (is_player_2, binary) in input.messages {
+        let player_num = if is_player_2 { 2 } else { 1 };
+        match PlayerRequest::parse(binary, player_num, input.validate_moves) {
+            PlayerRequest::Lobby(msg) => sender.send(msg).unwrap(),
+            PlayerRequest::Resync => {}
+            // The player is disconnected, ending the game
+            PlayerRequest::Unrecognized => {
+                sender
+                    .send(SpecialMessage(ConnectionProtocol::KILL_CONNECTION))
+                    .unwrap();
+                break;
+            }
+        }
+    }
+    drop(sender);
+    runtime.block_on(run_lobby(
+        receiver,
+        game_update_sender,
+        state_sender,
+        board,
+        None,
+        player_addresses,
+        || {},
+    ));
+
+    let mut boards = Vec::new();
+    while let Ok(update) = game_updates.try_recv() {
+        if update.binary.len() == ConnectionProtocol::MESSAGE_SIZE {
+            boards.push(update);
+        }
+    }
+    let state = state.borrow().clone();
+
+    // Turns alternate, except that an undo gives the turn back to the player who moved last
+    let mut is_p1_turn = true;
+    for update in &boards {
+        let is_undo = ConnectionProtocol::is_undo_move(&update.binary);
+        assert_eq!(update.player_num == 1, is_p1_turn != is_undo, "played out of turn");
+        is_p1_turn = !is_p1_turn;
+    }
+    assert_eq!(state.is_p1_turn, is_p1_turn);
+    assert_eq!(
+        state.board,
+        boards.last().map_or(state.board.clone(), |update| update.binary.clone())
+    );
+
+    if input.validate_moves {
+        assert_eq!(boards.len(), state.moves.len());
+        let mut board = Bitboard::default();
+        let mut game_won = false;
+        for (i, (update, &col)) in boards.iter().zip(&state.moves).enumerate() {
+            assert!(!game_won && !board.is_full(), "moved after the game was over");
+            let player_num = i as u8 % 2 + 1;
+            assert_eq!(update.player_num, player_num);
+            game_won = board.drop_disk(player_num, col).expect("illegal move accepted");
+            assert_eq!(update.binary, board.to_game_update_binary(player_num == 2, game_won));
+            assert_legal(&ConnectionProtocol::decode_message(update.binary.clone()).unwrap());
+        }
+    }
+
+    // A client sent every board state rebuilds each of them from the moves and snapshots it is sent
+    let mut updates = UpdateEncoder::default();
+    let mut client = GameUpdate::default();
+    for (seq, update) in (1u16..).zip(&boards) {
+        let expected = ConnectionProtocol::decode_message(update.binary.clone()).unwrap();
+        match ConnectionProtocol::decode_sequenced(&updates.encode(update.binary.clone())) {
+            Some(SequencedUpdate::Move { seq: sent, delta }) => {
+                assert_eq!(sent, seq);
+                client.apply(delta).expect("move sent which cannot be played");
+            }
+            Some(SequencedUpdate::Snapshot { seq: sent, update }) => {
+                assert_eq!(sent, seq);
+                client = update;
+            }
+            None => panic!("sent an update which does not decode"),
+        }
+        assert_eq!(client, expected);
+    }
+    if let Some(last) = boards.last() {
+        match ConnectionProtocol::decode_sequenced(&updates.resync()) {
+            Some(SequencedUpdate::Snapshot { update, .. }) => {
+                assert_eq!(Ok(update), ConnectionProtocol::decode_message(last.binary.clone()));
+            }
+            other => panic!("resync sent as {:?}", other),
+        }
+    }
+});
+
+/// Checks every disk rests on another or the bottom, the player about to move has at most as many
+/// disks as the other player, and no bits are set outside the board
+fn assert_legal(update: &GameUpdate) {
+    let column = (1 << BOARD_HEIGHT) - 1;
+    let mut board = 0;
+    for col in 0..BOARD_WIDTH {
+        let disks = (update.mask >> (col * (BOARD_HEIGHT + 1))) & column;
+        assert_eq!(disks & (disks + 1), 0, "floating disk in column {}", col);
+        board |= column << (col * (BOARD_HEIGHT + 1));
+    }
+    assert_eq!(update.mask & !board, 0, "disk outside the board");
+    assert_eq!(update.position & !update.mask, 0, "disk in position but not mask");
+    let current = update.position.count_ones();
+    let other = update.mask.count_ones() - current;
+    assert!(current == other || current + 1 == other, "players have {} and {} disks", current, other);
+    assert_eq!(update.is_p1_turn, current == other);
+}
@@ -0,0 +1,92 @@
+//! round_trip builds boards from arbitrary moves and undos, checking each one encodes and decodes
+//! unchanged as a board state, an undo, a snapshot and a move, and that the move found between
+//! two boards changes the first into the second
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use constants::{ConnectionProtocol, GameUpdate, MoveDelta, SequencedUpdate};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Columns to play, or to take the top disk back from
+    deltas: Vec<(u8, bool)>,
+    seq: u16,
+    seat_token: u64,
+    lobby: String,
+}
+
+fuzz_target!(|input: Input| {
+    let mut board = GameUpdate::default();
+    let mut seq = input.seq;
+    for (col, undo) in input.deltas {
+        let delta = MoveDelta {
+            col: col % (constants::BOARD_WIDTH + 1),
+            undo,
+            game_won: col & 0x80 != 0,
+        };
+        let mut next = board.clone();
+        if next.apply(delta).is_err() {
+            assert_eq!(next, board, "failed move changed the board");
+            continue;
+        }
+        assert_eq!(board.delta_to(&next), Some(delta));
+
+        let encoded = ConnectionProtocol::encode_message(next.clone());
+        assert_eq!(ConnectionProtocol::decode_message(encoded.clone()), Ok(next.clone()));
+        let undo = ConnectionProtocol::encode_undo_message(next.clone());
+        assert!(ConnectionProtocol::is_undo_move(&undo));
+        assert_eq!(ConnectionProtocol::decode_message(undo), Ok(next.clone()));
+        assert!(!ConnectionProtocol::is_undo_move(&encoded));
+
+        seq = seq.wrapping_add(1);
+        match ConnectionProtocol::decode_sequenced(&ConnectionProtocol::encode_move(seq, delta)) {
+            Some(SequencedUpdate::Move {
+                seq: decoded_seq,
+                delta: decoded,
+            }) => assert!(decoded_seq == seq && decoded == delta),
+            other => panic!("move decoded as {:?}", other),
+        }
+        match ConnectionProtocol::decode_sequenced(&ConnectionProtocol::encode_snapshot(seq, &encoded)) {
+            Some(SequencedUpdate::Snapshot {
+                seq: decoded_seq,
+                update,
+            }) => assert!(decoded_seq == seq && update == next),
+            other => panic!("snapshot decoded as {:?}", other),
+        }
+        board = next;
+    }
+
+    let token = ConnectionProtocol::encode_seat_token(input.seat_token);
+    assert_eq!(ConnectionProtocol::decode_seat_token(&token), Some(input.seat_token));
+    if ConnectionProtocol::is_valid_lobby_name(&input.lobby) {
+        for seat_token in [None, Some(input.seat_token)] {
+            let request = ConnectionProtocol::encode_join_request(&input.lobby, seat_token);
+            assert_eq!(
+                ConnectionProtocol::decode_join_request(&request),
+                (input.lobby.clone(), seat_token)
+            );
+        }
+    }
+});
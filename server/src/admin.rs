@@ -0,0 +1,385 @@
+//! admin contains a small HTTP server for operators to inspect and manage the lobbies,
+//! and the blocklist of addresses which are temporarily refused connections
+//!
+//! Every request must carry the admin token as "Authorization: Bearer <token>"
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    http::{self, Request},
+    lobby::{lobby::LobbyHandle, registry::LobbyRegistry, util::LobbyState},
+};
+
+use board::analysis::{self, Settings, Tally};
+use constants::{solver::Solver, ConnectionProtocol, BOARD_HEIGHT, BOARD_WIDTH};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task,
+};
+use tracing::{info, warn};
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Most positions the solver may search to judge each move of a live game, kept low so
+/// an analysis of a game in progress answers quickly
+const LIVE_NODE_BUDGET: u64 = 2_000_000;
+
+/// Addresses which are temporarily refused connections, cheap to clone and share
+/// The lock is only ever held briefly, and never across an await
+#[derive(Clone, Default)]
+pub struct Blocklist(Arc<Mutex<HashMap<IpAddr, Instant>>>);
+
+impl Blocklist {
+    /// Refuses connections from the address until the duration has passed
+    /// Returns false, blocking nothing, if the duration is too long for the clock to reach
+    pub fn block(&self, address: IpAddr, duration: Duration) -> bool {
+        let Some(until) = Instant::now().checked_add(duration) else {
+            return false;
+        };
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(address, until);
+        true
+    }
+
+    /// Accepts connections from the address again
+    /// Returns whether the address was blocked
+    pub fn unblock(&self, address: IpAddr) -> bool {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&address)
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// Returns whether connections from the address are currently refused
+    pub fn is_blocked(&self, address: IpAddr) -> bool {
+        let mut blocked = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match blocked.get(&address) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                blocked.remove(&address);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Returns every blocked address along with how long it remains blocked for
+    pub fn list(&self) -> Vec<(IpAddr, Duration)> {
+        let mut blocked = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        blocked.retain(|_, until| *until > now);
+        blocked
+            .iter()
+            .map(|(address, until)| (*address, *until - now))
+            .collect()
+    }
+}
+
+/// Body of a request to block an address
+#[derive(Deserialize)]
+struct BlockRequest {
+    address: IpAddr,
+    seconds: u64,
+}
+
+/// serve answers authenticated HTTP requests for:
+///     GET /lobbies: every lobby, with its board, players and spectators
+///     GET /lobbies/{id}/analysis: each player's moves so far compared with a perfect solver's
+///     POST /lobbies/{id}/close: ends the lobby
+///     POST /lobbies/{id}/clients/{client}/kick: disconnects a client, ending the game if they are a player
+///     POST /notice: sends the plain text body as a notice to every client in a lobby
+///     GET /blocks: every blocked address
+///     POST /blocks: blocks {"address": ..., "seconds": ...}, disconnecting its clients
+///     DELETE /blocks/{address}: unblocks the address
+///
+/// Async to be run as a single task for the lifetime of the server
+pub async fn serve(listener: TcpListener, registry: LobbyRegistry, blocklist: Blocklist, token: String) {
+    let token = Arc::new(token);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Failed to accept admin request.");
+                continue;
+            }
+        };
+        let (registry, blocklist, token) = (registry.clone(), blocklist.clone(), token.clone());
+        tokio::spawn(async move {
+            handle_request(stream, registry, blocklist, &token).await;
+        });
+    }
+}
+
+/// Reads a single HTTP request and writes the JSON response, then closes the connection
+async fn handle_request(
+    mut stream: TcpStream,
+    registry: LobbyRegistry,
+    blocklist: Blocklist,
+    token: &str,
+) {
+    let request = match http::read_request(&mut stream).await {
+        Some(request) => request,
+        None => return,
+    };
+
+    let (status, body) = if is_authorized(&request, token) {
+        respond(&request, &registry, &blocklist).await
+    } else {
+        warn!(method = request.method, path = request.path, "Rejected unauthorized admin request.");
+        ("401 Unauthorized", error("missing or incorrect admin token"))
+    };
+    http::write_response(&mut stream, status, "application/json", &body.to_string()).await;
+}
+
+/// Returns whether the request carries the admin token
+/// Compares every byte so the time taken does not reveal how much of the token was correct
+fn is_authorized(request: &Request, token: &str) -> bool {
+    let given = match request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(given) => given.as_bytes(),
+        None => return false,
+    };
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Carries out the request, returning the status and body of the response
+async fn respond(
+    request: &Request,
+    registry: &LobbyRegistry,
+    blocklist: &Blocklist,
+) -> (&'static str, Value) {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["lobbies"]) => {
+            let mut lobbies = Vec::new();
+            for (id, handle) in registry.lobbies().await {
+                lobbies.push(describe_lobby(id, &handle).await);
+            }
+            ("200 OK", Value::Array(lobbies))
+        }
+        ("GET", ["lobbies", id, "analysis"]) => match find_lobby(registry, id).await {
+            Some((id, handle)) => ("200 OK", analyze_lobby(id, &handle).await),
+            None => ("404 Not Found", error("no such lobby")),
+        },
+        ("POST", ["lobbies", id, "close"]) => match find_lobby(registry, id).await {
+            Some((id, handle)) => {
+                info!(id, "Closing lobby for admin.");
+                handle.close();
+                ("200 OK", json!({ "closed": true }))
+            }
+            None => ("404 Not Found", error("no such lobby")),
+        },
+        ("POST", ["lobbies", id, "clients", client, "kick"]) => {
+            let (id, handle, client) =
+                match (find_lobby(registry, id).await, client.parse::<u64>()) {
+                    (Some((id, handle)), Ok(client)) => (id, handle, client),
+                    _ => return ("404 Not Found", error("no such lobby")),
+                };
+            if handle.kick(client).await {
+                info!(id, client, "Kicked client for admin.");
+                ("200 OK", json!({ "kicked": true }))
+            } else {
+                ("404 Not Found", error("no such client"))
+            }
+        }
+        ("POST", ["notice"]) => {
+            let notice = String::from_utf8_lossy(&request.body).trim().to_string();
+            if notice.is_empty() {
+                return ("400 Bad Request", error("notice is empty"));
+            }
+            info!(notice, "Sending notice for admin.");
+            registry.notify_all(notice);
+            ("200 OK", json!({ "sent": true }))
+        }
+        ("GET", ["blocks"]) => {
+            let blocks = blocklist
+                .list()
+                .into_iter()
+                .map(|(address, remaining)| {
+                    json!({ "address": address, "seconds": remaining.as_secs() })
+                })
+                .collect();
+            ("200 OK", Value::Array(blocks))
+        }
+        ("POST", ["blocks"]) => {
+            let block = match serde_json::from_slice::<BlockRequest>(&request.body) {
+                Ok(block) => block,
+                Err(e) => return ("400 Bad Request", error(&e.to_string())),
+            };
+            if !blocklist.block(block.address, Duration::from_secs(block.seconds)) {
+                return ("400 Bad Request", error("block is too long"));
+            }
+            // Disconnect the clients already connected from the address
+            let mut kicked = 0;
+            for (_, handle) in registry.lobbies().await {
+                for client in handle.clients().await {
+                    if client.peer.ip() == block.address && handle.kick(client.id).await {
+                        kicked += 1;
+                    }
+                }
+            }
+            info!(address = %block.address, seconds = block.seconds, kicked, "Blocked address for admin.");
+            ("200 OK", json!({ "blocked": true, "kicked": kicked }))
+        }
+        ("DELETE", ["blocks", address]) => match address.parse::<IpAddr>() {
+            Ok(address) if blocklist.unblock(address) => {
+                info!(%address, "Unblocked address for admin.");
+                ("200 OK", json!({ "unblocked": true }))
+            }
+            _ => ("404 Not Found", error("address is not blocked")),
+        },
+        _ => ("404 Not Found", error("not found")),
+    }
+}
+
+/// Returns the lobby with the given id along with the parsed id, if it has not yet ended
+async fn find_lobby(registry: &LobbyRegistry, id: &str) -> Option<(u64, LobbyHandle)> {
+    let id = id.parse::<u64>().ok()?;
+    registry
+        .lobbies()
+        .await
+        .into_iter()
+        .find(|(lobby_id, _)| *lobby_id == id)
+}
+
+/// Describes the game in the lobby and the clients connected to it
+async fn describe_lobby(id: u64, handle: &LobbyHandle) -> Value {
+    let state = handle.state.borrow().clone();
+    let (players, spectators): (Vec<_>, Vec<_>) = handle
+        .clients()
+        .await
+        .into_iter()
+        .partition(|client| client.player_num != 0);
+    json!({
+        "id": id,
+        "name": state.name,
+        "game_started": state.game_started,
+        "is_p1_turn": state.is_p1_turn,
+        "board_engine": handle.board,
+        "spectator_delay": handle.spectator_delay,
+        "moves": state.moves,
+        "board": render_board(&state),
+        "players": players,
+        "spectators": spectators,
+    })
+}
+
+/// Judges the moves of the game in the lobby so far with a perfect solver, on a blocking thread
+/// as solving can take a while, and sums up how each player did
+async fn analyze_lobby(id: u64, handle: &LobbyHandle) -> Value {
+    let moves = handle.state.borrow().moves.clone();
+    let clients = handle.clients().await;
+    let judged = {
+        let moves = moves.clone();
+        task::spawn_blocking(move || {
+            let settings = Settings {
+                node_budget: LIVE_NODE_BUDGET,
+                ..Settings::default()
+            };
+            analysis::judge_game(&moves, &settings, &mut Solver::new(settings.node_budget, Solver::LARGE_TABLE))
+        })
+        .await
+        .unwrap_or_default()
+    };
+    let players: Vec<Value> = (1..=2)
+        .map(|player_num| {
+            let tally = Tally::of(&judged, player_num);
+            json!({
+                "player_num": player_num,
+                "peer": clients
+                    .iter()
+                    .find(|client| client.player_num == player_num)
+                    .map(|client| client.peer),
+                "judged": tally.positions,
+                "matches": tally.matches,
+                "match_rate": tally.match_rate(),
+                "accuracy": tally.accuracy(),
+            })
+        })
+        .collect();
+    let judged: Vec<Value> = judged
+        .iter()
+        .map(|judged| {
+            json!({
+                "ply": judged.ply,
+                "player_num": judged.player_num,
+                "col": judged.col,
+                "scores": judged.scores,
+                "matched": judged.matched(),
+                "accurate": judged.accurate(),
+            })
+        })
+        .collect();
+    json!({
+        "id": id,
+        "moves": moves,
+        "players": players,
+        "judged": judged,
+    })
+}
+
+/// Draws the board as rows from top to bottom, with '1' and '2' for each player's disks and '.' for empty spaces
+fn render_board(state: &LobbyState) -> Vec<String> {
+    let update = match ConnectionProtocol::decode_message(state.board.clone()) {
+        Ok(update) => update,
+        Err(()) => return Vec::new(),
+    };
+    (0..BOARD_HEIGHT)
+        .rev()
+        .map(|row| {
+            (0..BOARD_WIDTH)
+                .map(|col| {
+                    // Position holds the disks of the player whose turn it is
+                    let bit = 1u64 << (row + col * (BOARD_HEIGHT + 1));
+                    if update.mask & bit == 0 {
+                        '.'
+                    } else if (update.position & bit != 0) == update.is_p1_turn {
+                        '1'
+                    } else {
+                        '2'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Body of a response describing what went wrong
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
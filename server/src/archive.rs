@@ -0,0 +1,65 @@
+//! archive appends every game played on the server to a file, one JSON object per line,
+//! for board-analyze to look for players getting help from an engine
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use board::analysis::ArchivedGame;
+
+use tokio::task;
+use tracing::{debug, warn};
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// File games are appended to, cheap to clone and share between lobbies
+/// The lock keeps games written by different lobbies from interleaving
+#[derive(Clone)]
+pub struct GameArchive(Arc<Mutex<File>>);
+
+impl GameArchive {
+    /// Opens the archive at the given path, creating it if it does not exist
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Arc::new(Mutex::new(file))))
+    }
+
+    /// Appends the game to the archive, logging rather than returning any failure
+    pub async fn record(&self, game: ArchivedGame) {
+        let file = self.0.clone();
+        let written = task::spawn_blocking(move || {
+            let mut line = serde_json::to_vec(&game)?;
+            line.push(b'\n');
+            file.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .write_all(&line)
+        })
+        .await
+        .map_err(io::Error::other)
+        .and_then(|written| written);
+        match written {
+            Ok(()) => debug!("Archived game."),
+            Err(e) => warn!(error = %e, "Failed to archive game."),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! boards chooses the board each lobby checks moves with: a C++ board plugin named after the
+//! lobby, the default plugin, or the built-in Bitboard
+//!
+//! Plugins are loaded into the server itself, or run in sandboxed child processes if a board host is given
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    lobby::util::is_named_after,
+    sandbox::{BoardHost, SandboxedBoard},
+};
+
+use board::{Bitboard, BoardEngine, Plugin};
+use constants::ConnectionProtocol;
+
+use tracing::{info, warn};
+
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
+
+/// Name of the built-in Bitboard
+pub const BUILTIN: &str = "builtin";
+
+/// A board plugin to load, given on the command line as <name>=<path>
+pub struct PluginSpec {
+    name: String,
+    path: PathBuf,
+}
+
+impl FromStr for PluginSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, path))
+                if !name.is_empty()
+                    && name != BUILTIN
+                    && ConnectionProtocol::is_valid_lobby_name(name) =>
+            {
+                Ok(Self {
+                    name: name.to_string(),
+                    path: PathBuf::from(path),
+                })
+            }
+            _ => Err(format!(
+                "expected <name>=<path>, with a name of letters, digits, - and _ other than {}, got {}",
+                BUILTIN, s
+            )),
+        }
+    }
+}
+
+/// Why a board did not accept a move
+#[derive(Debug)]
+pub enum MoveError {
+    /// The move breaks the rules
+    Illegal,
+    /// The board could not check the move, e.g. because its process crashed or timed out
+    Failed(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::Illegal => write!(f, "illegal move"),
+            MoveError::Failed(reason) => write!(f, "board failed: {}", reason),
+        }
+    }
+}
+
+/// The board a lobby checks moves with
+pub enum LobbyBoard {
+    InProcess(Box<dyn BoardEngine>),
+    Sandboxed(Box<SandboxedBoard>),
+}
+
+impl LobbyBoard {
+    /// Drops a disk for the player into the column, returning whether the player won
+    pub async fn drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, MoveError> {
+        match self {
+            LobbyBoard::InProcess(board) => board
+                .drop_disk(player_num, col)
+                .map_err(|_| MoveError::Illegal),
+            LobbyBoard::Sandboxed(board) => board.drop_disk(player_num, col).await,
+        }
+    }
+
+    /// Encodes the board as a message to be sent to clients
+    pub fn to_game_update_binary(&self, is_p1_turn: bool, game_won: bool) -> Vec<u8> {
+        match self {
+            LobbyBoard::InProcess(board) => board.to_game_update_binary(is_p1_turn, game_won),
+            LobbyBoard::Sandboxed(board) => {
+                board::encode_disks(board.disks(), is_p1_turn, game_won)
+            }
+        }
+    }
+}
+
+/// A loaded board plugin
+enum BoardPlugin {
+    InProcess(Plugin),
+    /// Path to the library, run by the board host
+    Sandboxed(PathBuf),
+}
+
+/// The board plugins loaded on startup, and which lobbies use them
+pub struct Boards {
+    /// Whether the server checks moves, rather than trusting the board states clients send
+    validate_moves: bool,
+    plugins: HashMap<String, BoardPlugin>,
+    /// Runs sandboxed plugins, if plugins are not loaded into the server
+    host: Option<BoardHost>,
+    /// Plugin used by lobbies not named after a plugin
+    default: Option<String>,
+}
+
+impl Boards {
+    /// Loads the plugins, falling back to the built-in board for any which fail to load
+    /// With a board host, each plugin is checked in a child process instead of being loaded into the server
+    /// Moves are checked if asked to, or if any plugins were given
+    pub async fn load(
+        specs: Vec<PluginSpec>,
+        default: Option<String>,
+        validate_moves: bool,
+        host: Option<BoardHost>,
+    ) -> Self {
+        let validate_moves = validate_moves || !specs.is_empty();
+        let mut plugins = HashMap::new();
+        for PluginSpec { name, path } in specs {
+            let plugin = match &host {
+                Some(host) => host
+                    .check(&path)
+                    .await
+                    .map(|_| BoardPlugin::Sandboxed(path.clone())),
+                // Safety: plugins are given by whoever runs the server, who trusts them
+                None => unsafe { Plugin::load(&path) }
+                    .map(BoardPlugin::InProcess)
+                    .map_err(|e| e.to_string()),
+            };
+            match plugin {
+                Ok(plugin) => {
+                    info!(
+                        board = name,
+                        path = %path.display(),
+                        sandboxed = host.is_some(),
+                        "Loaded board plugin."
+                    );
+                    plugins.insert(name, plugin);
+                }
+                Err(e) => warn!(
+                    board = name,
+                    path = %path.display(),
+                    error = %e,
+                    "Failed to load board plugin, lobbies using it will use the built-in board."
+                ),
+            }
+        }
+        if let Some(default) = &default {
+            if !plugins.contains_key(default) && default != BUILTIN {
+                warn!(
+                    board = default,
+                    "Default board is not a loaded plugin, using the built-in board."
+                );
+            }
+        }
+        Self {
+            validate_moves,
+            plugins,
+            host,
+            default,
+        }
+    }
+
+    /// Returns whether clients send the columns they play, to be checked by the server,
+    /// rather than the board states after their moves
+    pub fn validates_moves(&self) -> bool {
+        self.validate_moves
+    }
+
+    /// Returns a new empty board for the lobby along with the name of its plugin (or BUILTIN),
+    /// or None if moves are not checked
+    /// Lobbies named <plugin> or <plugin>-<anything> use that plugin, and other lobbies use the default
+    pub fn new_board(&self, lobby: &str) -> Option<(String, LobbyBoard)> {
+        if !self.validate_moves {
+            return None;
+        }
+        let named = self
+            .plugins
+            .keys()
+            .filter(|name| is_named_after(lobby, name))
+            .max_by_key(|name| name.len());
+        let board = match named
+            .or(self.default.as_ref())
+            .map(|name| (name, self.plugins.get(name)))
+        {
+            Some((name, Some(BoardPlugin::InProcess(plugin)))) => (
+                name.clone(),
+                LobbyBoard::InProcess(Box::new(plugin.new_board())),
+            ),
+            Some((name, Some(BoardPlugin::Sandboxed(path)))) => match &self.host {
+                Some(host) => (
+                    name.clone(),
+                    LobbyBoard::Sandboxed(Box::new(host.new_board(path.clone()))),
+                ),
+                None => (
+                    BUILTIN.to_string(),
+                    LobbyBoard::InProcess(Box::<Bitboard>::default()),
+                ),
+            },
+            _ => (
+                BUILTIN.to_string(),
+                LobbyBoard::InProcess(Box::<Bitboard>::default()),
+            ),
+        };
+        Some(board)
+    }
+}
@@ -23,10 +23,7 @@
 
 use constants::ConnectionProtocol;
 
-use tokio::{
-    net::TcpStream,
-    task,
-};
+use tokio::net::TcpStream;
 
 #[cfg(feature = "use-certificate")]
 use {
@@ -41,46 +38,56 @@ use tokio_tungstenite::tungstenite::error::Error;
 #[cfg(not(feature = "use-certificate"))]
 use futures::{SinkExt, StreamExt};
 
-use std::sync::{Arc, Mutex};
+use crate::{
+    limits::ConnectionGuard,
+    lobby::{registry::LobbyRegistry, util::NewClient},
+    metrics::{self, METRICS},
+};
+
+use tracing::{debug, info, warn};
 
-use crate::{lobby::lobby, Lobbies};
+use std::net::SocketAddr;
 
 #[cfg(feature = "use-certificate")]
-type Args = (TlsAcceptor, TcpStream, Arc<Mutex<Lobbies>>);
+type Args = (TlsAcceptor, TcpStream, SocketAddr, ConnectionGuard, LobbyRegistry);
 #[cfg(not(feature = "use-certificate"))]
-type Args = (TcpStream, Arc<Mutex<Lobbies>>);
+type Args = (TcpStream, SocketAddr, ConnectionGuard, LobbyRegistry);
 
 /// Takes a websocket request, tells the client the connection was successful,
 /// and places the client into the desired lobby
 ///
+/// Expected to run in a span with the peer address of the client
+///
 /// Async to be run as a new task whenever a connection is established
 pub async fn handle_connection(
     args: Args
 ) -> Result<(), Error> {
 
     #[cfg(feature = "use-certificate")]
-    let (acceptor, incoming, lobbies) = args;
+    let (acceptor, incoming, peer, connection, registry) = args;
     #[cfg(not(feature = "use-certificate"))]
-    let (incoming, lobbies) = args;
+    let (incoming, peer, connection, registry) = args;
 
     // Accept the websocket request
     #[cfg(feature = "use-certificate")]
     let mut client = TlsClient::accept(incoming, acceptor).await?;
     #[cfg(not(feature = "use-certificate"))]
     let mut client = tokio_tungstenite::accept_async(incoming).await?;
+    metrics::increment(&METRICS.connections);
 
     // Confirm (besides the websocket handshake) the connection was successful
     // Length of the confirmation message indicated what type of message the client should send to the server
-    #[cfg(feature = "cppintegration")]
-    client
-        .send(Binary(vec![ConnectionProtocol::CONNECTION_SUCCESS]))
-        .await?;
-    #[cfg(not(feature = "cppintegration"))]
-    {
+    // A single byte asks the client to send the columns it plays, for the server to check
+    if registry.boards().validates_moves() {
+        client
+            .send(Binary(vec![ConnectionProtocol::CONNECTION_SUCCESS]))
+            .await?;
+    } else {
         client
             .send(Binary(vec![ConnectionProtocol::CONNECTION_SUCCESS, 0]))
             .await?;
     }
+    metrics::increment(&METRICS.messages_out);
 
     // Get the lobby name from the client and place the client into the desired lobby
     #[cfg(feature = "use-certificate")]
@@ -93,35 +100,31 @@ pub async fn handle_connection(
     };
     #[cfg(not(feature = "use-certificate"))]
     let msg = client.next().await.unwrap_or(Err(Error::AlreadyClosed))?;
-    println!("Received msg from client.");
-    if let Text(lobby) = msg {
-        println!("Lobby: {}", lobby);
-        task::block_in_place(move || {
-            let lobby_name = lobby.clone();
-            if let Ok(mut lobbies_map) = lobbies.lock() {
-                // Send the player to the lobby if it already exists
-                if let Some(sender) = lobbies_map.get(&lobby) {
-                    sender.send(client).unwrap_or_default();
-                    if lobby == "".to_string() {
-                        lobbies_map.remove(&lobby);
-                    }
-                    println!("Sent player to lobby.");
-                } else {
-                    // If the lobby does not already exist
-                    // Create a new lobby
-                    let lobbies_ref = Arc::clone(&lobbies);
-                    let new_client_sender = lobby::create_lobby(Box::new(move || {
-                        lobbies_ref.lock().unwrap().remove(&lobby_name);
-                    }));
-                    lobbies_map.insert(lobby, new_client_sender.clone());
-                    // Send the player to the new lobby
-                    new_client_sender.send(client).unwrap_or_default();
-                    println!("Created lobby.");
-                }
-            }
-        });
+    metrics::increment(&METRICS.messages_in);
+    if let Text(request) = msg {
+        let (lobby, seat_token) = ConnectionProtocol::decode_join_request(&request);
+        let new_client = NewClient {
+            client,
+            peer,
+            seat_token,
+            connection,
+        };
+        // Names are checked before being logged, as they come straight from the client
+        if ConnectionProtocol::is_valid_lobby_name(&lobby) {
+            info!(lobby, has_seat_token = seat_token.is_some(), "Client asked to join lobby.");
+            registry.join(lobby, new_client);
+        } else {
+            warn!(len = lobby.len(), "Client asked for an invalid lobby name.");
+            new_client
+                .reject(&format!(
+                    "Lobby names can be at most {} letters, digits, - and _.",
+                    ConnectionProtocol::MAX_LOBBY_NAME_LENGTH
+                ))
+                .await;
+        }
+    } else {
+        debug!("Client did not send a lobby name.");
+        metrics::increment(&METRICS.protocol_errors);
     }
-
-    println!("Connection handled.");
     Ok(())
 }
@@ -0,0 +1,120 @@
+//! http contains just enough of HTTP/1.1 to serve the metrics and admin endpoints:
+//! reading a single request and writing a single response per connection
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{self, Duration, Instant},
+};
+
+/// Longest request head that is accepted
+const MAX_HEAD_SIZE: usize = 8192;
+/// Longest request body that is accepted
+const MAX_BODY_SIZE: usize = 8192;
+/// Longest a client may take to send a whole request, so idle connections are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP request, with header names lowercased
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the header with the given (lowercase) name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads a single request from the stream
+/// Returns None if the connection closed early, the request is malformed or too large, or the
+/// client took longer than REQUEST_TIMEOUT to send it
+pub async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut data = Vec::new();
+    let mut buf = [0; 1024];
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        if data.len() > MAX_HEAD_SIZE {
+            return None;
+        }
+        match time::timeout_at(deadline, stream.read(&mut buf)).await.ok()? {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+        }
+    };
+
+    // Request line is "<method> <path> <version>", followed by "<name>: <value>" headers
+    let head = String::from_utf8_lossy(&data[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let (method, path) = (request_line.next()?.to_string(), request_line.next()?.to_string());
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    // Read the rest of the body, if there is one
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map_or(Some(0), |(_, value)| value.parse::<usize>().ok())?;
+    if content_length > MAX_BODY_SIZE {
+        return None;
+    }
+    let mut body = data.split_off(head_end + 4);
+    while body.len() < content_length {
+        match time::timeout_at(deadline, stream.read(&mut buf)).await.ok()? {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => body.extend_from_slice(&buf[..n]),
+        }
+    }
+    body.truncate(content_length);
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Writes a response with the given status (e.g. "200 OK") and body, then closes the connection
+pub async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.unwrap_or_default();
+    stream.shutdown().await.unwrap_or_default();
+}
@@ -0,0 +1,47 @@
+//! server contains the lobbies and everything around them, used by the server binary,
+//! and by the fuzz targets to drive lobbies without a network
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+#[cfg(not(feature = "use-certificate"))]
+use {
+    tokio::net::TcpStream,
+    tokio_tungstenite::WebSocketStream
+};
+
+#[cfg(feature = "use-certificate")]
+pub mod tlsclient;
+pub mod admin;
+pub mod archive;
+pub mod boards;
+pub mod connection;
+pub mod http;
+pub mod limits;
+pub mod lobby;
+pub mod logging;
+pub mod metrics;
+pub mod persistence;
+pub mod sandbox;
+pub mod shutdown;
+
+#[cfg(feature = "use-certificate")]
+pub type Client = tlsclient::TlsClient;
+#[cfg(not(feature = "use-certificate"))]
+pub type Client = WebSocketStream<TcpStream>;
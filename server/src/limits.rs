@@ -0,0 +1,201 @@
+//! limits contains the limits protecting the server from abusive clients: how often and how many
+//! connections each address can open, how often clients can send messages, and how many
+//! lobbies and spectators the server holds at once
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Number of addresses tracked before idle ones are forgotten
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Limits set on the command line
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Connections each address can have open at once
+    pub connections_per_address: usize,
+    /// Connections each address can open per minute
+    pub connections_per_minute: u32,
+    /// Lobbies each address can create per minute
+    pub lobbies_per_minute: u32,
+    /// Messages each client can send per second
+    pub messages_per_second: u32,
+    /// Lobbies which can exist at once
+    pub max_lobbies: usize,
+    /// Clients which can spectate at once, across every lobby
+    pub max_spectators: u64,
+}
+
+/// Token bucket allowing a burst of up to its rate at once, refilled at its rate
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// Allows the given number of events per second
+    pub fn per_second(rate: u32) -> Self {
+        Self::new(rate as f64, rate as f64)
+    }
+
+    /// Allows the given number of events per minute
+    pub fn per_minute(rate: u32) -> Self {
+        Self::new(rate as f64, rate as f64 / 60.0)
+    }
+
+    fn new(capacity: f64, per_second: f64) -> Self {
+        Self {
+            capacity,
+            per_second,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Records an event, returning false if the rate has been exceeded
+    pub fn allow(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns whether no events have been recorded recently, so the limiter can be forgotten
+    pub fn is_idle(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last = now;
+    }
+}
+
+/// Connections an address has open, and how recently it opened them
+struct AddressConnections {
+    open: usize,
+    rate: RateLimiter,
+}
+
+/// Limits the connections each address can open, cheap to clone and share
+/// The lock is only ever held briefly, and never across an await
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    limits: Limits,
+    addresses: Arc<Mutex<HashMap<IpAddr, AddressConnections>>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            addresses: Arc::default(),
+        }
+    }
+
+    /// Records a new connection from the address
+    /// Returns a guard which counts the connection as open until dropped,
+    /// or None if the address has too many connections open or opened too many recently
+    pub fn admit(&self, address: IpAddr) -> Option<ConnectionGuard> {
+        let mut addresses = self.addresses.lock().unwrap_or_else(|e| e.into_inner());
+        if addresses.len() >= PRUNE_THRESHOLD {
+            addresses.retain(|_, connections| connections.open > 0 || !connections.rate.is_idle());
+        }
+        let connections = addresses
+            .entry(address)
+            .or_insert_with(|| AddressConnections {
+                open: 0,
+                rate: RateLimiter::per_minute(self.limits.connections_per_minute),
+            });
+        if connections.open >= self.limits.connections_per_address || !connections.rate.allow() {
+            return None;
+        }
+        connections.open += 1;
+        Some(ConnectionGuard {
+            limiter: self.clone(),
+            address,
+        })
+    }
+}
+
+/// Counts a connection as open for its address while held
+/// Travels with the client into its lobby, so the connection counts until the client leaves
+pub struct ConnectionGuard {
+    limiter: ConnectionLimiter,
+    address: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut addresses = self
+            .limiter
+            .addresses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(connections) = addresses.get_mut(&self.address) {
+            connections.open -= 1;
+            if connections.open == 0 && connections.rate.is_idle() {
+                addresses.remove(&self.address);
+            }
+        }
+    }
+}
+
+/// Limits how often each address can do something, for use by a single task
+pub struct AddressRateLimiter {
+    rate: u32,
+    addresses: HashMap<IpAddr, RateLimiter>,
+}
+
+impl AddressRateLimiter {
+    /// Allows each address the given number of events per minute
+    pub fn per_minute(rate: u32) -> Self {
+        Self {
+            rate,
+            addresses: HashMap::new(),
+        }
+    }
+
+    /// Records an event from the address, returning false if its rate has been exceeded
+    pub fn allow(&mut self, address: IpAddr) -> bool {
+        if self.addresses.len() >= PRUNE_THRESHOLD {
+            self.addresses.retain(|_, rate| !rate.is_idle());
+        }
+        let rate = self.rate;
+        self.addresses
+            .entry(address)
+            .or_insert_with(|| RateLimiter::per_minute(rate))
+            .allow()
+    }
+}
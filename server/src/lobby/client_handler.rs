@@ -21,12 +21,17 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use super::util::{MessageFromClient, Subtasks};
-use crate::Client;
+use super::{
+    delay::{DelayedUpdates, SpectatorDelay},
+    registry::LobbyRegistry,
+    updates::UpdateEncoder,
+    util::{ClientInfo, ClientRequest, LobbyState, MessageFromClient, NewClient, PlayerRequest},
+};
+use crate::{
+    limits::RateLimiter,
+    metrics::{self, GaugeGuard, METRICS},
+};
 
-#[cfg(feature = "cppintegration")]
-type Message = MessageFromClient;
-#[cfg(not(feature = "cppintegration"))]
 use super::util::Message::{self, BoardState, SpecialMessage};
 
 use constants::ConnectionProtocol;
@@ -35,6 +40,7 @@ use constants::ConnectionProtocol;
 use crate::tlsclient::{TlsClientReader, TlsClientWriter};
 #[cfg(not(feature = "use-certificate"))]
 use {
+    crate::Client,
     futures::{
         stream::{SplitSink, SplitStream},
         SinkExt, StreamExt
@@ -44,94 +50,338 @@ use {
 
 use tokio::{
     sync::{
-        broadcast::{Receiver as BroadcastReceiver, Sender as BroadcastSender},
-        mpsc::{UnboundedReceiver, UnboundedSender},
+        broadcast::{error::RecvError, Receiver as BroadcastReceiver, Sender as BroadcastSender},
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch::{Receiver as WatchReceiver, Sender as WatchSender},
     },
-    task::{self, JoinHandle},
+    task::{self, AbortHandle, JoinHandle},
+    time::{self, Duration, Instant},
+};
+use tokio_tungstenite::tungstenite::{
+    Error,
+    Message::{self as TungsteniteMessage, Binary, Text},
 };
-use tokio_tungstenite::tungstenite::Message::Binary;
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+
+/// How long a restored lobby waits for both players to return before ending
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(300);
 
 /// new_client_handler spawns tasks to read and write data over websockets to clients and to communicate with the main lobby task
 /// It also tells clients whether they are playing (and as which player) or spectating
 ///
+/// In a game restored from a snapshot, seats are only given to clients with the matching seat token,
+/// and the lobby ends if both players do not return within RESTORE_TIMEOUT
+///
+/// Also answers requests from the admin API to list and kick clients
+///
+/// Ends once the main lobby task drops the state sender, killing the tasks listening to clients
+/// Clients that were sent to the lobby as it was closing are sent back to the registry
+///
 /// Async to be run as a new task whenever a new lobby is created
 pub async fn new_client_handler(
     sender: UnboundedSender<Message>,
-    mut new_client_receiver: UnboundedReceiver<Client>,
+    mut new_client_receiver: UnboundedReceiver<NewClient>,
+    mut client_request_receiver: UnboundedReceiver<ClientRequest>,
     game_update_sender: BroadcastSender<MessageFromClient>,
-    subtasks: Arc<Mutex<Subtasks>>,
+    mut state_receiver: WatchReceiver<LobbyState>,
+    player_addresses: WatchSender<[Option<IpAddr>; 2]>,
+    registry: LobbyRegistry,
 ) {
-    // Receive new clients sent to the lobby
-    while let Some(client) = new_client_receiver.recv().await {
-        let (mut writer, reader) = client.split();
-
-        task::block_in_place(|| {
-            let mut subtasks = subtasks.lock().unwrap();
-            let (mut player_num, mut client_type) = (0, ConnectionProtocol::IS_SPECTATOR);
-
-            // If there are not yet two players, make this client a player
-            let subtasks_len = subtasks.tasks.len();
-            if subtasks_len < 2 {
-                (player_num, client_type) = if subtasks_len == 0 {
-                    (1, ConnectionProtocol::IS_PLAYER_1)
-                } else {
-                    (2, ConnectionProtocol::IS_PLAYER_2)
-                };
-            }
+    let (lobby_name, seats_reserved) = {
+        let state = state_receiver.borrow();
+        (state.name.clone(), state.game_started)
+    };
+    // Clients in the lobby, with handles to their tasks (so they can be killed when the lobby closes)
+    let mut clients: Vec<ConnectedClient> = Vec::new();
+    let mut next_client_id = 0;
+    let mut seats = Seats {
+        reserved: seats_reserved,
+        taken: [false; 2],
+        addresses: player_addresses,
+    };
+    // The registry stops sending clients to the default lobby once it is full
+    let mut accepting_clients = true;
+    // A restored game is abandoned if the players do not come back for it
+    let restore_timeout = time::sleep(RESTORE_TIMEOUT);
+    tokio::pin!(restore_timeout);
+    let mut waiting_for_players = seats_reserved;
 
-            // Spawn a task to write to the client
-            // This task ends when lobby drops game_update_receiver or when the reader task receives ConnectionProtocol::KILL_CONNECTION
-            let game_update_receiver = game_update_sender.subscribe();
-            let last_board_state = subtasks.last_board_state.clone();
-            let client_task = task::spawn(async move {
-                // Send to the client which player it is, or if it is a spectator
-                writer
-                    .send(Binary(vec![client_type]))
-                    .await
-                    .unwrap_or_default();
-                if subtasks_len != 0 {
-                    // Send the current board state to the client
-                    writer
-                        .send(Binary(last_board_state))
-                        .await
-                        .unwrap_or_default();
+    loop {
+        tokio::select! {
+            // Receive new clients sent to the lobby
+            client = new_client_receiver.recv(), if accepting_clients => match client {
+                Some(new_client) => {
+                    // Forget spectators who have left
+                    clients.retain(|client| !client.listener.is_finished());
+                    if let Some(client) = add_client(
+                        next_client_id,
+                        new_client,
+                        &mut seats,
+                        &sender,
+                        &game_update_sender,
+                        &state_receiver,
+                        &registry,
+                    ) {
+                        clients.push(client);
+                        next_client_id += 1;
+                    }
+                    waiting_for_players &= seats.taken != [true; 2];
                 }
-                // Write to the client on game update
-                client_writer(writer, game_update_receiver, player_num).await;
-            });
-
-            // Spawn the appropriate listener and store its handle (so it can be ended when clients leave / the game ends)
-            if subtasks_len < 2 {
-                if subtasks_len == 1 {
-                    #[cfg(feature = "cppintegration")]
-                    sender
-                        .send(Message {
-                            binary: vec![ConnectionProtocol::SECOND_PLAYER_CONNECTED],
-                            player_num: 2,
-                        })
-                        .unwrap_or_default();
-                    #[cfg(not(feature = "cppintegration"))]
-                    sender
-                        .send(BoardState(MessageFromClient {
-                            binary: vec![ConnectionProtocol::SECOND_PLAYER_CONNECTED],
-                            player_num: 2,
-                        }))
-                        .unwrap_or_default();
+                None => accepting_clients = false,
+            },
+            // Answer the admin API
+            Some(request) = client_request_receiver.recv() => match request {
+                ClientRequest::List(reply) => {
+                    let connected = clients
+                        .iter()
+                        .filter(|client| !client.listener.is_finished())
+                        .map(|client| client.info.clone())
+                        .collect();
+                    reply.send(connected).unwrap_or_default();
                 }
-                let sender = sender.clone();
-                subtasks.tasks.push(task::spawn(async move {
-                    player_listener(reader, sender, player_num).await;
-                }));
-            } else {
-                subtasks.tasks.push(task::spawn(async move {
-                    spectator_listener(reader, client_task).await;
-                }));
-            }
+                ClientRequest::Kick { id, reply } => {
+                    let client = clients
+                        .iter()
+                        .find(|client| client.info.id == id && !client.listener.is_finished());
+                    if let Some(client) = client {
+                        info!(id, peer = %client.info.peer, player_num = client.info.player_num, "Kicking client.");
+                        client.listener.abort();
+                        client.writer.abort();
+                        if client.info.player_num != 0 {
+                            end_game(&sender);
+                        }
+                    }
+                    reply.send(client.is_some()).unwrap_or_default();
+                }
+            },
+            // Errors once the main lobby task has ended
+            changed = state_receiver.changed() => if changed.is_err() {
+                break;
+            },
+            // End a restored game nobody came back for
+            _ = &mut restore_timeout, if waiting_for_players => {
+                waiting_for_players = false;
+                warn!("Players did not return to restored lobby.");
+                end_game(&sender);
+            },
+        }
+    }
+
+    for client in &clients {
+        client.listener.abort();
+    }
+    new_client_receiver.close();
+    while let Ok(client) = new_client_receiver.try_recv() {
+        debug!(peer = %client.peer, "Sending client back to the registry.");
+        registry.join(lobby_name.clone(), client);
+    }
+    debug!("Exiting new client handler.");
+}
+
+/// Player seats in a lobby
+struct Seats {
+    /// Whether seats are kept for the players of a restored game
+    reserved: bool,
+    /// Which seats have a player in them
+    taken: [bool; 2],
+    /// Addresses the players in each seat last joined from, for the game archive
+    addresses: WatchSender<[Option<IpAddr>; 2]>,
+}
+
+/// A client in the lobby, with handles to its tasks
+struct ConnectedClient {
+    info: ClientInfo,
+    /// Listens to the client, finishes once the client leaves
+    listener: JoinHandle<()>,
+    writer: AbortHandle,
+}
+
+/// Tells the main lobby task to end the game, as if a player had killed the connection
+fn end_game(sender: &UnboundedSender<Message>) {
+    sender
+        .send(SpecialMessage(ConnectionProtocol::KILL_CONNECTION))
+        .unwrap_or_default();
+}
+
+/// Spawns the writer and listener tasks for a client that was just sent to the lobby
+/// The first two clients become players, and the rest become spectators
+/// If seats are reserved, only clients with the token for an empty seat become players
+/// The tasks run in a span with the client's address, inside the lobby's span
+/// Returns the client along with the given id, or None if it was turned away as there are too many spectators
+fn add_client(
+    id: u64,
+    new_client: NewClient,
+    seats: &mut Seats,
+    sender: &UnboundedSender<Message>,
+    game_update_sender: &BroadcastSender<MessageFromClient>,
+    state_receiver: &WatchReceiver<LobbyState>,
+    registry: &LobbyRegistry,
+) -> Option<ConnectedClient> {
+    let (last_board_state, seat_tokens, moves, lobby_name) = {
+        let state = state_receiver.borrow();
+        (
+            state.board.clone(),
+            state.seat_tokens,
+            state.moves.clone(),
+            state.name.clone(),
+        )
+    };
+
+    // Find the seat this client should take, if any
+    let seat = if seats.reserved {
+        new_client
+            .seat_token
+            .and_then(|token| (0..2).find(|&i| !seats.taken[i] && seat_tokens[i] == token))
+    } else {
+        (0..2).find(|&i| !seats.taken[i])
+    };
+    let limits = registry.limits();
+    let validate_moves = registry.boards().validates_moves();
+    if seat.is_none() && metrics::load(&METRICS.spectators) >= limits.max_spectators {
+        warn!(peer = %new_client.peer, "Turned client away, too many spectators.");
+        task::spawn(async move {
+            new_client
+                .reject("This server has too many spectators. Try again later.")
+                .await;
         });
+        return None;
+    }
+    let NewClient {
+        client,
+        peer,
+        connection,
+        ..
+    } = new_client;
+    let (mut writer, reader) = client.split();
+    let (player_num, client_type) = match seat {
+        Some(0) => (1, ConnectionProtocol::IS_PLAYER_1),
+        Some(_) => (2, ConnectionProtocol::IS_PLAYER_2),
+        None => (0, ConnectionProtocol::IS_SPECTATOR),
+    };
+    if let Some(seat) = seat {
+        seats.taken[seat] = true;
+        seats
+            .addresses
+            .send_modify(|addresses| addresses[seat] = Some(peer.ip()));
     }
-    println!("Exiting new client handler.");
+    // The first player of a new game has no board to catch up on
+    let send_board_state = seats.reserved || player_num != 1;
+    // Spectators may be kept behind the game, starting from an earlier board
+    let delay = match seat {
+        Some(_) => SpectatorDelay::None,
+        None => registry.spectator_delays().for_lobby(&lobby_name),
+    };
+    let (delayed, first_board_state) = DelayedUpdates::join(delay, last_board_state, &moves);
+    let span = info_span!("client", peer = %peer, player_num);
+    span.in_scope(|| {
+        info!(
+            returning = seats.reserved && seat.is_some(),
+            "Client joined as {}.",
+            match player_num {
+                0 => "spectator",
+                1 => "player 1",
+                _ => "player 2",
+            }
+        )
+    });
+
+    // Spawn a task to write to the client
+    // This task ends when lobby drops game_update_receiver or when the reader task receives ConnectionProtocol::KILL_CONNECTION
+    let game_update_receiver = game_update_sender.subscribe();
+    let notice_receiver = registry.subscribe_notices();
+    // The listener tells the writer when the client asks to resync
+    let (resync_sender, resync_receiver) = mpsc::unbounded_channel();
+    let client_task = task::spawn(
+        async move {
+            let mut updates = UpdateEncoder::default();
+            // Send to the client which player it is, or if it is a spectator
+            send(&mut writer, Binary(vec![client_type]))
+                .await
+                .unwrap_or_default();
+            // Give players the token to reclaim their seat with if they are disconnected
+            if let Some(seat) = seat {
+                send(
+                    &mut writer,
+                    Binary(ConnectionProtocol::encode_seat_token(seat_tokens[seat])),
+                )
+                .await
+                .unwrap_or_default();
+            }
+            if let Some(board_state) = first_board_state.filter(|_| send_board_state) {
+                // Send the current board state to the client, or an earlier one to a delayed spectator
+                send(&mut writer, Binary(updates.snapshot(board_state)))
+                    .await
+                    .unwrap_or_default();
+            }
+            // Write to the client on game update
+            client_writer(
+                writer,
+                game_update_receiver,
+                notice_receiver,
+                resync_receiver,
+                player_num,
+                delayed,
+                updates,
+            )
+            .await;
+        }
+        .instrument(span.clone()),
+    );
+
+    // Spawn the appropriate listener and return its handle (so it can be ended when clients leave / the game ends)
+    let writer = client_task.abort_handle();
+    let listener = if seat.is_some() {
+        if seats.taken == [true; 2] {
+            sender
+                .send(BoardState(MessageFromClient {
+                    binary: vec![ConnectionProtocol::SECOND_PLAYER_CONNECTED],
+                    player_num,
+                }))
+                .unwrap_or_default();
+        }
+        let sender = sender.clone();
+        task::spawn(
+            async move {
+                let _connection = connection;
+                player_listener(
+                    reader,
+                    sender,
+                    resync_sender,
+                    player_num,
+                    limits.messages_per_second,
+                    validate_moves,
+                )
+                .await;
+            }
+            .instrument(span),
+        )
+    } else {
+        task::spawn(
+            async move {
+                let _connection = connection;
+                spectator_listener(
+                    reader,
+                    client_task,
+                    resync_sender,
+                    limits.messages_per_second,
+                )
+                .await;
+            }
+            .instrument(span),
+        )
+    };
+
+    Some(ConnectedClient {
+        info: ClientInfo {
+            id,
+            peer,
+            player_num,
+        },
+        listener,
+        writer,
+    })
 }
 
 #[cfg(feature = "use-certificate")]
@@ -139,80 +389,84 @@ type ClientStream = TlsClientReader;
 #[cfg(not(feature = "use-certificate"))]
 type ClientStream = SplitStream<Client>;
 
-/// player_listener forwards messages received from the player to the main lobby task
-/// When the player leaves, it sends ConnectionProtocol::KILL_CONNECTION as the game is now over
+/// player_listener forwards messages received from the player to the main lobby task,
+/// except requests to resync, which go to the player's writer task
+/// When the player leaves, or is disconnected for sending more than messages_per_second,
+/// it sends ConnectionProtocol::KILL_CONNECTION as the game is now over
 ///
 /// Async to be run as a new task whenever a player joins the lobby
 async fn player_listener(
     mut client: ClientStream,
     sender: UnboundedSender<Message>,
+    resync_sender: UnboundedSender<()>,
     player_num: u8,
+    messages_per_second: u32,
+    validate_moves: bool,
 ) {
+    let _connection = GaugeGuard::new(&METRICS.active_connections);
+    let mut message_rate = RateLimiter::per_second(messages_per_second);
     // Read in new messages from the client
     while let Some(Ok(msg)) = client.next().await {
+        metrics::increment(&METRICS.messages_in);
+        if !message_rate.allow() {
+            warn!("Disconnected player for sending messages too quickly.");
+            metrics::increment(&METRICS.rejected_clients);
+            break;
+        }
         if let Binary(binary) = msg {
-            // Forward the message to the main lobby task
-            #[cfg(feature = "cppintegration")]
-            if binary.len() == 1 && binary[0] != ConnectionProtocol::SECOND_PLAYER_CONNECTED {
-                sender
-                    .send(MessageFromClient { binary, player_num })
-                    .unwrap_or_default();
-            } else {
-                println!("Player sent unrecognized message.");
-                break;
-            }
-
-            #[cfg(not(feature = "cppintegration"))]
-            if binary.len() == 1 {
-                sender.send(SpecialMessage(binary[0])).unwrap_or_default();
-            } else if binary.len() == ConnectionProtocol::MESSAGE_SIZE {
-                sender
-                    .send(BoardState(MessageFromClient {
-                        binary,
-                        player_num,
-                    }))
-                    .unwrap_or_default();
-            } else {
-                println!("Player sent unrecognized message.");
-                break;
+            let len = binary.len();
+            match PlayerRequest::parse(binary, player_num, validate_moves) {
+                // Forward the message to the main lobby task
+                PlayerRequest::Lobby(msg) => sender.send(msg).unwrap_or_default(),
+                PlayerRequest::Resync => resync_sender.send(()).unwrap_or_default(),
+                PlayerRequest::Unrecognized => {
+                    warn!(len, "Player sent unrecognized message.");
+                    metrics::increment(&METRICS.protocol_errors);
+                    break;
+                }
             }
         }
     }
 
     // Tell the main lobby task to kill the lobby: the player left so the game is now over
-    #[cfg(feature = "cppintegration")]
-    sender
-        .send(MessageFromClient {
-            binary: vec![ConnectionProtocol::KILL_CONNECTION],
-            player_num,
-        })
-        .unwrap_or_default();
-    #[cfg(not(feature = "cppintegration"))]
-    sender
-        .send(SpecialMessage(ConnectionProtocol::KILL_CONNECTION))
-        .unwrap_or_default();
-    println!("Ending player listener.");
+    end_game(&sender);
+    info!("Player left.");
 }
 
-/// spectator_listener kills the respective writer task (to save on resources) whenever a spectator leaves
+/// spectator_listener kills the respective writer task (to save on resources) whenever a spectator leaves,
+/// or is disconnected for sending more than messages_per_second
+/// Requests to resync are passed on to the writer task
 ///
 /// Async to be run as a new task whenever a spectator joins the lobby
 async fn spectator_listener(
     mut client: ClientStream,
     client_task: JoinHandle<()>,
+    resync_sender: UnboundedSender<()>,
+    messages_per_second: u32,
 ) {
+    let _connection = GaugeGuard::new(&METRICS.active_connections);
+    let _spectator = GaugeGuard::new(&METRICS.spectators);
+    let mut message_rate = RateLimiter::per_second(messages_per_second);
     // When a message is received, check if it the spectator is killing the connection
     while let Some(Ok(msg)) = client.next().await {
+        metrics::increment(&METRICS.messages_in);
+        if !message_rate.allow() {
+            warn!("Disconnected spectator for sending messages too quickly.");
+            metrics::increment(&METRICS.rejected_clients);
+            break;
+        }
         if let Binary(binary) = msg {
-            if binary.len() == 1 && binary[0] == ConnectionProtocol::KILL_CONNECTION {
+            if binary == [ConnectionProtocol::KILL_CONNECTION] {
                 break;
+            } else if binary == [ConnectionProtocol::RESYNC] {
+                resync_sender.send(()).unwrap_or_default();
             }
         }
     }
 
     // Kill the corresponding writer task
     client_task.abort();
-    println!("Killed spectator task.");
+    info!("Spectator left.");
 }
 
 #[cfg(feature = "use-certificate")]
@@ -220,23 +474,98 @@ type ClientSink = TlsClientWriter;
 #[cfg(not(feature = "use-certificate"))]
 type ClientSink = SplitSink<Client, WebSocketMessage>;
 
-/// client_writer sends game updates to the client
+/// client_writer sends game updates and server notices to the client
+/// Board states are held back by delayed until they are due, so delayed spectators stay behind the game,
+/// then sent as sequenced moves or snapshots by updates
+/// Every client is sent every board state, including its own moves, so the sequence has no gaps
+/// A client which asks to resync is sent a snapshot of the last board state it was sent
 ///
 /// Async to be run as a new task whenever a spectator joins the lobby
 /// One task per client due to awaiting the send over a websocket
 async fn client_writer(
     mut client: ClientSink,
     mut receiver: BroadcastReceiver<MessageFromClient>,
+    mut notice_receiver: BroadcastReceiver<String>,
+    mut resync_receiver: UnboundedReceiver<()>,
     player_num: u8,
+    mut delayed: DelayedUpdates,
+    mut updates: UpdateEncoder,
 ) {
-    // Wait for a game update
-    while let Ok(msg) = receiver.recv().await {
-        // If this message did not come from this client, send it to the client
-        if msg.player_num != player_num {
-            if let Err(_) = client.send(Binary(msg.binary)).await {
-                break;
-            }
+    let mut notices_open = true;
+    loop {
+        let deadline = delayed.next_deadline();
+        tokio::select! {
+            // Notices go first, so a final notice is sent before the lobby closes
+            biased;
+            // Forward notices from the server, skipping any this client fell behind on
+            notice = notice_receiver.recv(), if notices_open => match notice {
+                Ok(notice) => if send(&mut client, Text(notice)).await.is_err() {
+                    break;
+                },
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => notices_open = false,
+            },
+            // Send the board states held back for a time once they are due
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                if send_board_states(&mut client, &mut updates, delayed.due()).await.is_err() {
+                    break;
+                }
+            },
+            // Send a snapshot to a client which missed an update
+            Some(()) = resync_receiver.recv() => {
+                debug!("Client asked to resync.");
+                metrics::increment(&METRICS.resyncs);
+                if send(&mut client, Binary(updates.resync())).await.is_err() {
+                    break;
+                }
+            },
+            // Wait for a game update
+            msg = receiver.recv() => match msg {
+                // Send board states to the client once they are due
+                Ok(msg) if msg.binary.len() == ConnectionProtocol::MESSAGE_SIZE => {
+                    let due = delayed.push(msg.binary);
+                    if send_board_states(&mut client, &mut updates, due).await.is_err() {
+                        break;
+                    }
+                }
+                // If any other message did not come from this client, send it to the client
+                Ok(msg) => if msg.player_num != player_num
+                    && send(&mut client, Binary(msg.binary)).await.is_err()
+                {
+                    break;
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Client fell too far behind on game updates.");
+                    break;
+                }
+                // The game is over, so nothing is held back any more
+                Err(RecvError::Closed) => {
+                    send_board_states(&mut client, &mut updates, delayed.flush())
+                        .await
+                        .unwrap_or_default();
+                    break;
+                }
+            },
         }
     }
-    println!("Exiting client writer for player {}.", player_num)
+    debug!("Exiting client writer.");
+}
+
+/// Sends each board state to the client in order, encoded by updates, stopping if one fails to send
+async fn send_board_states(
+    client: &mut ClientSink,
+    updates: &mut UpdateEncoder,
+    board_states: Vec<Vec<u8>>,
+) -> Result<(), Error> {
+    for board_state in board_states {
+        send(client, Binary(updates.encode(board_state))).await?;
+    }
+    Ok(())
+}
+
+/// Sends a message to the client, counting it if it was sent
+async fn send(client: &mut ClientSink, msg: TungsteniteMessage) -> Result<(), Error> {
+    client.send(msg).await?;
+    metrics::increment(&METRICS.messages_out);
+    Ok(())
 }
@@ -0,0 +1,248 @@
+//! delay holds game updates back from spectators, so a spectator cannot relay advice to a player
+//! about the position they are in, while players still receive every move at once
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{lobby::game_over, util::is_named_after};
+
+use board::{Bitboard, BoardEngine};
+use constants::ConnectionProtocol;
+
+use serde::Serialize;
+use tokio::time::{Duration, Instant};
+
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+/// How far behind the game spectators are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectatorDelay {
+    #[default]
+    None,
+    /// Spectators see the board as it was this many moves ago
+    Moves(usize),
+    /// Spectators see each move this many seconds after it was made
+    Seconds(u64),
+}
+
+impl SpectatorDelay {
+    /// Longest delay in seconds, a day, so the time a move is due can always be reached
+    pub const MAX_SECONDS: u64 = 24 * 60 * 60;
+}
+
+impl FromStr for SpectatorDelay {
+    type Err = String;
+
+    /// Parses "none", "<n>moves" or "<n>s", with at most MAX_SECONDS seconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let delay = if s == "none" {
+            Some(SpectatorDelay::None)
+        } else if let Some(moves) = s.strip_suffix("moves") {
+            moves.parse().ok().map(SpectatorDelay::Moves)
+        } else if let Some(seconds) = s.strip_suffix('s') {
+            seconds.parse().ok().map(SpectatorDelay::Seconds)
+        } else {
+            None
+        };
+        match delay {
+            Some(SpectatorDelay::Moves(0) | SpectatorDelay::Seconds(0)) => Ok(SpectatorDelay::None),
+            Some(SpectatorDelay::Seconds(seconds)) if seconds > Self::MAX_SECONDS => Err(format!(
+                "a delay can be at most {}s, got {}",
+                Self::MAX_SECONDS,
+                s
+            )),
+            Some(delay) => Ok(delay),
+            None => Err(format!(
+                "expected none, <n>moves or <n>s (seconds), got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SpectatorDelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpectatorDelay::None => write!(f, "none"),
+            SpectatorDelay::Moves(moves) => write!(f, "{}moves", moves),
+            SpectatorDelay::Seconds(seconds) => write!(f, "{}s", seconds),
+        }
+    }
+}
+
+/// A spectator delay for some lobbies, given on the command line as <name>=<delay>
+pub struct DelaySpec {
+    name: String,
+    delay: SpectatorDelay,
+}
+
+impl FromStr for DelaySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, delay))
+                if !name.is_empty() && ConnectionProtocol::is_valid_lobby_name(name) =>
+            {
+                Ok(Self {
+                    name: name.to_string(),
+                    delay: delay.parse()?,
+                })
+            }
+            _ => Err(format!(
+                "expected <name>=<delay>, with a name of letters, digits, - and _, got {}",
+                s
+            )),
+        }
+    }
+}
+
+/// The spectator delays set on startup, and which lobbies they apply to
+#[derive(Debug, Default)]
+pub struct SpectatorDelays {
+    /// Delays for lobbies named after them
+    lobbies: Vec<(String, SpectatorDelay)>,
+    /// Delay for every other lobby
+    default: SpectatorDelay,
+}
+
+impl SpectatorDelays {
+    pub fn new(specs: Vec<DelaySpec>, default: SpectatorDelay) -> Self {
+        Self {
+            lobbies: specs
+                .into_iter()
+                .map(|spec| (spec.name, spec.delay))
+                .collect(),
+            default,
+        }
+    }
+
+    /// Returns the delay for the lobby
+    /// Lobbies named <name> or <name>-<anything> use the delay given for that name, and other lobbies use the default
+    pub fn for_lobby(&self, lobby: &str) -> SpectatorDelay {
+        self.lobbies
+            .iter()
+            .filter(|(name, _)| is_named_after(lobby, name))
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.default, |(_, delay)| *delay)
+    }
+}
+
+/// Board states held back from a spectator, oldest first, with when each is due to be sent
+pub struct DelayedUpdates {
+    delay: SpectatorDelay,
+    queue: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl DelayedUpdates {
+    /// Starts holding back the board states of a game the spectator joined part way through
+    /// Returns the board state to show the spectator first, if it should be shown at once
+    ///
+    /// With a delay in moves, the spectator is shown the board as it was that many moves ago,
+    /// worked out from the moves played so far, and the boards since are held back
+    /// With a delay in seconds, the current board is held back like any other
+    pub fn join(delay: SpectatorDelay, board: Vec<u8>, moves: &[u8]) -> (Self, Option<Vec<u8>>) {
+        let mut updates = Self {
+            delay,
+            queue: VecDeque::new(),
+        };
+        let first = match delay {
+            _ if game_over(&board) => Some(board),
+            SpectatorDelay::None => Some(board),
+            SpectatorDelay::Moves(delay) => match past_boards(moves, delay) {
+                Some(past) => {
+                    let now = Instant::now();
+                    updates
+                        .queue
+                        .extend(past.into_iter().chain([board]).map(|board| (now, board)));
+                    updates.queue.pop_front().map(|(_, board)| board)
+                }
+                // Without the moves the board cannot be wound back, so the spectator waits for the next move
+                None => {
+                    updates.queue.push_back((Instant::now(), board));
+                    None
+                }
+            },
+            SpectatorDelay::Seconds(_) => {
+                updates.push(board);
+                None
+            }
+        };
+        (updates, first)
+    }
+
+    /// Holds back the board state, returning the board states now due to be sent, oldest first
+    /// Everything held back is sent once the game is over, as there is nothing left to give away
+    pub fn push(&mut self, board: Vec<u8>) -> Vec<Vec<u8>> {
+        let finished = game_over(&board);
+        match self.delay {
+            SpectatorDelay::None => return vec![board],
+            SpectatorDelay::Moves(_) => self.queue.push_back((Instant::now(), board)),
+            SpectatorDelay::Seconds(seconds) => self
+                .queue
+                .push_back((Instant::now() + Duration::from_secs(seconds), board)),
+        }
+        if finished {
+            return self.flush();
+        }
+        match self.delay {
+            SpectatorDelay::Moves(delay) => {
+                let due = self.queue.len().saturating_sub(delay);
+                self.queue.drain(..due).map(|(_, board)| board).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns when the next board state held back for a time is due, if there is one
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.delay {
+            SpectatorDelay::Seconds(_) => self.queue.front().map(|(due, _)| *due),
+            _ => None,
+        }
+    }
+
+    /// Returns the board states held back for a time which are now due, oldest first
+    pub fn due(&mut self) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        let due = self.queue.iter().take_while(|(due, _)| *due <= now).count();
+        self.queue.drain(..due).map(|(_, board)| board).collect()
+    }
+
+    /// Returns every board state held back, oldest first
+    pub fn flush(&mut self) -> Vec<Vec<u8>> {
+        self.queue.drain(..).map(|(_, board)| board).collect()
+    }
+}
+
+/// Returns the board states before each of the last delay moves were played, oldest first
+/// Returns None if the moves cannot be replayed
+fn past_boards(moves: &[u8], delay: usize) -> Option<Vec<Vec<u8>>> {
+    let start = moves.len().saturating_sub(delay);
+    let mut board = Bitboard::default();
+    let mut boards = Vec::new();
+    for (i, &col) in moves.iter().enumerate() {
+        if i >= start {
+            boards.push(board::encode_disks(board.disks(), i % 2 == 0, false));
+        }
+        board.drop_disk(i as u8 % 2 + 1, col).ok()?;
+    }
+    Some(boards)
+}
@@ -21,134 +21,320 @@
 
 use constants::ConnectionProtocol;
 
-#[cfg(feature = "cppintegration")]
-use crate::bindings::Board;
-use crate::Client;
+use crate::{
+    archive::GameArchive,
+    boards::{LobbyBoard, MoveError},
+    metrics::{self, METRICS},
+};
+
+use board::analysis::{ArchivedGame, GameResult};
 
 use super::{
     client_handler,
-    util::{MessageFromClient, Subtasks},
+    delay::SpectatorDelay,
+    registry::LobbyRegistry,
+    util::{ClientInfo, ClientRequest, LobbyState, MessageFromClient, NewClient},
 };
 
-#[cfg(feature = "cppintegration")]
-type Message = MessageFromClient;
-#[cfg(not(feature = "cppintegration"))]
 use super::util::Message::{self, BoardState, SpecialMessage};
 
 use tokio::{
     sync::{
         broadcast::{self, Sender as BroadcastSender},
         mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+        watch::{self, Receiver as WatchReceiver, Sender as WatchSender},
     },
     task,
 };
+use tracing::{debug, info, info_span, warn, Instrument};
 
-use std::sync::{Arc, Mutex};
+use std::{
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 /// run_lobby is the main task for each lobby and accordingly handles the lifecycle of the lobby
+/// Moves are checked with the board if there is one, otherwise players are trusted to send valid board states
+/// Games which started are written to the archive, if there is one, before the lobby is removed
 ///
 /// Async to be run as a new task whenever a lobby is created
-async fn run_lobby(
+pub async fn run_lobby(
     mut receiver: UnboundedReceiver<Message>,
     game_update_sender: BroadcastSender<MessageFromClient>,
-    subtasks: Arc<Mutex<Subtasks>>,
-    remove_lobby: Box<dyn FnOnce() -> () + Send + Sync>,
+    state_sender: WatchSender<LobbyState>,
+    mut board: Option<LobbyBoard>,
+    archive: Option<GameArchive>,
+    player_addresses: WatchReceiver<[Option<IpAddr>; 2]>,
+    remove_lobby: impl FnOnce(),
 ) {
-    #[cfg(feature = "cppintegration")]
-    let mut board = Board::default();
+    // Is player1's turn at the start of the game, unless the game was restored
+    let mut is_p1_turn = state_sender.borrow().is_p1_turn;
 
-    // Is player1's turn at the start of the game
-    let mut is_p1_turn = true;
-    // When player input is received
-    #[cfg(feature = "cppintegration")]
-    while let Some(mut msg) = receiver.recv().await {
-        let msg_byte = msg.binary[0];
-        if msg_byte == ConnectionProtocol::KILL_CONNECTION {
-            break;
-        }
-        if msg_byte == ConnectionProtocol::SECOND_PLAYER_CONNECTED {
-            game_update_sender.send(msg).unwrap_or_default();
-            continue;
-        }
-        if is_p1_turn == (msg.player_num == 1) {
-            if let Ok(game_won) = board.make_move(msg.player_num, msg_byte) {
-                is_p1_turn = !is_p1_turn;
-                msg.binary = board.to_game_update_binary(is_p1_turn, game_won);
-                task::block_in_place(|| {
-                    subtasks.lock().unwrap().last_board_state = msg.binary.clone();
-                });
-                game_update_sender.send(msg).unwrap_or_default();
-            }
+    // Moves are no longer checked once the game is won or drawn
+    let mut game_finished = game_over(&state_sender.borrow().board);
+
+    // Replay the moves of a restored game onto the board
+    if let Some(board) = &mut board {
+        let moves = state_sender.borrow().moves.clone();
+        for (i, col) in moves.into_iter().enumerate() {
+            board.drop_disk(if i % 2 == 0 { 1 } else { 2 }, col).await.ok();
         }
     }
 
-    #[cfg(not(feature = "cppintegration"))]
+    // When player input is received
     while let Some(msg) = receiver.recv().await {
-        match msg {
-            // If a message was received from the player whose turn it was, store the updated game state and send it to all clients
-            BoardState(state) => {
-                if state.binary.len() == 1 {
-                    game_update_sender.send(state).unwrap_or_default();
+        match (msg, &mut board) {
+            (BoardState(state), _) if state.binary == [ConnectionProtocol::SECOND_PLAYER_CONNECTED] => {
+                start_game(&state_sender);
+                game_update_sender.send(state).unwrap_or_default();
+            }
+            // If the player whose turn it was played a column the board allows, store the updated game state and send it to all clients
+            (BoardState(mut state), Some(board)) => {
+                let col = state.binary[0];
+                if game_finished || is_p1_turn != (state.player_num == 1) {
                     continue;
                 }
-                let expected_to_be_p1 = if ConnectionProtocol::is_undo_move(&state.binary) {
-                    !is_p1_turn
-                } else {
-                    is_p1_turn
+                let game_won = match board.drop_disk(state.player_num, col).await {
+                    Ok(game_won) => game_won,
+                    Err(MoveError::Illegal) => {
+                        debug!(player_num = state.player_num, col, "Board rejected move.");
+                        continue;
+                    }
+                    // The move cannot be checked, so it is ignored and the player can try again
+                    Err(MoveError::Failed(reason)) => {
+                        warn!(player_num = state.player_num, col, reason, "Board failed to check move.");
+                        metrics::increment(&METRICS.board_failures);
+                        continue;
+                    }
                 };
+                is_p1_turn = !is_p1_turn;
+                state.binary = board.to_game_update_binary(is_p1_turn, game_won);
+                state_sender.send_modify(|lobby_state| {
+                    lobby_state.board = state.binary.clone();
+                    lobby_state.is_p1_turn = is_p1_turn;
+                    lobby_state.moves.push(col);
+                });
+                debug!(player_num = state.player_num, col, "Made move.");
+                if game_won || board_full(&state.binary) {
+                    game_finished = true;
+                    info!(game_won, "Game finished.");
+                    metrics::increment(&METRICS.games_finished);
+                }
+                game_update_sender.send(state).unwrap_or_default();
+            }
+            // If a message was received from the player whose turn it was, store the updated game state and send it to all clients
+            (BoardState(state), None) => {
+                let is_undo = ConnectionProtocol::is_undo_move(&state.binary);
+                let expected_to_be_p1 = if is_undo { !is_p1_turn } else { is_p1_turn };
                 if expected_to_be_p1 == (state.player_num == 1) {
-                    task::block_in_place(|| {
-                        subtasks.lock().unwrap().last_board_state = state.binary.clone();
-                    });
                     is_p1_turn = !is_p1_turn;
+                    state_sender.send_modify(|lobby_state| {
+                        if is_undo {
+                            lobby_state.moves.pop();
+                        } else if let Some(col) = column_played(&lobby_state.board, &state.binary) {
+                            lobby_state.moves.push(col);
+                        }
+                        lobby_state.board = state.binary.clone();
+                        lobby_state.is_p1_turn = is_p1_turn;
+                    });
+                    debug!(player_num = state.player_num, is_undo, "Made move.");
+                    if !is_undo && game_over(&state.binary) {
+                        info!("Game finished.");
+                        metrics::increment(&METRICS.games_finished);
+                    }
                     game_update_sender.send(state).unwrap_or_default();
                 }
             }
             // Special messages at this stage means a player killed the connection
-            SpecialMessage(_) => {
+            (SpecialMessage(_), _) => {
                 break;
             }
         }
     }
 
-    // Delete this lobby and kill all tasks listening to players
-    // All writer tasks will end once the senders to them are dropped
-    task::block_in_place(move || {
-        remove_lobby();
+    if let Some(archive) = archive {
+        let state = state_sender.borrow().clone();
+        let players = *player_addresses.borrow();
+        if state.game_started && !state.moves.is_empty() {
+            archive
+                .record(ArchivedGame {
+                    result: GameResult::of(&state.moves),
+                    lobby: state.name,
+                    ended_at: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|time| time.as_secs())
+                        .unwrap_or_default(),
+                    players,
+                    moves: state.moves,
+                })
+                .await;
+        }
+    }
+
+    // Delete this lobby
+    // Dropping state_sender tells the client handler to kill all tasks listening to players,
+    // and all writer tasks will end once the senders to them are dropped
+    remove_lobby();
+    info!(moves = state_sender.borrow().moves.len(), "Ending lobby.");
+}
 
-        for subtask in &subtasks.lock().unwrap().tasks {
-            subtask.abort();
+/// Marks the game as started once both players have joined, unless it was restored already started
+fn start_game(state_sender: &WatchSender<LobbyState>) {
+    state_sender.send_if_modified(|state| {
+        if state.game_started {
+            return false;
         }
+        state.game_started = true;
+        info!("Game started.");
+        metrics::increment(&METRICS.games_started);
+        true
     });
-    println!("Ending lobby.");
 }
 
-/// create_lobby starts the run_lobby and new_client_handler tasks for the given lobby
-/// Returns a sender which can send new clients to the lobby
-pub fn create_lobby(
-    remove_lobby: Box<dyn FnOnce() -> () + Send + Sync>,
-) -> UnboundedSender<Client> {
+/// Returns whether every column of the board state is full
+fn board_full(board: &[u8]) -> bool {
+    ConnectionProtocol::decode_message(board.to_vec()).is_ok_and(|update| {
+        update.mask.count_ones() == (constants::BOARD_WIDTH * constants::BOARD_HEIGHT) as u32
+    })
+}
+
+/// Returns whether the board state is of a game that was won or ended in a draw
+pub fn game_over(board: &[u8]) -> bool {
+    ConnectionProtocol::decode_message(board.to_vec()).is_ok_and(|update| update.game_won)
+        || board_full(board)
+}
+
+/// Returns the column of the disk added between two board states, if exactly one disk was added
+fn column_played(old_board: &[u8], new_board: &[u8]) -> Option<u8> {
+    let old = ConnectionProtocol::decode_message(old_board.to_vec()).ok()?;
+    let new = ConnectionProtocol::decode_message(new_board.to_vec()).ok()?;
+    let added = new.mask & !old.mask;
+    if added.count_ones() != 1 {
+        return None;
+    }
+    Some((added.trailing_zeros() / (constants::BOARD_HEIGHT as u32 + 1)) as u8)
+}
+
+/// Handle the registry keeps for each lobby
+#[derive(Clone)]
+pub struct LobbyHandle {
+    /// Sends new clients to the lobby, along with the seat token they joined with
+    pub new_client_sender: UnboundedSender<NewClient>,
+    /// Latest state of the game in the lobby
+    pub state: WatchReceiver<LobbyState>,
+    /// Sends game updates to the clients in the lobby, kept to see how far behind they are
+    pub game_update_sender: BroadcastSender<MessageFromClient>,
+    /// Name of the board checking moves in the lobby, if moves are checked
+    pub board: Option<String>,
+    /// How far behind the game spectators are kept
+    pub spectator_delay: SpectatorDelay,
+    /// Sends messages to the main lobby task, as if from a player
+    sender: UnboundedSender<Message>,
+    /// Sends requests about the lobby's clients to the client handler
+    client_requests: UnboundedSender<ClientRequest>,
+}
+
+impl LobbyHandle {
+    /// Ends the lobby, as if a player had killed the connection
+    pub fn close(&self) {
+        self.sender
+            .send(SpecialMessage(ConnectionProtocol::KILL_CONNECTION))
+            .unwrap_or_default();
+    }
+
+    /// Gets the players and spectators currently in the lobby
+    /// Returns no clients if the lobby has ended
+    pub async fn clients(&self) -> Vec<ClientInfo> {
+        let (reply, receiver) = oneshot::channel();
+        self.client_requests
+            .send(ClientRequest::List(reply))
+            .unwrap_or_default();
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Disconnects the client with the given id, ending the game if they are a player
+    /// Returns whether the client was in the lobby
+    pub async fn kick(&self, id: u64) -> bool {
+        let (reply, receiver) = oneshot::channel();
+        self.client_requests
+            .send(ClientRequest::Kick { id, reply })
+            .unwrap_or_default();
+        receiver.await.unwrap_or_default()
+    }
+}
+
+/// create_lobby starts the run_lobby and new_client_handler tasks for a lobby with the given state,
+/// which is either a new game or one restored from a snapshot
+/// The lobby removes itself from the registry when it ends
+/// Both tasks run in a span named after the lobby, so everything logged about the game can be filtered by it
+/// Moves are checked with the board the registry's Boards chooses for the lobby's name
+/// Returns a LobbyHandle, which can send new clients to the lobby
+pub fn create_lobby(state: LobbyState, id: u64, registry: LobbyRegistry) -> LobbyHandle {
     let (sender, receiver) = mpsc::unbounded_channel();
     let (new_client_sender, new_client_receiver) = mpsc::unbounded_channel();
+    let (client_requests, client_request_receiver) = mpsc::unbounded_channel();
 
     let (game_update_sender, _) = broadcast::channel(3);
     let game_update_sender_clone = game_update_sender.clone();
+    let game_update_sender_handle = game_update_sender.clone();
 
-    let subtasks = Arc::new(Mutex::new(Subtasks::default()));
-    let subtasks_ref = Arc::clone(&subtasks);
+    // Last board state, for when new players / spectators join, and seat tokens for returning players
+    let name = state.name.clone();
+    let span = info_span!("lobby", lobby = name, id);
+    let (board_name, board) = registry.boards().new_board(&name).unzip();
+    if let Some(board_name) = &board_name {
+        span.in_scope(|| debug!(board = board_name, "Checking moves with board."));
+    }
+    let spectator_delay = registry.spectator_delays().for_lobby(&name);
+    if spectator_delay != SpectatorDelay::None {
+        span.in_scope(|| debug!(%spectator_delay, "Delaying spectators."));
+    }
+    let (state_sender, state_receiver) = watch::channel(state);
+    let (player_addresses, player_addresses_receiver) = watch::channel([None; 2]);
 
-    task::spawn(async move {
-        run_lobby(receiver, game_update_sender, subtasks, remove_lobby).await;
-    });
-    task::spawn(async move {
-        client_handler::new_client_handler(
-            sender,
-            new_client_receiver,
-            game_update_sender_clone,
-            subtasks_ref,
-        )
-        .await;
-    });
+    let registry_ref = registry.clone();
+    task::spawn(
+        async move {
+            let archive = registry_ref.archive().cloned();
+            run_lobby(
+                receiver,
+                game_update_sender,
+                state_sender,
+                board,
+                archive,
+                player_addresses_receiver,
+                move || registry_ref.remove(name, id),
+            )
+            .await;
+        }
+        .instrument(span.clone()),
+    );
+    let sender_clone = sender.clone();
+    let state_receiver_clone = state_receiver.clone();
+    task::spawn(
+        async move {
+            client_handler::new_client_handler(
+                sender_clone,
+                new_client_receiver,
+                client_request_receiver,
+                game_update_sender_clone,
+                state_receiver_clone,
+                player_addresses,
+                registry,
+            )
+            .await;
+        }
+        .instrument(span),
+    );
 
-    new_client_sender
+    LobbyHandle {
+        new_client_sender,
+        state: state_receiver,
+        game_update_sender: game_update_sender_handle,
+        board: board_name,
+        spectator_delay,
+        sender,
+        client_requests,
+    }
 }
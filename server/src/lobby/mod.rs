@@ -17,7 +17,10 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+pub mod delay;
 pub mod lobby;
+pub mod registry;
 // lobby helper functions and structs
 mod client_handler;
-mod util;
+pub mod updates;
+pub mod util;
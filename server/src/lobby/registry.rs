@@ -0,0 +1,392 @@
+//! registry contains the LobbyRegistry, which owns the map of lobbies in existence
+//!
+//! The map is only ever touched by the registry task, so joining, creating and removing lobbies
+//! never blocks a runtime worker thread; connection tasks just send requests to the registry task
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    delay::SpectatorDelays,
+    lobby::{self, LobbyHandle},
+    util::{LobbyState, NewClient},
+};
+use crate::{
+    archive::GameArchive,
+    boards::Boards,
+    limits::{AddressRateLimiter, Limits},
+};
+
+use tokio::{
+    sync::{
+        broadcast::{self, Receiver as BroadcastReceiver, Sender as BroadcastSender},
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot::{self, Sender as OneshotSender},
+        watch::{self, Receiver as WatchReceiver, Sender as WatchSender},
+    },
+    task,
+};
+
+use tracing::{info, warn};
+
+use std::{collections::HashMap, sync::Arc};
+
+/// Number of notices that can be queued for a client before older ones are skipped
+const NOTICE_CAPACITY: usize = 8;
+
+/// Requests that can be made of the registry task
+enum RegistryRequest {
+    /// Place the client into the lobby with the given name, creating the lobby if needed
+    /// A client with the seat token of a live lobby is placed into that lobby instead
+    Join {
+        lobby: String,
+        client: Box<NewClient>,
+    },
+    /// Forget the lobby with the given name and id
+    Remove { lobby: String, id: u64 },
+    /// Recreate a lobby from a snapshot
+    Restore(Box<LobbyState>),
+    /// Reply with the state of every game in progress
+    Snapshot(OneshotSender<Vec<LobbyState>>),
+    /// Reply with statistics about the lobbies, for metrics
+    Stats(OneshotSender<RegistryStats>),
+    /// Reply with a handle to every lobby along with its id, for the admin API
+    Lobbies(OneshotSender<Vec<(u64, LobbyHandle)>>),
+    /// Stop placing clients into lobbies
+    StopJoins,
+    /// End every lobby
+    CloseAll,
+}
+
+/// Statistics about a single lobby
+pub struct LobbyStats {
+    pub id: u64,
+    pub name: String,
+    /// Game updates the slowest client in the lobby has yet to be sent
+    pub lag: usize,
+}
+
+/// Statistics about the registry and its lobbies
+pub struct RegistryStats {
+    pub accepting_joins: bool,
+    pub lobbies: Vec<LobbyStats>,
+}
+
+/// Handle to the registry task, cheap to clone and share between connection and lobby tasks
+#[derive(Clone)]
+pub struct LobbyRegistry {
+    sender: UnboundedSender<RegistryRequest>,
+    notice_sender: BroadcastSender<String>,
+    live_lobbies: WatchReceiver<usize>,
+    limits: Limits,
+    boards: Arc<Boards>,
+    archive: Option<GameArchive>,
+    spectator_delays: Arc<SpectatorDelays>,
+}
+
+impl LobbyRegistry {
+    /// Spawns the registry task, which holds the lobbies to the given limits and creates them
+    /// with the given boards, archive and spectator delays, and returns a handle to it
+    pub fn spawn(
+        limits: Limits,
+        boards: Boards,
+        archive: Option<GameArchive>,
+        spectator_delays: SpectatorDelays,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (notice_sender, _) = broadcast::channel(NOTICE_CAPACITY);
+        let (live_lobbies_sender, live_lobbies) = watch::channel(0);
+        let registry = Self {
+            sender,
+            notice_sender,
+            live_lobbies,
+            limits,
+            boards: Arc::new(boards),
+            archive,
+            spectator_delays: Arc::new(spectator_delays),
+        };
+        let registry_ref = registry.clone();
+        task::spawn(async move {
+            run_registry(receiver, live_lobbies_sender, registry_ref).await;
+        });
+        registry
+    }
+
+    /// Sends the client to the lobby with the given name, which is created if it does not exist
+    /// If the client has the seat token of a live lobby, it is sent to that lobby instead
+    pub fn join(&self, lobby: String, client: NewClient) {
+        self.sender
+            .send(RegistryRequest::Join {
+                lobby,
+                client: Box::new(client),
+            })
+            .unwrap_or_default();
+    }
+
+    /// Recreates a lobby saved in a snapshot, which waits for its players to return
+    pub fn restore(&self, state: LobbyState) {
+        self.sender
+            .send(RegistryRequest::Restore(Box::new(state)))
+            .unwrap_or_default();
+    }
+
+    /// Returns the state of every game in progress, to be saved in a snapshot
+    pub async fn snapshot(&self) -> Vec<LobbyState> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(RegistryRequest::Snapshot(sender))
+            .unwrap_or_default();
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Returns statistics about the lobbies, or never resolves if the registry task has stopped
+    pub async fn stats(&self) -> RegistryStats {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(RegistryRequest::Stats(sender))
+            .unwrap_or_default();
+        match receiver.await {
+            Ok(stats) => stats,
+            Err(_) => std::future::pending().await,
+        }
+    }
+
+    /// Returns a handle to every lobby which has not yet ended along with its id, ordered by id
+    pub async fn lobbies(&self) -> Vec<(u64, LobbyHandle)> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender
+            .send(RegistryRequest::Lobbies(sender))
+            .unwrap_or_default();
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Removes the lobby with the given name and id
+    pub fn remove(&self, lobby: String, id: u64) {
+        self.sender
+            .send(RegistryRequest::Remove { lobby, id })
+            .unwrap_or_default();
+    }
+
+    /// Clients sent to the registry after this are disconnected rather than placed into lobbies
+    pub fn stop_joins(&self) {
+        self.sender
+            .send(RegistryRequest::StopJoins)
+            .unwrap_or_default();
+    }
+
+    /// Ends every lobby in existence
+    pub fn close_all(&self) {
+        self.sender
+            .send(RegistryRequest::CloseAll)
+            .unwrap_or_default();
+    }
+
+    /// Sends a notice to be displayed by every client in a lobby
+    pub fn notify_all(&self, notice: String) {
+        self.notice_sender.send(notice).unwrap_or_default();
+    }
+
+    /// Returns a receiver of the notices sent with notify_all
+    pub fn subscribe_notices(&self) -> BroadcastReceiver<String> {
+        self.notice_sender.subscribe()
+    }
+
+    /// Returns a receiver of the number of lobbies that have not yet ended
+    pub fn live_lobbies(&self) -> WatchReceiver<usize> {
+        self.live_lobbies.clone()
+    }
+
+    /// Returns the limits the server was started with
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Returns the boards lobbies check moves with
+    pub fn boards(&self) -> &Boards {
+        &self.boards
+    }
+
+    /// Returns how far behind the game spectators of each lobby are kept
+    pub fn spectator_delays(&self) -> &SpectatorDelays {
+        &self.spectator_delays
+    }
+
+    /// Returns the archive finished games are written to, if there is one
+    pub fn archive(&self) -> Option<&GameArchive> {
+        self.archive.as_ref()
+    }
+}
+
+/// run_registry is the only task with access to the lobbies, and handles requests in order
+///
+/// Async to be run as a single task for the lifetime of the server
+async fn run_registry(
+    mut receiver: UnboundedReceiver<RegistryRequest>,
+    live_lobbies: WatchSender<usize>,
+    registry: LobbyRegistry,
+) {
+    // Every lobby which has not yet ended
+    let mut lobbies: HashMap<u64, LobbyHandle> = HashMap::new();
+    // Lobbies which can be joined by name
+    let mut names: HashMap<String, u64> = HashMap::new();
+    // Lobbies which can be rejoined with a seat token
+    let mut seats: HashMap<u64, u64> = HashMap::new();
+    let mut next_id = 0u64;
+    let mut accepting_joins = true;
+    // How recently each address created a lobby
+    let mut lobby_creations = AddressRateLimiter::per_minute(registry.limits.lobbies_per_minute);
+
+    while let Some(request) = receiver.recv().await {
+        match request {
+            RegistryRequest::Join { lobby, client } => {
+                if !accepting_joins {
+                    info!(lobby, "Turned client away, the server is shutting down.");
+                    task::spawn(async move {
+                        client
+                            .reject("The server is shutting down. Try again later.")
+                            .await;
+                    });
+                    continue;
+                }
+                let mut client = *client;
+                // Send a returning player back to the lobby of their seat
+                if let Some((id, handle)) = client
+                    .seat_token
+                    .and_then(|token| seats.get(&token))
+                    .and_then(|id| Some((*id, lobbies.get(id)?)))
+                {
+                    match handle.new_client_sender.send(client) {
+                        Ok(()) => {
+                            info!(lobby, id, "Returned player to lobby.");
+                            continue;
+                        }
+                        Err(returned) => client = returned.0,
+                    }
+                }
+                // Send the player to the lobby if it already exists
+                let client = match names.get(&lobby).and_then(|id| Some((*id, lobbies.get(id)?))) {
+                    Some((id, handle)) => match handle.new_client_sender.send(client) {
+                        Ok(()) => {
+                            // The default lobby is only ever used to pair up two players
+                            if lobby.is_empty() {
+                                names.remove(&lobby);
+                            }
+                            info!(lobby, id, "Sent player to lobby.");
+                            continue;
+                        }
+                        // The lobby is closing but has not yet removed itself, so replace it
+                        Err(returned) => returned.0,
+                    },
+                    None => client,
+                };
+
+                // Create a new lobby and send the player to it, unless that would exceed a limit
+                if lobbies.len() >= registry.limits.max_lobbies {
+                    warn!(lobby, "Turned client away, too many lobbies.");
+                    task::spawn(async move {
+                        client.reject("The server is full. Try again later.").await;
+                    });
+                    continue;
+                }
+                if !lobby_creations.allow(client.peer.ip()) {
+                    warn!(lobby, peer = %client.peer, "Turned client away, creating lobbies too quickly.");
+                    task::spawn(async move {
+                        client
+                            .reject("You are creating lobbies too quickly. Try again in a minute.")
+                            .await;
+                    });
+                    continue;
+                }
+                let id = next_id;
+                next_id += 1;
+                let handle =
+                    lobby::create_lobby(LobbyState::new(lobby.clone()), id, registry.clone());
+                handle.new_client_sender.send(client).unwrap_or_default();
+                for token in handle.state.borrow().seat_tokens {
+                    seats.insert(token, id);
+                }
+                lobbies.insert(id, handle);
+                info!(lobby, id, "Created lobby.");
+                names.insert(lobby, id);
+            }
+            RegistryRequest::Remove { lobby, id } => {
+                if let Some(handle) = lobbies.remove(&id) {
+                    for token in handle.state.borrow().seat_tokens {
+                        seats.remove(&token);
+                    }
+                }
+                if names.get(&lobby) == Some(&id) {
+                    names.remove(&lobby);
+                }
+            }
+            RegistryRequest::Restore(state) => {
+                let id = next_id;
+                next_id += 1;
+                // Restored games already have both players, so the default lobby cannot be joined by name
+                if !state.name.is_empty() {
+                    names.insert(state.name.clone(), id);
+                }
+                for token in state.seat_tokens {
+                    seats.insert(token, id);
+                }
+                info!(lobby = state.name, id, moves = state.moves.len(), "Restored lobby.");
+                lobbies.insert(id, lobby::create_lobby(*state, id, registry.clone()));
+            }
+            RegistryRequest::Snapshot(reply) => {
+                let states = lobbies
+                    .values()
+                    .map(|handle| handle.state.borrow().clone())
+                    .filter(|state| state.game_started)
+                    .collect();
+                reply.send(states).unwrap_or_default();
+            }
+            RegistryRequest::Stats(reply) => {
+                let lobbies = lobbies
+                    .iter()
+                    .map(|(id, handle)| LobbyStats {
+                        id: *id,
+                        name: handle.state.borrow().name.clone(),
+                        lag: handle.game_update_sender.len(),
+                    })
+                    .collect();
+                reply
+                    .send(RegistryStats {
+                        accepting_joins,
+                        lobbies,
+                    })
+                    .unwrap_or_default();
+            }
+            RegistryRequest::Lobbies(reply) => {
+                let mut handles: Vec<(u64, LobbyHandle)> = lobbies
+                    .iter()
+                    .map(|(id, handle)| (*id, handle.clone()))
+                    .collect();
+                handles.sort_unstable_by_key(|(id, _)| *id);
+                reply.send(handles).unwrap_or_default();
+            }
+            RegistryRequest::StopJoins => accepting_joins = false,
+            RegistryRequest::CloseAll => {
+                for handle in lobbies.values() {
+                    handle.close();
+                }
+            }
+        }
+        live_lobbies.send_replace(lobbies.len());
+    }
+}
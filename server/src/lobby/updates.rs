@@ -0,0 +1,77 @@
+//! updates turns the board states sent to a client into sequenced messages: the move which changed
+//! the client's board where there is one, and a checksummed snapshot of the whole board otherwise
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use constants::{ConnectionProtocol, GameUpdate};
+
+/// Every this many messages a snapshot is sent instead of a move, so a client whose board went wrong
+/// without it noticing is put right
+const SNAPSHOT_INTERVAL: u16 = 8;
+
+/// Numbers the board states sent to one client, remembering the last one to work out the next move
+pub struct UpdateEncoder {
+    /// Sequence number of the last message sent
+    seq: u16,
+    /// Last board state sent, which the client should now have
+    last: Vec<u8>,
+}
+
+/// An encoder for a client which has just joined, and so has an empty board
+impl Default for UpdateEncoder {
+    fn default() -> Self {
+        Self {
+            seq: 0,
+            last: ConnectionProtocol::encode_message(GameUpdate::default()),
+        }
+    }
+}
+
+impl UpdateEncoder {
+    /// Returns the message to send the board state as: a move if a single move changes the client's
+    /// board into it, or a snapshot if not, or if one is due
+    pub fn encode(&mut self, board: Vec<u8>) -> Vec<u8> {
+        self.seq = self.seq.wrapping_add(1);
+        let delta = decode(&self.last).and_then(|last| last.delta_to(&decode(&board)?));
+        self.last = board;
+        match delta {
+            Some(delta) if !self.seq.is_multiple_of(SNAPSHOT_INTERVAL) => {
+                ConnectionProtocol::encode_move(self.seq, delta)
+            }
+            _ => ConnectionProtocol::encode_snapshot(self.seq, &self.last),
+        }
+    }
+
+    /// Returns a snapshot of the board state, whatever the client's board is
+    pub fn snapshot(&mut self, board: Vec<u8>) -> Vec<u8> {
+        self.seq = self.seq.wrapping_add(1);
+        self.last = board;
+        ConnectionProtocol::encode_snapshot(self.seq, &self.last)
+    }
+
+    /// Returns a snapshot of the last board state sent, for a client which missed an update
+    pub fn resync(&mut self) -> Vec<u8> {
+        self.snapshot(self.last.clone())
+    }
+}
+
+/// Decodes a board state made by ConnectionProtocol::encode_message
+fn decode(board: &[u8]) -> Option<GameUpdate> {
+    ConnectionProtocol::decode_message(board.to_vec()).ok()
+}
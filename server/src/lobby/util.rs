@@ -19,38 +19,145 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use constants::ConnectionProtocol;
+use crate::{
+    limits::ConnectionGuard,
+    metrics::{self, METRICS},
+    Client,
+};
 
-use tokio::task::JoinHandle;
+use self::Message::{BoardState, SpecialMessage};
 
-/// Struct to store handles to client reader tasks (so they can be killed when the lobby closes)
-/// as well as the last board state (for when new players / spectators join)
-pub struct Subtasks {
-    pub tasks: Vec<JoinHandle<()>>,
-    pub last_board_state: Vec<u8>,
-}
+use constants::ConnectionProtocol;
 
-/// Default, last_board_state is a board at the start of the match
-impl Default for Subtasks {
-    fn default() -> Self {
-        Subtasks {
-            tasks: Vec::new(),
-            last_board_state: vec![0; ConnectionProtocol::MESSAGE_SIZE],
-        }
-    }
-}
+#[cfg(not(feature = "use-certificate"))]
+use futures::SinkExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot::Sender as OneshotSender;
+use tokio_tungstenite::tungstenite::Message::{Close, Text};
+
+use std::net::SocketAddr;
 
 /// Message from the client, usually to be sent to other clients
-#[cfg(not(feature = "cppintegration"))]
 #[derive(Debug)]
 pub enum Message {
     BoardState(MessageFromClient),
     SpecialMessage(u8),
 }
 
-/// Message from the client that is ConnectionProtocol::MESSAGE_SIZE bytes
+/// Message from the client that is ConnectionProtocol::MESSAGE_SIZE bytes,
+/// or a single byte column when the server checks moves
 #[derive(Debug, Clone)]
 pub struct MessageFromClient {
     pub binary: Vec<u8>,
     pub player_num: u8,
 }
+
+/// What a binary message from a player asks for
+pub enum PlayerRequest {
+    /// A message for the main lobby task
+    Lobby(Message),
+    /// A snapshot of the board, for a player which missed a game update
+    Resync,
+    /// Anything else, which does not follow ConnectionProtocol
+    Unrecognized,
+}
+
+impl PlayerRequest {
+    /// Works out what a binary message from the player asks for
+    /// Players send the columns they play when moves are checked, and board states otherwise
+    pub fn parse(binary: Vec<u8>, player_num: u8, validate_moves: bool) -> Self {
+        if binary == [ConnectionProtocol::RESYNC] {
+            PlayerRequest::Resync
+        } else if validate_moves
+            && binary.len() == 1
+            && binary[0] != ConnectionProtocol::KILL_CONNECTION
+            && binary[0] != ConnectionProtocol::SECOND_PLAYER_CONNECTED
+        {
+            PlayerRequest::Lobby(BoardState(MessageFromClient { binary, player_num }))
+        } else if binary.len() == 1 {
+            PlayerRequest::Lobby(SpecialMessage(binary[0]))
+        } else if !validate_moves && binary.len() == ConnectionProtocol::MESSAGE_SIZE {
+            PlayerRequest::Lobby(BoardState(MessageFromClient { binary, player_num }))
+        } else {
+            PlayerRequest::Unrecognized
+        }
+    }
+}
+
+/// Client sent to a lobby, along with what the lobby needs to know about it
+pub struct NewClient {
+    pub client: Client,
+    /// Address the client connected from
+    pub peer: SocketAddr,
+    /// Token of the seat the client held before being disconnected, if any
+    pub seat_token: Option<u64>,
+    /// Counts the connection against the limit for its address until the client leaves
+    pub connection: ConnectionGuard,
+}
+
+impl NewClient {
+    /// Tells the client why it was turned away, then closes the connection
+    pub async fn reject(mut self, notice: &str) {
+        metrics::increment(&METRICS.rejected_clients);
+        if self.client.send(Text(notice.to_string())).await.is_ok() {
+            metrics::increment(&METRICS.messages_out);
+        }
+        self.client.send(Close(None)).await.unwrap_or_default();
+    }
+}
+
+/// A client connected to a lobby, as seen by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    /// Identifies the client within its lobby
+    pub id: u64,
+    pub peer: SocketAddr,
+    /// 1 or 2 for players, 0 for spectators
+    pub player_num: u8,
+}
+
+/// Requests the admin API can make of the client handler of a lobby
+pub enum ClientRequest {
+    /// Reply with every client still connected to the lobby
+    List(OneshotSender<Vec<ClientInfo>>),
+    /// Disconnect the client with the given id, replying with whether it was found
+    /// Kicking a player ends the game, as if they had left
+    Kick { id: u64, reply: OneshotSender<bool> },
+}
+
+/// Returns whether the lobby is named <name> or <name>-<anything>, and so uses what was set for that name
+pub fn is_named_after(lobby: &str, name: &str) -> bool {
+    lobby
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+/// State of a game in a lobby, which is published by the main lobby task and saved in snapshots
+/// so the game can be resumed after the server restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LobbyState {
+    pub name: String,
+    /// Last board state sent to clients, ConnectionProtocol::MESSAGE_SIZE bytes
+    pub board: Vec<u8>,
+    pub is_p1_turn: bool,
+    /// Columns played so far, in order
+    pub moves: Vec<u8>,
+    /// Tokens which let players reclaim their seats after reconnecting
+    pub seat_tokens: [u64; 2],
+    /// Whether both players have joined, games which never started are not worth restoring
+    pub game_started: bool,
+}
+
+impl LobbyState {
+    /// Creates the state of a new lobby, with an empty board and new seat tokens
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            board: vec![0; ConnectionProtocol::MESSAGE_SIZE],
+            is_p1_turn: true,
+            moves: Vec::new(),
+            seat_tokens: [rand::random(), rand::random()],
+            game_started: false,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! logging sets up where and how the server's tracing events and spans are written
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use tracing_subscriber::EnvFilter;
+
+use std::str::FromStr;
+
+/// Level of events logged when RUST_LOG is not set
+const DEFAULT_FILTER: &str = "info";
+
+/// Format logs are written to stdout in
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    /// One readable line per event
+    Human,
+    /// One JSON object per event, including the fields of the spans it is in
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format {}, expected human or json", s)),
+        }
+    }
+}
+
+/// Starts writing logs in the given format, filtered by the RUST_LOG environment variable
+/// (e.g. RUST_LOG=debug, or RUST_LOG=server::lobby=debug for just the lobbies)
+pub fn init(format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Human => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(false).init(),
+    }
+}
@@ -20,9 +20,9 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+use argh::FromArgs;
 #[cfg(feature = "use-certificate")]
 use {
-    argh::FromArgs,
     rustls_pemfile::{certs, rsa_private_keys},
     tokio_rustls::{
         rustls::{self, Certificate, PrivateKey},
@@ -32,51 +32,125 @@ use {
         fs::File,
         io::{self, BufReader},
         net::{SocketAddr, ToSocketAddrs},
-        path::{Path, PathBuf},
+        path::Path,
+        sync::Arc,
     }
 };
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
-use tokio::{
-    net::TcpListener,
-    sync::mpsc::UnboundedSender,
-};
 #[cfg(not(feature = "use-certificate"))]
-use {
-    tokio::net::TcpStream,
-    tokio_tungstenite::WebSocketStream
-};
+use std::net::ToSocketAddrs;
+use std::{env, path::PathBuf};
+use tokio::{net::TcpListener, time::Duration};
+use tracing::{debug, info, info_span, warn, Instrument};
 
-#[cfg(feature = "cppintegration")]
-mod bindings;
-#[cfg(feature = "use-certificate")]
-mod tlsclient;
-mod connection;
-mod lobby;
-
-#[cfg(feature = "use-certificate")]
-type Client = tlsclient::TlsClient;
-#[cfg(not(feature = "use-certificate"))]
-type Client = WebSocketStream<TcpStream>;
-type Lobbies = HashMap<String, UnboundedSender<Client>>;
+use server::{
+    admin, archive, boards, connection, limits, lobby, logging, metrics, persistence, sandbox,
+    shutdown,
+};
 
 /// Command line options
-#[cfg(feature = "use-certificate")]
 #[derive(FromArgs)]
 struct CLIOptions {
     /// address to bind to
-    #[argh(positional)]
+    #[argh(positional, default = "String::from(\"127.0.0.1:8081\")")]
     address: String,
 
     /// certificate file
+    #[cfg(feature = "use-certificate")]
     #[argh(option, short = 'c')]
     certificate: PathBuf,
 
     /// key file
+    #[cfg(feature = "use-certificate")]
     #[argh(option, short = 'k')]
     key: PathBuf,
+
+    /// seconds to warn clients for before shutting down (default 30)
+    #[argh(option, default = "30")]
+    shutdown_countdown: u64,
+
+    /// let games in progress finish before shutting down, rather than ending them after the countdown
+    #[argh(switch)]
+    drain_games: bool,
+
+    /// file to save games in progress to, and restore them from on startup
+    #[argh(option)]
+    snapshot_file: Option<PathBuf>,
+
+    /// seconds between saving snapshots of games in progress (default 10)
+    #[argh(option, default = "10")]
+    snapshot_interval: u64,
+
+    /// address to serve metrics and health checks on over HTTP
+    #[argh(option)]
+    metrics_address: Option<String>,
+
+    /// address to serve the admin API on over HTTP, authenticated with the ADMIN_TOKEN environment variable
+    #[argh(option)]
+    admin_address: Option<String>,
+
+    /// connections each address can have open at once (default 16)
+    #[argh(option, default = "16")]
+    connections_per_address: usize,
+
+    /// connections each address can open per minute (default 60)
+    #[argh(option, default = "60")]
+    connections_per_minute: u32,
+
+    /// lobbies each address can create per minute (default 10)
+    #[argh(option, default = "10")]
+    lobbies_per_minute: u32,
+
+    /// messages each client can send per second before being disconnected (default 10)
+    #[argh(option, default = "10")]
+    messages_per_second: u32,
+
+    /// lobbies which can exist at once (default 1000)
+    #[argh(option, default = "1000")]
+    max_lobbies: usize,
+
+    /// clients which can spectate at once, across every lobby (default 1000)
+    #[argh(option, default = "1000")]
+    max_spectators: u64,
+
+    /// check moves on the server, rather than trusting the board states clients send
+    /// (always on if a board plugin is given)
+    #[argh(switch)]
+    validate_moves: bool,
+
+    /// board plugin (a C++ board built as a shared library) to check moves with, as <name>=<path>;
+    /// lobbies named <name> or <name>-<anything> use it (can be repeated)
+    #[argh(option)]
+    board_plugin: Vec<boards::PluginSpec>,
+
+    /// board plugin used by lobbies not named after one (default builtin)
+    #[argh(option)]
+    default_board: Option<String>,
+
+    /// board-host program to run board plugins in, each lobby in its own child process,
+    /// rather than loading them into the server
+    #[argh(option)]
+    board_host: Option<PathBuf>,
+
+    /// milliseconds a sandboxed board has to answer each move before it is restarted (default 1000)
+    #[argh(option, default = "1000")]
+    board_timeout_ms: u64,
+
+    /// file to append every game played to, for board-analyze to look for engine assistance in
+    #[argh(option)]
+    game_archive: Option<PathBuf>,
+
+    /// how far behind the game spectators are kept: none, <n>moves or <n>s (seconds) (default none)
+    #[argh(option, default = "lobby::delay::SpectatorDelay::None")]
+    spectator_delay: lobby::delay::SpectatorDelay,
+
+    /// spectator delay for some lobbies, as <name>=<delay>; lobbies named <name> or <name>-<anything>
+    /// use it instead of --spectator-delay (can be repeated)
+    #[argh(option)]
+    lobby_spectator_delay: Vec<lobby::delay::DelaySpec>,
+
+    /// format to write logs in, human or json (default human)
+    #[argh(option, default = "logging::LogFormat::Human")]
+    log_format: logging::LogFormat,
 }
 
 /// Loads in a certificate stored in a file found at the given path
@@ -97,8 +171,7 @@ fn load_keys(path: &Path) -> io::Result<Vec<PrivateKey>> {
 
 /// Returns an address and TlsAcceptor after reading in the certificate and keys as determined by CLI arguments
 #[cfg(feature = "use-certificate")]
-fn get_address_and_tlsacceptor() -> Result<(SocketAddr, TlsAcceptor), std::io::Error> {
-    let cli_options: CLIOptions = argh::from_env();
+fn get_address_and_tlsacceptor(cli_options: &CLIOptions) -> Result<(SocketAddr, TlsAcceptor), std::io::Error> {
     let address = cli_options
         .address
         .to_socket_addrs()?
@@ -106,10 +179,10 @@ fn get_address_and_tlsacceptor() -> Result<(SocketAddr, TlsAcceptor), std::io::E
         .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
     let certificates = load_certs(&cli_options.certificate)?;
     let mut keys = load_keys(&cli_options.key)?;
-    println!(
-        "Successfully loaded {} certificates and {} keys.",
-        certificates.len(),
-        keys.len()
+    info!(
+        certificates = certificates.len(),
+        keys = keys.len(),
+        "Successfully loaded certificates and keys."
     );
 
     let config = rustls::ServerConfig::builder()
@@ -121,41 +194,156 @@ fn get_address_and_tlsacceptor() -> Result<(SocketAddr, TlsAcceptor), std::io::E
 }
 
 /// Main loop: listens for connection requests, and creates a task to handle each requests
+/// On SIGINT / SIGTERM, stops listening and shuts down the lobbies before exiting
 /// Uses a multithreaded asynchronous runtime
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    #[cfg(feature = "cppintegration")]
-    println!("C++ integration enabled.");
-    #[cfg(not(feature = "cppintegration"))]
-    println!("C++ integration disabled.");
+    let cli_options: CLIOptions = argh::from_env();
+    logging::init(cli_options.log_format);
 
     #[cfg(feature = "use-certificate")]
-    let (address, acceptor) = get_address_and_tlsacceptor()?;
+    let (address, acceptor) = get_address_and_tlsacceptor(&cli_options)?;
 
     #[cfg(not(feature = "use-certificate"))]
-    let address = "127.0.0.1:8081";
+    let address = cli_options.address.as_str();
 
     let listener = TcpListener::bind(&address).await?;
-    println!("Listening on {}", address);
+    info!(%address, "Listening.");
+
+    // Limits on clients, and the boards lobbies check moves with
+    let limits = limits::Limits {
+        connections_per_address: cli_options.connections_per_address,
+        connections_per_minute: cli_options.connections_per_minute,
+        lobbies_per_minute: cli_options.lobbies_per_minute,
+        messages_per_second: cli_options.messages_per_second,
+        max_lobbies: cli_options.max_lobbies,
+        max_spectators: cli_options.max_spectators,
+    };
+    info!(?limits, "Limiting clients.");
+    let connection_limiter = limits::ConnectionLimiter::new(limits);
+    let board_host = cli_options.board_host.map(|program| {
+        sandbox::BoardHost::new(program, Duration::from_millis(cli_options.board_timeout_ms))
+    });
+    let boards = boards::Boards::load(
+        cli_options.board_plugin,
+        cli_options.default_board,
+        cli_options.validate_moves,
+        board_host,
+    )
+    .await;
+    info!(validate_moves = boards.validates_moves(), "Loaded boards.");
+
+    // Every game played is written to the archive, if there is one
+    let archive = match &cli_options.game_archive {
+        Some(path) => {
+            info!(path = %path.display(), "Archiving games.");
+            Some(archive::GameArchive::open(path)?)
+        }
+        None => None,
+    };
 
-    // "Global" storage of the lobbies in existence
-    let lobbies = Arc::new(Mutex::new(Lobbies::new()));
+    let spectator_delays = lobby::delay::SpectatorDelays::new(
+        cli_options.lobby_spectator_delay,
+        cli_options.spectator_delay,
+    );
+    info!(?spectator_delays, "Delaying spectators.");
+
+    // Task which owns the lobbies in existence
+    let registry =
+        lobby::registry::LobbyRegistry::spawn(limits, boards, archive, spectator_delays);
+
+    // Serve metrics and health checks alongside the websocket listener
+    if let Some(metrics_address) = &cli_options.metrics_address {
+        let metrics_listener = TcpListener::bind(metrics_address).await?;
+        info!(address = metrics_address, "Serving metrics.");
+        tokio::spawn(metrics::serve(metrics_listener, registry.clone()));
+    }
+
+    // Serve the admin API, which can block addresses from connecting
+    let blocklist = admin::Blocklist::default();
+    if let Some(admin_address) = &cli_options.admin_address {
+        let token = env::var("ADMIN_TOKEN").unwrap_or_default();
+        if token.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "ADMIN_TOKEN must be set to serve the admin API",
+            ));
+        }
+        let admin_listener = TcpListener::bind(admin_address).await?;
+        if admin_address
+            .to_socket_addrs()?
+            .any(|address| !address.ip().is_loopback())
+        {
+            warn!(address = admin_address, "Admin API is reachable from other machines.");
+        }
+        info!(address = admin_address, "Serving admin API.");
+        tokio::spawn(admin::serve(admin_listener, registry.clone(), blocklist.clone(), token));
+    }
+
+    // Restore the games saved before the server last stopped, and keep saving them
+    let snapshots = cli_options.snapshot_file.clone().map(|path| {
+        let states = persistence::load(&path);
+        info!(games = states.len(), "Restoring games.");
+        for state in states {
+            registry.restore(state);
+        }
+        persistence::SnapshotTask::spawn(
+            registry.clone(),
+            path,
+            Duration::from_secs(cli_options.snapshot_interval.max(1)),
+        )
+    });
+
+    let shutdown_signal = shutdown::signal();
+    tokio::pin!(shutdown_signal);
 
     loop {
         // Wait for new connection requests
-        let (incoming, _) = listener.accept().await?;
+        let (incoming, peer) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = &mut shutdown_signal => break,
+        };
+        // Drop connections from blocked addresses
+        if blocklist.is_blocked(peer.ip()) {
+            debug!(%peer, "Refused connection from blocked address.");
+            continue;
+        }
+        // Drop connections from addresses which have too many open or opened too many recently
+        let connection = match connection_limiter.admit(peer.ip()) {
+            Some(connection) => connection,
+            None => {
+                debug!(%peer, "Refused connection, address exceeded its connection limits.");
+                metrics::increment(&metrics::METRICS.rejected_clients);
+                continue;
+            }
+        };
         // Handle the request
-        let lobbies = Arc::clone(&lobbies);
+        let registry = registry.clone();
         #[cfg(feature = "use-certificate")]
         let args = {
-            (acceptor.clone(), incoming, lobbies)
+            (acceptor.clone(), incoming, peer, connection, registry)
         };
         #[cfg(not(feature = "use-certificate"))]
-        let args = (incoming, lobbies);
-        tokio::spawn(async move {
-            if let Err(e) = connection::handle_connection(args).await {
-                println!("Client failed to connect with {}", e);
+        let args = (incoming, peer, connection, registry);
+        tokio::spawn(
+            async move {
+                if let Err(e) = connection::handle_connection(args).await {
+                    metrics::increment(&metrics::METRICS.protocol_errors);
+                    warn!(error = %e, "Client failed to connect.");
+                }
             }
-        });
+            .instrument(info_span!("connection", peer = %peer)),
+        );
     }
+
+    // Stop accepting new connections, then warn clients and close the lobbies
+    drop(listener);
+    shutdown::shut_down(
+        registry,
+        cli_options.shutdown_countdown,
+        cli_options.drain_games,
+        snapshots,
+    )
+    .await;
+    Ok(())
 }
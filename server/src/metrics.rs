@@ -0,0 +1,261 @@
+//! metrics contains the counters and gauges describing what the server is doing, and a small HTTP
+//! server exposing them in the Prometheus text format along with liveness and readiness checks
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    http,
+    lobby::registry::{LobbyRegistry, RegistryStats},
+};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::{self, Duration},
+};
+use tracing::warn;
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// How long the registry has to answer before the server is considered not alive
+const REGISTRY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Counters and gauges updated by the connection and lobby tasks
+pub struct Metrics {
+    /// Websocket connections accepted
+    pub connections: AtomicU64,
+    /// Clients currently in a lobby, as players or spectators
+    pub active_connections: AtomicU64,
+    /// Clients currently spectating a lobby
+    pub spectators: AtomicU64,
+    pub games_started: AtomicU64,
+    /// Games which were won or ended in a draw
+    pub games_finished: AtomicU64,
+    /// Websocket messages received from clients
+    pub messages_in: AtomicU64,
+    /// Websocket messages sent to clients
+    pub messages_out: AtomicU64,
+    /// Failed handshakes and messages which did not follow ConnectionProtocol
+    pub protocol_errors: AtomicU64,
+    /// Connections and clients turned away or disconnected for exceeding a limit
+    pub rejected_clients: AtomicU64,
+    /// Moves which could not be checked because the board crashed, hung or misbehaved
+    pub board_failures: AtomicU64,
+    /// Snapshots sent to clients which missed a game update and asked to resync
+    pub resyncs: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    connections: AtomicU64::new(0),
+    active_connections: AtomicU64::new(0),
+    spectators: AtomicU64::new(0),
+    games_started: AtomicU64::new(0),
+    games_finished: AtomicU64::new(0),
+    messages_in: AtomicU64::new(0),
+    messages_out: AtomicU64::new(0),
+    protocol_errors: AtomicU64::new(0),
+    rejected_clients: AtomicU64::new(0),
+    board_failures: AtomicU64::new(0),
+    resyncs: AtomicU64::new(0),
+};
+
+/// Adds one to a counter
+pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the current value of a counter or gauge
+pub fn load(value: &AtomicU64) -> u64 {
+    value.load(Ordering::Relaxed)
+}
+
+/// Adds one to a gauge while held, and subtracts it again when dropped
+/// Held by tasks so the gauge stays correct however the task ends, including being aborted
+pub struct GaugeGuard(&'static AtomicU64);
+
+impl GaugeGuard {
+    pub fn new(gauge: &'static AtomicU64) -> Self {
+        increment(gauge);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// serve answers HTTP requests for:
+///     /metrics: every metric in the Prometheus text format
+///     /healthz: liveness, OK while the registry task is responding
+///     /readyz: readiness, OK while the server is placing clients into lobbies
+///
+/// Async to be run as a single task for the lifetime of the server
+pub async fn serve(listener: TcpListener, registry: LobbyRegistry) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!(error = %e, "Failed to accept metrics request.");
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            handle_request(stream, registry).await;
+        });
+    }
+}
+
+/// Reads a single HTTP request and writes the response, then closes the connection
+async fn handle_request(mut stream: TcpStream, registry: LobbyRegistry) {
+    let request = match http::read_request(&mut stream).await {
+        Some(request) => request,
+        None => return,
+    };
+
+    let stats = time::timeout(REGISTRY_TIMEOUT, registry.stats()).await.ok();
+    let (status, body) = match (request.method.as_str(), request.path.as_str(), stats) {
+        ("GET", "/metrics", Some(stats)) => ("200 OK", render(&registry, &stats)),
+        ("GET", "/metrics", None) => {
+            ("503 Service Unavailable", "registry not responding\n".to_string())
+        }
+        ("GET", "/healthz", Some(_)) => ("200 OK", "ok\n".to_string()),
+        ("GET", "/healthz", None) => {
+            ("503 Service Unavailable", "registry not responding\n".to_string())
+        }
+        ("GET", "/readyz", Some(stats)) if stats.accepting_joins => {
+            ("200 OK", "ok\n".to_string())
+        }
+        ("GET", "/readyz", _) => {
+            ("503 Service Unavailable", "not accepting clients\n".to_string())
+        }
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    http::write_response(&mut stream, status, "text/plain; version=0.0.4", &body).await;
+}
+
+/// Writes every metric in the Prometheus text format
+fn render(registry: &LobbyRegistry, stats: &RegistryStats) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+        writeln!(out, "# HELP connect_four_{} {}", name, help).unwrap_or_default();
+        writeln!(out, "# TYPE connect_four_{} {}", name, kind).unwrap_or_default();
+        writeln!(out, "connect_four_{} {}", name, value).unwrap_or_default();
+    };
+
+    metric(
+        "connections_total",
+        "counter",
+        "Websocket connections accepted.",
+        load(&METRICS.connections),
+    );
+    metric(
+        "active_connections",
+        "gauge",
+        "Clients currently in a lobby, as players or spectators.",
+        load(&METRICS.active_connections),
+    );
+    metric(
+        "spectators",
+        "gauge",
+        "Clients currently spectating a lobby.",
+        load(&METRICS.spectators),
+    );
+    metric(
+        "lobbies",
+        "gauge",
+        "Lobbies which have not yet ended.",
+        *registry.live_lobbies().borrow() as u64,
+    );
+    metric(
+        "games_started_total",
+        "counter",
+        "Games where both players joined.",
+        load(&METRICS.games_started),
+    );
+    metric(
+        "games_finished_total",
+        "counter",
+        "Games which were won or ended in a draw.",
+        load(&METRICS.games_finished),
+    );
+    metric(
+        "messages_in_total",
+        "counter",
+        "Websocket messages received from clients.",
+        load(&METRICS.messages_in),
+    );
+    metric(
+        "messages_out_total",
+        "counter",
+        "Websocket messages sent to clients.",
+        load(&METRICS.messages_out),
+    );
+    metric(
+        "protocol_errors_total",
+        "counter",
+        "Failed handshakes and messages which did not follow the protocol.",
+        load(&METRICS.protocol_errors),
+    );
+    metric(
+        "rejected_clients_total",
+        "counter",
+        "Connections and clients turned away or disconnected for exceeding a limit.",
+        load(&METRICS.rejected_clients),
+    );
+    metric(
+        "board_failures_total",
+        "counter",
+        "Moves which could not be checked because the board crashed, hung or misbehaved.",
+        load(&METRICS.board_failures),
+    );
+    metric(
+        "resyncs_total",
+        "counter",
+        "Snapshots sent to clients which missed a game update and asked to resync.",
+        load(&METRICS.resyncs),
+    );
+
+    out.push_str("# HELP connect_four_lobby_lag_updates Game updates the slowest client in the lobby has yet to be sent.\n");
+    out.push_str("# TYPE connect_four_lobby_lag_updates gauge\n");
+    for lobby in &stats.lobbies {
+        writeln!(
+            out,
+            "connect_four_lobby_lag_updates{{id=\"{}\",lobby=\"{}\"}} {}",
+            lobby.id,
+            escape_label(&lobby.name),
+            lobby.lag
+        )
+        .unwrap_or_default();
+    }
+    out
+}
+
+/// Escapes a label value as required by the Prometheus text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
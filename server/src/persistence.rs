@@ -0,0 +1,120 @@
+//! persistence saves the games in progress to a snapshot file and loads them back in,
+//! so restarting the server does not end every game
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::lobby::{registry::LobbyRegistry, util::LobbyState};
+
+use constants::ConnectionProtocol;
+
+use tokio::{
+    sync::oneshot::{self, Sender as OneshotSender},
+    task::{self, JoinHandle},
+    time::{self, Duration, MissedTickBehavior},
+};
+use tracing::{info, warn};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Loads the games saved in the snapshot file at the given path
+/// Returns no games if the file does not exist or cannot be read
+pub fn load(path: &Path) -> Vec<LobbyState> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!(error = %e, "Failed to read snapshot file.");
+            return Vec::new();
+        }
+    };
+    match serde_json::from_slice::<Vec<LobbyState>>(&contents) {
+        Ok(states) => states
+            .into_iter()
+            .filter(|state| state.board.len() == ConnectionProtocol::MESSAGE_SIZE)
+            .collect(),
+        Err(e) => {
+            warn!(error = %e, "Failed to parse snapshot file.");
+            Vec::new()
+        }
+    }
+}
+
+/// Saves the games to the snapshot file at the given path
+/// Writes to a temporary file first, so a crash part way through never leaves a corrupt snapshot
+async fn save(path: PathBuf, states: Vec<LobbyState>) -> io::Result<()> {
+    task::spawn_blocking(move || {
+        let contents = serde_json::to_vec(&states)?;
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, contents)?;
+        fs::rename(&temp_path, &path)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Task which periodically saves the games in progress to the snapshot file
+pub struct SnapshotTask {
+    path: PathBuf,
+    stop_sender: OneshotSender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl SnapshotTask {
+    /// Spawns a task which saves a snapshot every interval
+    pub fn spawn(registry: LobbyRegistry, path: PathBuf, interval: Duration) -> Self {
+        let (stop_sender, mut stop_receiver) = oneshot::channel();
+        let task_path = path.clone();
+        let handle = task::spawn(async move {
+            let mut interval = time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = &mut stop_receiver => break,
+                }
+                if let Err(e) = save(task_path.clone(), registry.snapshot().await).await {
+                    warn!(error = %e, "Failed to save snapshot.");
+                }
+            }
+        });
+        Self {
+            path,
+            stop_sender,
+            handle,
+        }
+    }
+
+    /// Stops the periodic snapshots and saves one last snapshot
+    /// Should be called before the lobbies are closed, as closed lobbies are not saved
+    pub async fn finish(self, registry: &LobbyRegistry) {
+        // Let a snapshot being saved finish, so it cannot overwrite the last one
+        self.stop_sender.send(()).unwrap_or_default();
+        self.handle.await.ok();
+        let states = registry.snapshot().await;
+        let num_games = states.len();
+        match save(self.path, states).await {
+            Ok(()) => info!(games = num_games, "Saved games."),
+            Err(e) => warn!(error = %e, "Failed to save snapshot."),
+        }
+    }
+}
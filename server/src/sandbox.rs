@@ -0,0 +1,243 @@
+//! sandbox runs board plugins in board-host child processes instead of the server's own, so a board
+//! that crashes, hangs or corrupts memory only costs the lobby a move rather than the whole server
+//!
+//! Each lobby gets its own process, restarted with the game's moves replayed whenever it fails
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::boards::MoveError;
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    time::{self, Duration},
+};
+use tracing::{debug, warn, Instrument};
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+/// Longest line a board may print, far longer than any line of the protocol
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// The board-host program, and how long it has to answer each request
+#[derive(Clone)]
+pub struct BoardHost {
+    program: PathBuf,
+    timeout: Duration,
+}
+
+impl BoardHost {
+    pub fn new(program: PathBuf, timeout: Duration) -> Self {
+        Self { program, timeout }
+    }
+
+    /// Starts a host for the library to check it loads, then stops it
+    pub async fn check(&self, library: &Path) -> Result<(), String> {
+        HostProcess::spawn(self, library).await.map(|_| ())
+    }
+
+    /// Returns a new empty board run by the library, whose process is started on the first move
+    pub fn new_board(&self, library: PathBuf) -> SandboxedBoard {
+        SandboxedBoard {
+            host: self.clone(),
+            library,
+            process: None,
+            moves: Vec::new(),
+            disks: [0, 0],
+        }
+    }
+}
+
+/// A board run by a plugin in a child process
+pub struct SandboxedBoard {
+    host: BoardHost,
+    library: PathBuf,
+    process: Option<HostProcess>,
+    /// Moves the board has accepted, replayed onto new processes
+    moves: Vec<(u8, u8)>,
+    /// Disks of player 1 and player 2 after the last accepted move
+    disks: [u64; 2],
+}
+
+impl SandboxedBoard {
+    /// Drops a disk for the player into the column, returning whether the player won
+    /// If the process fails it is killed, and a new one is started for the next move
+    pub async fn drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, MoveError> {
+        let result = self.try_drop_disk(player_num, col).await;
+        if let Err(MoveError::Failed(reason)) = &result {
+            warn!(board = %self.library.display(), reason, "Board process failed, restarting it.");
+            self.process = None;
+        }
+        result
+    }
+
+    async fn try_drop_disk(&mut self, player_num: u8, col: u8) -> Result<bool, MoveError> {
+        let process = match &mut self.process {
+            Some(process) => process,
+            None => self
+                .process
+                .insert(self.restart().await.map_err(MoveError::Failed)?),
+        };
+        match process.drop_disk(player_num, col, self.host.timeout).await {
+            Ok(Some((game_won, disks))) => {
+                self.moves.push((player_num, col));
+                self.disks = disks;
+                Ok(game_won)
+            }
+            Ok(None) => Err(MoveError::Illegal),
+            Err(reason) => Err(MoveError::Failed(reason)),
+        }
+    }
+
+    /// Starts a new process and replays the accepted moves onto its board
+    async fn restart(&self) -> Result<HostProcess, String> {
+        let mut process = HostProcess::spawn(&self.host, &self.library).await?;
+        for (player_num, col) in &self.moves {
+            if process
+                .drop_disk(*player_num, *col, self.host.timeout)
+                .await?
+                .is_none()
+            {
+                return Err("board rejected a move it accepted before".to_string());
+            }
+        }
+        Ok(process)
+    }
+
+    /// Returns the disks of player 1 and player 2
+    pub fn disks(&self) -> [u64; 2] {
+        self.disks
+    }
+}
+
+/// A running board-host process, killed when dropped
+struct HostProcess {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl HostProcess {
+    /// Starts board-host for the library and waits for it to be ready
+    /// Anything the board prints is logged at debug level
+    async fn spawn(host: &BoardHost, library: &Path) -> Result<Self, String> {
+        let mut child = Command::new(&host.program)
+            .arg(library)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", host.program.display(), e))?;
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(
+                async move {
+                    let mut stderr = BufReader::new(stderr);
+                    while let Ok(Some(line)) = next_line(&mut stderr).await {
+                        debug!(output = line, "Board printed.");
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, BufReader::new(stdout)),
+            _ => return Err("failed to open pipes".to_string()),
+        };
+        let mut process = Self {
+            _child: child,
+            stdin,
+            stdout,
+        };
+
+        match process.read_line(host.timeout).await?.as_str() {
+            "ready" => Ok(process),
+            response => Err(response
+                .strip_prefix("error ")
+                .unwrap_or(response)
+                .to_string()),
+        }
+    }
+
+    /// Asks the board to drop a disk for the player into the column
+    /// Returns whether the player won and the disks of both players, or None if the move is illegal
+    async fn drop_disk(
+        &mut self,
+        player_num: u8,
+        col: u8,
+        timeout: Duration,
+    ) -> Result<Option<(bool, [u64; 2])>, String> {
+        let request = format!("drop {} {}\n", player_num, col);
+        time::timeout(timeout, self.stdin.write_all(request.as_bytes()))
+            .await
+            .map_err(|_| "timed out".to_string())?
+            .map_err(|e| e.to_string())?;
+        let response = self.read_line(timeout).await?;
+        let words: Vec<&str> = response.split_whitespace().collect();
+        match words.as_slice() {
+            ["ok", game_won, p1, p2] => {
+                match (u64::from_str_radix(p1, 16), u64::from_str_radix(p2, 16)) {
+                    (Ok(p1), Ok(p2)) => Ok(Some((*game_won == "1", [p1, p2]))),
+                    _ => Err(format!("malformed response {:?}", response)),
+                }
+            }
+            ["illegal"] => Ok(None),
+            _ => Err(format!("unexpected response {:?}", response)),
+        }
+    }
+
+    /// Reads one line written by the process, failing if it takes too long, is too long or the
+    /// process exited
+    async fn read_line(&mut self, timeout: Duration) -> Result<String, String> {
+        match time::timeout(timeout, next_line(&mut self.stdout)).await {
+            Ok(Ok(Some(line))) => Ok(line),
+            Ok(Ok(None)) => Err("process exited".to_string()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("timed out".to_string()),
+        }
+    }
+}
+
+/// Reads one line from the reader without its line ending, or None once there is nothing left
+/// Fails rather than buffering a line longer than MAX_LINE_LENGTH
+async fn next_line(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<String>, String> {
+    let mut line = Vec::new();
+    let len = (&mut *reader)
+        .take(MAX_LINE_LENGTH as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(|e| e.to_string())?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if len > MAX_LINE_LENGTH {
+        return Err("line too long".to_string());
+    }
+    String::from_utf8(line).map(Some).map_err(|e| e.to_string())
+}
@@ -0,0 +1,116 @@
+//! shutdown contains the signal and shut_down functions, which let the server stop gracefully
+//! rather than dropping every game without notice
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{lobby::registry::LobbyRegistry, persistence::SnapshotTask};
+
+use tokio::time::{self, Duration};
+use tracing::{info, warn};
+
+/// How long to wait for lobbies to end once they have been told to close
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolves once the server is asked to stop, with SIGINT (Ctrl-C) or SIGTERM
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{self, SignalKind};
+        match unix::signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(_) => tokio::signal::ctrl_c().await.unwrap_or_default(),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.unwrap_or_default();
+}
+
+/// Warns clients that the server is shutting down with a countdown of the given number of seconds,
+/// optionally waits for the games in progress to finish, then ends every lobby
+/// A second signal skips straight to ending the lobbies
+/// If snapshots are being saved, the games still in progress are saved before the lobbies end
+///
+/// The listener should already be dropped, so no new clients arrive
+pub async fn shut_down(
+    registry: LobbyRegistry,
+    countdown: u64,
+    drain_games: bool,
+    snapshots: Option<SnapshotTask>,
+) {
+    info!("Shutting down.");
+    registry.stop_joins();
+
+    let mut live_lobbies = registry.live_lobbies();
+    let force = signal();
+    tokio::pin!(force);
+    let mut forced = false;
+
+    // Count down, stopping early if every lobby ends on its own
+    let mut remaining = countdown;
+    while remaining > 0 && !forced {
+        if remaining == countdown || remaining.is_multiple_of(10) || remaining <= 5 {
+            registry.notify_all(format!(
+                "Server shutting down for maintenance in {} second{}.",
+                remaining,
+                if remaining == 1 { "" } else { "s" }
+            ));
+        }
+        tokio::select! {
+            _ = time::sleep(Duration::from_secs(1)) => remaining -= 1,
+            _ = live_lobbies.wait_for(|num| *num == 0) => break,
+            _ = &mut force => forced = true,
+        }
+    }
+
+    if drain_games && !forced && *live_lobbies.borrow() != 0 {
+        info!(lobbies = *live_lobbies.borrow(), "Waiting for lobbies to finish.");
+        registry.notify_all(
+            "Server shutting down for maintenance once this game finishes.".to_string(),
+        );
+        tokio::select! {
+            _ = live_lobbies.wait_for(|num| *num == 0) => {}
+            _ = &mut force => {}
+        }
+    }
+
+    match snapshots {
+        Some(snapshots) => {
+            snapshots.finish(&registry).await;
+            registry.notify_all(
+                "Server restarting for maintenance. Rejoin this lobby to resume your game."
+                    .to_string(),
+            );
+        }
+        None => registry.notify_all("Server shut down for maintenance.".to_string()),
+    }
+    registry.close_all();
+    if time::timeout(CLOSE_TIMEOUT, live_lobbies.wait_for(|num| *num == 0))
+        .await
+        .is_err()
+    {
+        warn!("Timed out waiting for lobbies to close.");
+    }
+    info!("Shut down.");
+}
@@ -21,6 +21,8 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+use constants::ConnectionProtocol;
+
 use tokio::net::TcpStream;
 use tokio::io::{self, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio_rustls::{server::TlsStream, TlsAcceptor};
@@ -70,6 +72,8 @@ impl TlsClient {
 
 impl TlsClientWriter {
     /// Sends a message through the TlsStream
+    /// Only binary messages are sent: the stream is not framed, so a text notice would be read
+    /// as part of the next binary message
     pub async fn send(&mut self, item: Message) -> Result<(), Error> {
         if let Binary(binary) = item {
             self.writer.write(&binary).await?;
@@ -80,9 +84,11 @@ impl TlsClientWriter {
 
 impl TlsClientReader {
     /// Gets the next message from the TlsStream
+    /// The stream is not framed, so each read is taken as one message, and the buffer holds the
+    /// longest message a client sends, a join request with a seat token
     /// Always returns Some variant, Option returned to mirror futures library
     pub async fn next(&mut self) -> Option<Result<Message, Error>> {
-        let mut msg_buf = [0u8; 16];
+        let mut msg_buf = [0u8; ConnectionProtocol::MAX_JOIN_REQUEST_SIZE];
         let len = match self.reader.read(&mut msg_buf).await {
             Err(_) => return Some(Err(Error::AlreadyClosed)),
             Ok(len) => len
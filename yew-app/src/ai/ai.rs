@@ -25,6 +25,8 @@ use crate::util::disks::Disks;
 pub trait AI {
     /// Requests the next move, possibly asynchronously. The AI should use a previously provided callback to update the board.
     fn request_move(&self, disks: &Disks) -> u8;
+    /// Stops working on the move last requested, so it is never made. Used when it is undone.
+    fn cancel(&self) {}
 }
 
 /// AI that can also be the ever increasingly difficult opponent in survival mode
@@ -1,5 +1,5 @@
 //! Contains the BruteForceAI struct.
-//! This AI asynchronously finds the best move using the
+//! This AI finds the best move in a web worker using the
 //! BruteForceAIHelper, which looks ahead several moves into the future.
 
 /*
@@ -24,108 +24,91 @@
 use super::{
     super::{
         ai::{SurvivalAI, AI},
-        util::AI_INCREMENT_MESSAGE,
+        util,
     },
-    brute_force_helper::BruteForceAIHelper,
-    position_lookup_table::PositionLookupTable,
-};
-use crate::{
-    ai::util::BRUTE_FORCE_SURVIVAL_DIFFICULTY_INCREMENT,
-    util::{disks::Disks, util::GameUpdateMessage},
+    worker::{SearchMode, SearchWorker},
 };
+use crate::util::{disks::Disks, util::GameUpdateMessage};
 use constants::*;
-use gloo::console::log;
-use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
-use wasm_bindgen_futures::spawn_local;
+use serde::{Deserialize, Serialize};
 use yew::Callback;
 
-use std::{
-    cell::RefCell,
-    rc::Rc
-};
+/// How long the brute force AI may think about each move, and how many moves ahead it may look
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SearchBudget {
+    pub max_moves_look_ahead: u8,
+    pub time_budget_ms: u32,
+}
 
-use GameUpdateMessage::SimpleMessage;
+impl SearchBudget {
+    /// The versus bot looks as far ahead as it has time for
+    pub const VERSUS_BOT: SearchBudget = SearchBudget {
+        max_moves_look_ahead: BOARD_WIDTH * BOARD_HEIGHT,
+        time_budget_ms: 1500,
+    };
+
+    /// The survival AI starts out looking one move ahead, and looks further ahead and thinks
+    /// for longer every round
+    pub fn survival(difficulty_level: u8) -> Self {
+        let rounds = difficulty_level.saturating_sub(1);
+        Self {
+            max_moves_look_ahead: rounds
+                .saturating_mul(util::BRUTE_FORCE_SURVIVAL_DIFFICULTY_INCREMENT)
+                .saturating_add(1),
+            time_budget_ms: util::BRUTE_FORCE_SURVIVAL_TIME_INCREMENT_MS * difficulty_level as u32,
+        }
+    }
+}
 
-/// Struct to asynchronously run the BruteForceAIHelper to find the best possible move
+/// Struct to run the BruteForceAIHelper in a web worker to find the best possible move
 pub struct BruteForceAI {
-    pub request_sender: UnboundedSender<GameUpdateMessage>,
-    difficulty_level: Rc<RefCell<u8>>
+    worker: SearchWorker,
+    difficulty_level: u8,
 }
 
 impl BruteForceAI {
-    /// Creates a BruteForceAI and spawns a computational task to asynchronously run the AI algorithm
+    /// Creates a BruteForceAI and starts a web worker to run the AI algorithm
+    /// The budget sets how long the AI thinks about each move and how far ahead it may look
+    /// If use_opening_book is set, the AI plays early moves from the opening book instead of searching
     pub fn new(
-        max_moves_look_ahead: u8,
+        budget: SearchBudget,
+        use_opening_book: bool,
         rerender_board_callback: Callback<GameUpdateMessage>,
     ) -> Self {
-        let (request_sender, receiver) = mpsc::unbounded_channel();
-        let difficulty_level = Rc::new(RefCell::new(1));
-        Self::spawn_computation_task(
-            receiver,
+        let worker = SearchWorker::new(
+            SearchMode::BruteForce {
+                budget,
+                use_opening_book,
+            },
             rerender_board_callback,
-            max_moves_look_ahead,
-            Rc::clone(&difficulty_level)
         );
-        Self { request_sender, difficulty_level }
-    }
-
-    /// Creates a task with a BruteForceAIHelper to run the AI algorithm
-    fn spawn_computation_task(
-        mut receiver: UnboundedReceiver<GameUpdateMessage>,
-        rerender_board_callback: Callback<GameUpdateMessage>,
-        max_moves_look_ahead: u8,
-        difficulty_level: Rc<RefCell<u8>>
-    ) {
-        spawn_local(async move {
-            let mut ai = BruteForceAIHelper::new(max_moves_look_ahead);
-            // Everytime a move is requested from the AI
-            while let Some(msg) = receiver.recv().await {
-                match msg {
-                    GameUpdateMessage::Disks(disks) => {
-                        let ai_move = ai.get_move(&disks).await;
-                        // if let Err(_) = receiver.try_recv() {
-                        rerender_board_callback.emit(SimpleMessage(ai_move)); // Make the move
-                        // }
-                    }
-                    GameUpdateMessage::SimpleMessage(inc) => {
-                        // Increase the difficulty
-                        if inc == AI_INCREMENT_MESSAGE {
-                            *difficulty_level.borrow_mut() += 1;
-                            ai.max_moves_look_ahead += BRUTE_FORCE_SURVIVAL_DIFFICULTY_INCREMENT;
-                            // Old table may contain invalid scores, now that the AI looks further ahead into the future
-                            ai.position_lookup_table = PositionLookupTable::new(LOOKUP_TABLE_SIZE);
-                            log!(format!(
-                                "Difficulty increased to {}",
-                                ai.max_moves_look_ahead
-                            ));
-                        }
-                    }
-                    _ => panic!("Received message the AI thread cannot handle."),
-                }
-            }
-        });
+        Self {
+            worker,
+            difficulty_level: 1,
+        }
     }
 }
 
 impl AI for BruteForceAI {
-    /// Give the current disk arrangement to the computational task to find the next move for the AI to make
+    /// Give the current disk arrangement to the web worker to find the next move for the AI to make
     fn request_move(&self, disks: &Disks) -> u8 {
-        self.request_sender
-            .send(GameUpdateMessage::Disks(disks.clone()))
-            .unwrap_or_default();
-        BOARD_WIDTH
+        self.worker.request_move(disks)
+    }
+
+    fn cancel(&self) {
+        self.worker.cancel();
     }
 }
 
 impl SurvivalAI for BruteForceAI {
     /// Used for survival mode, to make the AI harder each round.
     fn increment_difficulty(&mut self) {
-        // Tell the helper on the computational task to increase the difficulty
-        self.request_sender
-            .send(SimpleMessage(AI_INCREMENT_MESSAGE))
-            .unwrap_or_default();
+        // Tell the helper in the web worker to increase the difficulty
+        self.difficulty_level += 1;
+        self.worker
+            .set_budget(SearchBudget::survival(self.difficulty_level));
     }
     fn get_difficulty_level(&self) -> u8 {
-        *self.difficulty_level.borrow()
+        self.difficulty_level
     }
 }
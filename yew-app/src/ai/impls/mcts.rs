@@ -0,0 +1,76 @@
+//! Contains the MCTSAI struct.
+//! This AI finds its move in a web worker using the MCTSHelper, which plays
+//! random games from the position and picks the move that won the most of them.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    super::ai::AI,
+    worker::{SearchMode, SearchWorker},
+};
+use crate::util::{disks::Disks, util::GameUpdateMessage};
+use serde::{Deserialize, Serialize};
+use yew::Callback;
+
+/// How many random games the MCTS AI may play for each move and for how long, and how much it
+/// tries moves which have been losing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MCTSConfig {
+    pub max_iterations: u32,
+    pub time_budget_ms: u32,
+    /// Larger values try the moves played the least more often, smaller values keep to the
+    /// moves which have been winning
+    pub exploration: f64,
+}
+
+impl MCTSConfig {
+    /// The versus bot plays as many games as it can in a second, up to a limit which keeps the
+    /// tree of games to a few tens of megabytes
+    pub const VERSUS_BOT: MCTSConfig = MCTSConfig {
+        max_iterations: 200_000,
+        time_budget_ms: 1000,
+        exploration: std::f64::consts::SQRT_2,
+    };
+}
+
+/// Struct to run the MCTSHelper in a web worker to find a move
+pub struct MCTSAI {
+    worker: SearchWorker,
+}
+
+impl MCTSAI {
+    /// Creates an MCTSAI and starts a web worker to run the AI algorithm
+    pub fn new(config: MCTSConfig, rerender_board_callback: Callback<GameUpdateMessage>) -> Self {
+        Self {
+            worker: SearchWorker::new(SearchMode::MCTS { config }, rerender_board_callback),
+        }
+    }
+}
+
+impl AI for MCTSAI {
+    /// Give the current disk arrangement to the web worker to find the next move for the AI to make
+    fn request_move(&self, disks: &Disks) -> u8 {
+        self.worker.request_move(disks)
+    }
+
+    fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
@@ -0,0 +1,210 @@
+//! Contains the MCTSHelper struct, used by the MCTSAI to perform computations in a web worker.
+//! At a high level, this AI plays many random games from the current position, spending more
+//! of them on the moves which have been winning, and picks the move it played the most.
+//! It judges moves by how often they win rather than by best play, so it plays more like a
+//! person than the searching AIs: strong where many replies win, but able to miss a trap.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    ai::{impls::mcts::MCTSConfig, util},
+    util::disks::Disks,
+};
+use constants::*;
+use gloo::console::log;
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+
+/// A position in the tree of games played so far
+struct Node {
+    disks: Disks,
+    /// Column dropped into to reach the position
+    col: u8,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// Columns which have not been tried from the position yet
+    untried: Vec<u8>,
+    /// Whether the game is over: the move into the position won or filled the board
+    game_over: bool,
+    visits: u32,
+    /// Total result of the games played through the position, for the player who moved into
+    /// it: 1 for each win and 0.5 for each draw
+    reward: f64,
+}
+
+/// MCTSHelper stores AI data in a web worker for an MCTSAI
+pub struct MCTSHelper {
+    // How many games to play and for how long, and how much to try moves which have been losing
+    pub config: MCTSConfig,
+    rng: ThreadRng,
+}
+
+impl MCTSHelper {
+    /// Games to play between checks of the time
+    const ITERATIONS_PER_TIME_CHECK: u32 = 256;
+
+    /// Creates a new MCTSHelper
+    pub fn new(config: MCTSConfig) -> MCTSHelper {
+        MCTSHelper {
+            config,
+            rng: rand::thread_rng(),
+        }
+    }
+
+    /// Returns which column the AI chose to drop a disk into.
+    pub fn get_move(&mut self, board: &Disks) -> u8 {
+        log!("Move requested from AI.");
+        let started = util::now_ms();
+        let deadline = started + self.config.time_budget_ms as f64;
+        let mut tree = vec![Self::new_node(board.clone(), BOARD_WIDTH, None, false)];
+
+        let mut iterations = 0;
+        while iterations < self.config.max_iterations {
+            if iterations % Self::ITERATIONS_PER_TIME_CHECK == 0
+                && iterations > 0
+                && util::now_ms() >= deadline
+            {
+                break;
+            }
+            let leaf = self.select_and_expand(&mut tree);
+            let reward = self.play_out(&tree[leaf]);
+            Self::back_propagate(&mut tree, leaf, reward);
+            iterations += 1;
+        }
+
+        // The move played the most is the one the AI is surest of
+        let best = tree[0]
+            .children
+            .iter()
+            .map(|&child| &tree[child])
+            .max_by_key(|child| child.visits);
+        let win_rate = best.map_or(0.0, |child| child.reward / child.visits.max(1) as f64);
+        log!(format!(
+            "Played {} games in {}ms, winning {:.0}% of those through the chosen move.",
+            iterations,
+            util::now_ms() - started,
+            100.0 * win_rate
+        ));
+        best.map_or(0, |child| child.col)
+    }
+
+    ///// PRIVATE METHODS /////
+
+    /// Creates a node for a position, with its open columns left to try
+    fn new_node(disks: Disks, col: u8, parent: Option<usize>, game_over: bool) -> Node {
+        let untried = if game_over {
+            Vec::new()
+        } else {
+            (0..BOARD_WIDTH)
+                .filter(|&col| !disks.is_col_full(col))
+                .collect()
+        };
+        Node {
+            disks,
+            col,
+            parent,
+            children: Vec::new(),
+            untried,
+            game_over,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+
+    /// Walks down the tree by the most promising moves until it reaches a position with a move
+    /// not yet tried, then adds the position that move leads to and returns it.
+    /// Returns the position walked to instead if the game is over there.
+    fn select_and_expand(&mut self, tree: &mut Vec<Node>) -> usize {
+        let mut node = 0;
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            node = self.most_promising_child(tree, node);
+        }
+        if tree[node].untried.is_empty() {
+            return node;
+        }
+
+        let index = self.rng.gen_range(0..tree[node].untried.len());
+        let col = tree[node].untried.swap_remove(index);
+        let mut disks = tree[node].disks.clone();
+        disks.drop_disk(col).unwrap_or_default();
+        let game_over = disks.check_last_drop_won() || disks.is_full();
+        tree.push(Self::new_node(disks, col, Some(node), game_over));
+        let child = tree.len() - 1;
+        tree[node].children.push(child);
+        child
+    }
+
+    /// Returns the child with the best upper confidence bound: the children which have been
+    /// winning most often, and those which have been tried the least, are the most promising
+    fn most_promising_child(&self, tree: &[Node], node: usize) -> usize {
+        let log_visits = (tree[node].visits.max(1) as f64).ln();
+        let bound = |child: usize| {
+            let child = &tree[child];
+            let visits = child.visits.max(1) as f64;
+            child.reward / visits + self.config.exploration * (log_visits / visits).sqrt()
+        };
+        tree[node]
+            .children
+            .iter()
+            .copied()
+            .max_by(|&a, &b| bound(a).total_cmp(&bound(b)))
+            .unwrap_or(node)
+    }
+
+    /// Plays random moves from the position until the game ends, and returns the result for
+    /// the player who moved into the position
+    fn play_out(&mut self, node: &Node) -> f64 {
+        if node.game_over {
+            return if node.disks.check_last_drop_won() {
+                1.0
+            } else {
+                0.5
+            };
+        }
+        let mut disks = node.disks.clone();
+        let mut open_cols: Vec<u8> = Vec::with_capacity(BOARD_WIDTH as usize);
+        // The opponent of the player who moved into the position drops the first disk
+        let mut opponents_turn = false;
+        loop {
+            opponents_turn = !opponents_turn;
+            open_cols.clear();
+            open_cols.extend((0..BOARD_WIDTH).filter(|&col| !disks.is_col_full(col)));
+            let col = match open_cols.choose(&mut self.rng) {
+                Some(&col) => col,
+                None => return 0.5, // the board is full, so the game is a draw
+            };
+            disks.drop_disk(col).unwrap_or_default();
+            if disks.check_last_drop_won() {
+                return if opponents_turn { 0.0 } else { 1.0 };
+            }
+        }
+    }
+
+    /// Adds the result of a game to every position it went through, from the point of view of
+    /// the player who moved into each one
+    fn back_propagate(tree: &mut [Node], leaf: usize, mut reward: f64) {
+        let mut node = Some(leaf);
+        while let Some(index) = node {
+            tree[index].visits += 1;
+            tree[index].reward += reward;
+            reward = 1.0 - reward;
+            node = tree[index].parent;
+        }
+    }
+}
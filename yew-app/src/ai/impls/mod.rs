@@ -18,7 +18,13 @@
  */
 
 pub mod brute_force;
+pub mod mcts;
+pub mod perfect;
 pub mod random;
+pub mod tabular;
+pub mod worker;
 // only used internally
 mod brute_force_helper;
-mod position_lookup_table;
+mod mcts_helper;
+mod opening_book;
+mod tabular_helper;
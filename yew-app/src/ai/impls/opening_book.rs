@@ -0,0 +1,79 @@
+//! Contains the LazyOpeningBook struct, which fetches the opening book shipped with the app
+//! the first time an AI asks it about a position, so the book is only downloaded if it is used.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{ai::util, util::disks::Disks};
+use constants::book::OpeningBook;
+use gloo::console::{error, log};
+
+/// Where the app serves the opening book from, relative to the page's base URL so it is found
+/// wherever the app is hosted
+pub const OPENING_BOOK_URL: &str = "opening_book.bin";
+
+/// The opening book, fetched when it is first needed
+pub struct LazyOpeningBook {
+    /// Where to fetch the book from
+    url: String,
+    book: Option<OpeningBook>,
+    /// Whether fetching the book has been tried, so a missing book is only asked for once
+    fetched: bool,
+}
+
+impl LazyOpeningBook {
+    /// Creates a LazyOpeningBook which fetches the book from the url once it is needed
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            book: None,
+            fetched: false,
+        }
+    }
+
+    /// Returns the columns with the best score, if the opening book has the position and at
+    /// least one of them (see OpeningBook::best_moves)
+    pub async fn best_moves(&mut self, disks: &Disks) -> Option<Vec<u8>> {
+        let game = disks.to_game_update(false);
+        if !self.fetched {
+            self.fetched = true;
+            self.book = Self::fetch(&self.url).await;
+        }
+        self.book.as_ref()?.best_moves(game.position, game.mask)
+    }
+
+    /// Downloads and reads the opening book, logging why if it could not be
+    async fn fetch(url: &str) -> Option<OpeningBook> {
+        let bytes = util::fetch_bytes(url, "opening book").await?;
+        match OpeningBook::from_bytes(&bytes) {
+            Ok(book) => {
+                log!(format!(
+                    "Loaded opening book of {} positions, up to {} disks.",
+                    book.len(),
+                    book.max_ply()
+                ));
+                Some(book)
+            }
+            Err(e) => {
+                error!(format!("Failed to read opening book: {}", e));
+                None
+            }
+        }
+    }
+}
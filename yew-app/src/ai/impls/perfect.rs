@@ -0,0 +1,90 @@
+//! Contains the PerfectAI struct.
+//! This AI solves the position to the end of the game with the
+//! shared Solver in a web worker, and plays whichever move gives it the best result with best play.
+//! It never gives up on a position: the earliest ones are looked up in the opening book, and
+//! the solver takes as long as it needs for any other.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    super::{ai::AI, util},
+    opening_book::LazyOpeningBook,
+    worker::{SearchMode, SearchWorker},
+};
+use crate::util::{disks::Disks, util::GameUpdateMessage};
+use constants::solver::{Position, Solver};
+use gloo::console::log;
+use yew::Callback;
+
+/// Struct to run the Solver in a web worker to find the best possible move
+pub struct PerfectAI {
+    worker: SearchWorker,
+}
+
+impl PerfectAI {
+    /// Entries in the solver's transposition table, a prime so positions spread evenly (8 MB)
+    pub const TABLE_SIZE: usize = 1_048_573;
+
+    /// Creates a PerfectAI and starts a web worker to run the solver
+    pub fn new(rerender_board_callback: Callback<GameUpdateMessage>) -> Self {
+        Self {
+            worker: SearchWorker::new(SearchMode::Perfect, rerender_board_callback),
+        }
+    }
+
+    /// Returns which column the AI chose to drop a disk into: one of the columns with the best
+    /// score, at random.
+    /// The solver's table stays valid from one move to the next, so it is kept for the whole game
+    pub async fn get_move(
+        solver: &mut Solver,
+        opening_book: &mut LazyOpeningBook,
+        disks: &Disks,
+    ) -> u8 {
+        log!("Move requested from AI.");
+        if let Some(best_cols) = opening_book.best_moves(disks).await {
+            log!("Move found in the opening book.");
+            return *util::random_col_from_options(&best_cols).unwrap_or(&0);
+        }
+
+        let started = util::now_ms();
+        let game = disks.to_game_update(false);
+        // The solver has no node budget, so it always finishes
+        let (score, best_cols) = solver
+            .best_moves(&Position::new(game.position, game.mask))
+            .unwrap_or_default();
+        log!(format!(
+            "Position solved after {}ms, score {}",
+            util::now_ms() - started,
+            score
+        ));
+        *util::random_col_from_options(&best_cols).unwrap_or(&0)
+    }
+}
+
+impl AI for PerfectAI {
+    /// Give the current disk arrangement to the web worker to find the next move for the AI to make
+    fn request_move(&self, disks: &Disks) -> u8 {
+        self.worker.request_move(disks)
+    }
+
+    fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
@@ -0,0 +1,88 @@
+//! Contains the TabularAI struct.
+//! This AI finds its move in a web worker using the TabularHelper, which
+//! plays by a value table learned by self-play.
+//! The game is played on a board the size of the table, which the page reads from the table
+//! before the first move.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    super::ai::AI,
+    tabular_helper::{TabularHelper, VALUE_TABLE_URL},
+    worker::{SearchMode, SearchWorker},
+};
+use crate::util::{disks::Disks, util::GameUpdateMessage};
+use std::{cell::Cell, rc::Rc};
+use wasm_bindgen_futures::spawn_local;
+use yew::Callback;
+
+/// Struct to run the TabularHelper in a web worker to find a move
+pub struct TabularAI {
+    worker: SearchWorker,
+    /// Whether the AI is still the second player, so a table read after it was replaced does
+    /// not change the board of another game
+    playing: Rc<Cell<bool>>,
+}
+
+impl TabularAI {
+    /// Creates a TabularAI and starts a web worker to run the AI algorithm
+    /// Sends the board a Disks message with an empty board the size of the table once it is
+    /// read, or of the full board if it could not be
+    pub fn new(rerender_board_callback: Callback<GameUpdateMessage>) -> Self {
+        let playing = Rc::new(Cell::new(true));
+        {
+            let playing = Rc::clone(&playing);
+            let rerender_board_callback = rerender_board_callback.clone();
+            spawn_local(async move {
+                let disks = match TabularHelper::fetch(VALUE_TABLE_URL).await {
+                    Some(table) => {
+                        let (width, height) = table.size();
+                        Disks::with_size(width, height)
+                    }
+                    None => Disks::default(),
+                };
+                if playing.get() {
+                    rerender_board_callback.emit(GameUpdateMessage::Disks(disks));
+                }
+            });
+        }
+        Self {
+            worker: SearchWorker::new(SearchMode::Tabular, rerender_board_callback),
+            playing,
+        }
+    }
+}
+
+impl Drop for TabularAI {
+    fn drop(&mut self) {
+        self.playing.set(false);
+    }
+}
+
+impl AI for TabularAI {
+    /// Give the current disk arrangement to the web worker to find the next move for the AI to make
+    fn request_move(&self, disks: &Disks) -> u8 {
+        self.worker.request_move(disks)
+    }
+
+    fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
@@ -0,0 +1,148 @@
+//! Contains the TabularHelper struct, used by the TabularAI to perform computations in a web worker.
+//! At a high level, this AI looks up how good the position each move leads to is in a value
+//! table learned by self-play, and plays the move leading to the best one. It does not search:
+//! everything it knows is in the table, so positions it never saw in training count as even.
+//! The table may be for a board smaller than the full one, which the game is then played on.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::{ai::util, util::disks::Disks};
+use constants::{table::ValueTable, *};
+use gloo::console::{error, log};
+
+/// Where the app serves the value table from, relative to the page's base URL
+pub const VALUE_TABLE_URL: &str = "value_table.bin";
+
+/// TabularHelper stores AI data in a web worker for a TabularAI
+pub struct TabularHelper {
+    table: Option<ValueTable>,
+    // Where to fetch the table from, and whether that has been tried
+    table_url: String,
+    fetched: bool,
+}
+
+impl TabularHelper {
+    /// Creates a TabularHelper which fetches the table from the url once it is first needed
+    pub fn new(table_url: String) -> TabularHelper {
+        TabularHelper {
+            table: None,
+            table_url,
+            fetched: false,
+        }
+    }
+
+    /// Returns which column the AI chose to drop a disk into.
+    pub async fn get_move(&mut self, board: &Disks) -> u8 {
+        log!("Move requested from AI.");
+        if !self.fetched {
+            self.fetched = true;
+            self.table = Self::fetch(&self.table_url).await;
+        }
+
+        let mut scores = Vec::with_capacity(BOARD_WIDTH as usize);
+        let mut known = 0;
+        for col in 0..BOARD_WIDTH {
+            let mut child = board.clone();
+            if child.drop_disk(col).is_err() {
+                continue;
+            }
+            let score = if child.check_last_drop_won() {
+                1.0
+            } else if child.is_full() {
+                0.0
+            } else {
+                let game = child.to_game_update(false);
+                let (width, height) = child.size();
+                let value = self
+                    .table
+                    .as_ref()
+                    .filter(|table| table.size() == (width, height))
+                    .and_then(|table| {
+                        table.get(
+                            to_table_layout(game.position, width, height),
+                            to_table_layout(game.mask, width, height),
+                        )
+                    });
+                known += value.is_some() as usize;
+                -value.unwrap_or_default()
+            };
+            scores.push((col, score));
+        }
+
+        // choose at random between the moves the table likes best
+        let best = scores
+            .iter()
+            .map(|&(_, score)| score)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let best_cols: Vec<u8> = scores
+            .iter()
+            .filter(|&&(_, score)| score == best)
+            .map(|&(col, _)| col)
+            .collect();
+        log!(format!(
+            "Knew {} of {} moves, the best scoring {:.2}",
+            known,
+            scores.len(),
+            best
+        ));
+        util::random_col_from_options(&best_cols)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    ///// PRIVATE METHODS /////
+
+    /// Downloads and reads the table, logging why if it could not be or is for a board larger
+    /// than the app's
+    pub async fn fetch(url: &str) -> Option<ValueTable> {
+        let bytes = util::fetch_bytes(url, "value table").await?;
+        let table = match ValueTable::from_bytes(&bytes) {
+            Ok(table) => table,
+            Err(e) => {
+                error!(format!("Failed to read value table: {}", e));
+                return None;
+            }
+        };
+        let (width, height) = table.size();
+        if !(4..=BOARD_WIDTH).contains(&width) || !(4..=BOARD_HEIGHT).contains(&height) {
+            error!(format!(
+                "Value table is for a {}x{} board, which does not fit in {}x{}",
+                width, height, BOARD_WIDTH, BOARD_HEIGHT
+            ));
+            return None;
+        }
+        log!(format!(
+            "Loaded value table of {} positions for a {}x{} board.",
+            table.len(),
+            width,
+            height
+        ));
+        Some(table)
+    }
+}
+
+/// Moves the disks of a board of the given size from where Disks keeps them, BOARD_HEIGHT + 1
+/// bits to a column, to where a value table keeps them, height + 1 bits to a column
+fn to_table_layout(bits: u64, width: u8, height: u8) -> u64 {
+    (0..width as u32).fold(0, |packed, col| {
+        let column = (bits >> (col * (BOARD_HEIGHT as u32 + 1))) & ((1 << height) - 1);
+        packed | column << (col * (height as u32 + 1))
+    })
+}
@@ -0,0 +1,400 @@
+//! Contains the AIWorker, which runs the searching AIs in a Web Worker so the page keeps
+//! responding while they think, and the SearchWorker the AIs use to talk to it.
+//! A search cannot be interrupted once started, so cancelling one terminates the worker and
+//! replaces it.
+
+/*
+ * This file is part of Rust-Connect-Four
+ * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
+ * Copyright (C) 2022 Porter Shawver <portershawver@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published
+ * by the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use super::{
+    brute_force_helper::BruteForceAIHelper,
+    mcts::MCTSConfig,
+    mcts_helper::MCTSHelper,
+    opening_book::{LazyOpeningBook, OPENING_BOOK_URL},
+    perfect::PerfectAI,
+    random::RandomAI,
+    tabular_helper::{TabularHelper, VALUE_TABLE_URL},
+};
+use crate::{
+    ai::{ai::AI, impls::brute_force::SearchBudget},
+    util::{disks::Disks, util::GameUpdateMessage},
+};
+use constants::{solver::Solver, BOARD_WIDTH};
+use gloo::console::{error, log};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{Blob, BlobPropertyBag, DedicatedWorkerGlobalScope, MessageEvent, Url};
+use yew::Callback;
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use GameUpdateMessage::SimpleMessage;
+
+/// Where the app serves the worker's script from, relative to the page's base URL
+const WORKER_URL: &str = "ai_worker.js";
+
+/// Which search the worker runs, and how hard it searches
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SearchMode {
+    BruteForce {
+        budget: SearchBudget,
+        use_opening_book: bool,
+    },
+    Perfect,
+    MCTS {
+        config: MCTSConfig,
+    },
+    Tabular,
+}
+
+/// Messages the page sends the worker
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AIRequest {
+    /// Sets up the search, with the page's base URL to fetch the files it needs from
+    Start { mode: SearchMode, base_url: String },
+    /// Asks for a move, answered with an AIResponse with the same id
+    Move { id: u32, disks: Disks },
+    /// Changes how hard a brute force search searches, for survival mode
+    SetBudget(SearchBudget),
+}
+
+/// Messages the worker sends the page
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AIResponse {
+    /// The worker has loaded and can take requests
+    Loaded,
+    /// The move the worker chose for the Move request with the same id
+    Move { id: u32, col: u8 },
+}
+
+/// A search and everything it keeps from one move to the next
+enum Search {
+    BruteForce(BruteForceAIHelper),
+    Perfect(Solver, LazyOpeningBook),
+    MonteCarlo(MCTSHelper),
+    Tabular(TabularHelper),
+}
+
+impl Search {
+    fn new(mode: SearchMode, base_url: &str) -> Self {
+        let opening_book = LazyOpeningBook::new(format!("{}{}", base_url, OPENING_BOOK_URL));
+        match mode {
+            SearchMode::BruteForce {
+                budget,
+                use_opening_book,
+            } => Search::BruteForce(BruteForceAIHelper::new(
+                budget,
+                use_opening_book.then_some(opening_book),
+            )),
+            SearchMode::Perfect => {
+                Search::Perfect(Solver::new(u64::MAX, PerfectAI::TABLE_SIZE), opening_book)
+            }
+            SearchMode::MCTS { config } => Search::MonteCarlo(MCTSHelper::new(config)),
+            SearchMode::Tabular => Search::Tabular(TabularHelper::new(format!(
+                "{}{}",
+                base_url, VALUE_TABLE_URL
+            ))),
+        }
+    }
+
+    async fn get_move(&mut self, disks: &Disks) -> u8 {
+        match self {
+            Search::BruteForce(ai) => ai.get_move(disks).await,
+            Search::Perfect(solver, opening_book) => {
+                PerfectAI::get_move(solver, opening_book, disks).await
+            }
+            Search::MonteCarlo(ai) => ai.get_move(disks),
+            Search::Tabular(ai) => ai.get_move(disks).await,
+        }
+    }
+
+    fn set_budget(&mut self, budget: SearchBudget) {
+        if let Search::BruteForce(ai) = self {
+            // The table remembers how far ahead each score was searched, so it is kept
+            ai.budget = budget;
+            log!(format!("Difficulty changed to {:?}", budget));
+        }
+    }
+}
+
+/// Runs in the Web Worker, searching for the moves the page asks for one at a time
+pub struct AIWorker;
+
+impl AIWorker {
+    /// Starts answering the page's requests, and tells the page the worker has loaded
+    pub fn run() {
+        let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+        let (request_sender, receiver) = mpsc::unbounded_channel();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            match serde_wasm_bindgen::from_value(event.data()) {
+                Ok(request) => request_sender.send(request).unwrap_or_default(),
+                Err(e) => error!(format!("Failed to read request: {}", e)),
+            }
+        });
+        scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        // The worker answers requests until it is terminated
+        on_message.forget();
+        Self::spawn_search_task(scope.clone(), receiver);
+        Self::respond(&scope, &AIResponse::Loaded);
+    }
+
+    /// Creates a task which handles requests in the order they arrive, so a search can await the
+    /// opening book without another request starting meanwhile
+    fn spawn_search_task(
+        scope: DedicatedWorkerGlobalScope,
+        mut receiver: UnboundedReceiver<AIRequest>,
+    ) {
+        spawn_local(async move {
+            let mut search = None;
+            while let Some(request) = receiver.recv().await {
+                match request {
+                    AIRequest::Start { mode, base_url } => {
+                        search = Some(Search::new(mode, &base_url));
+                    }
+                    AIRequest::Move { id, disks } => {
+                        if let Some(search) = &mut search {
+                            let col = search.get_move(&disks).await;
+                            Self::respond(&scope, &AIResponse::Move { id, col });
+                        }
+                    }
+                    AIRequest::SetBudget(budget) => {
+                        if let Some(search) = &mut search {
+                            search.set_budget(budget);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn respond(scope: &DedicatedWorkerGlobalScope, response: &AIResponse) {
+        let sent = serde_wasm_bindgen::to_value(response).and_then(|message| {
+            scope
+                .post_message(&message)
+                .map_err(serde_wasm_bindgen::Error::from)
+        });
+        if let Err(e) = sent {
+            error!(format!("Failed to send response: {}", e));
+        }
+    }
+}
+
+/// A Web Worker running an AIWorker, which is terminated when this is dropped, even in the
+/// middle of a search
+struct WorkerHandle {
+    worker: web_sys::Worker,
+    /// Requests waiting for the worker to load, None once it has
+    pending: Rc<RefCell<Option<Vec<AIRequest>>>>,
+    /// Handles the worker's messages for as long as it runs
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl WorkerHandle {
+    /// Starts a worker from the script at the url, giving its responses to on_response
+    fn spawn(url: &str, on_response: impl Fn(AIResponse) + 'static) -> Result<Self, JsValue> {
+        // The worker script is a no-modules wasm-bindgen shim, loaded with its wasm next to it
+        let script = format!(
+            r#"importScripts("{}");wasm_bindgen("{}");"#,
+            url,
+            url.replace(".js", "_bg.wasm")
+        );
+        let options = BlobPropertyBag::new();
+        options.set_type("application/javascript");
+        let blob =
+            Blob::new_with_str_sequence_and_options(&js_sys::Array::of1(&script.into()), &options)?;
+        let worker = web_sys::Worker::new(&Url::create_object_url_with_blob(&blob)?)?;
+
+        let pending = Rc::new(RefCell::new(Some(Vec::new())));
+        let on_message = {
+            let worker = worker.clone();
+            let pending = Rc::clone(&pending);
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                match serde_wasm_bindgen::from_value(event.data()) {
+                    Ok(AIResponse::Loaded) => {
+                        for request in pending.borrow_mut().take().unwrap_or_default() {
+                            Self::post(&worker, &request);
+                        }
+                    }
+                    Ok(response) => on_response(response),
+                    Err(e) => error!(format!("Failed to read response: {}", e)),
+                }
+            })
+        };
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        Ok(Self {
+            worker,
+            pending,
+            _on_message: on_message,
+        })
+    }
+
+    /// Sends the worker a request, once it has loaded
+    fn send(&self, request: AIRequest) {
+        match self.pending.borrow_mut().as_mut() {
+            Some(pending) => pending.push(request),
+            None => Self::post(&self.worker, &request),
+        }
+    }
+
+    fn post(worker: &web_sys::Worker, request: &AIRequest) {
+        let sent = serde_wasm_bindgen::to_value(request).and_then(|message| {
+            worker
+                .post_message(&message)
+                .map_err(serde_wasm_bindgen::Error::from)
+        });
+        if let Err(e) = sent {
+            error!(format!("Failed to send request: {}", e));
+        }
+    }
+}
+
+impl Drop for WorkerHandle {
+    fn drop(&mut self) {
+        self.worker.terminate();
+        // The handler is about to be freed, so the worker must not call it again
+        self.worker.set_onmessage(None);
+    }
+}
+
+/// Lets an AI on the page search in an AIWorker, making the moves it answers with
+/// Dropping it terminates the worker, without making the move of any search it is running
+/// If the worker cannot be started, the AI plays at random instead
+pub struct SearchWorker {
+    /// None if the worker could not be started
+    worker: RefCell<Option<WorkerHandle>>,
+    /// The search the worker was started with, to start the same search in a new worker
+    mode: RefCell<SearchMode>,
+    /// Id of the latest move requested, answers to any other request are ignored
+    latest_id: Rc<Cell<u32>>,
+    /// Whether the worker is searching for the latest move requested
+    searching: Rc<Cell<bool>>,
+    rerender_board_callback: Callback<GameUpdateMessage>,
+}
+
+impl SearchWorker {
+    /// Starts a worker running the search
+    pub fn new(mode: SearchMode, rerender_board_callback: Callback<GameUpdateMessage>) -> Self {
+        let latest_id = Rc::new(Cell::new(0));
+        let searching = Rc::new(Cell::new(false));
+        let worker = Self::spawn_worker(
+            &mode,
+            Rc::clone(&latest_id),
+            Rc::clone(&searching),
+            rerender_board_callback.clone(),
+        );
+        Self {
+            worker: RefCell::new(worker),
+            mode: RefCell::new(mode),
+            latest_id,
+            searching,
+            rerender_board_callback,
+        }
+    }
+
+    /// Asks the worker for a move, which it makes with the rerender callback once found
+    /// Returns BOARD_WIDTH, or a random column to make at once if there is no worker
+    pub fn request_move(&self, disks: &Disks) -> u8 {
+        let id = self.latest_id.get().wrapping_add(1);
+        self.latest_id.set(id);
+        match &*self.worker.borrow() {
+            Some(worker) => {
+                self.searching.set(true);
+                worker.send(AIRequest::Move {
+                    id,
+                    disks: disks.clone(),
+                });
+                BOARD_WIDTH
+            }
+            None => RandomAI.request_move(disks),
+        }
+    }
+
+    /// Changes how hard a brute force search searches
+    pub fn set_budget(&self, budget: SearchBudget) {
+        if let SearchMode::BruteForce {
+            budget: mode_budget,
+            ..
+        } = &mut *self.mode.borrow_mut()
+        {
+            *mode_budget = budget;
+        }
+        if let Some(worker) = &*self.worker.borrow() {
+            worker.send(AIRequest::SetBudget(budget));
+        }
+    }
+
+    /// Makes sure the move being searched for is never made.
+    /// The worker is busy until its search ends, so it is terminated and replaced with a new one.
+    pub fn cancel(&self) {
+        self.latest_id.set(self.latest_id.get().wrapping_add(1));
+        if self.searching.replace(false) {
+            log!("Cancelled the AI's search.");
+            *self.worker.borrow_mut() = Self::spawn_worker(
+                &self.mode.borrow(),
+                Rc::clone(&self.latest_id),
+                Rc::clone(&self.searching),
+                self.rerender_board_callback.clone(),
+            );
+        }
+    }
+
+    /// Starts a worker running the search, making only the latest move requested from it
+    /// Returns None, logging why, if the page cannot start workers
+    fn spawn_worker(
+        mode: &SearchMode,
+        latest_id: Rc<Cell<u32>>,
+        searching: Rc<Cell<bool>>,
+        rerender_board_callback: Callback<GameUpdateMessage>,
+    ) -> Option<WorkerHandle> {
+        // The worker runs from a blob URL, so it is given the base URL to find the app's files
+        let base_url = gloo_utils::document()
+            .base_uri()
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let worker = WorkerHandle::spawn(&format!("{}{}", base_url, WORKER_URL), move |response| {
+            if let AIResponse::Move { id, col } = response {
+                if id == latest_id.get() {
+                    searching.set(false);
+                    rerender_board_callback.emit(SimpleMessage(col)); // Make the move
+                }
+            }
+        });
+        let worker = match worker {
+            Ok(worker) => worker,
+            Err(e) => {
+                error!(
+                    "Failed to start the AI's worker, playing at random instead:",
+                    e
+                );
+                return None;
+            }
+        };
+        worker.send(AIRequest::Start {
+            mode: mode.clone(),
+            base_url,
+        });
+        Some(worker)
+    }
+}
@@ -19,10 +19,40 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+use gloo::{console::error, net::http::Request};
 use rand::seq::SliceRandom;
 
-pub const AI_INCREMENT_MESSAGE: u8 = 8;
 pub const BRUTE_FORCE_SURVIVAL_DIFFICULTY_INCREMENT: u8 = 4;
+/// Extra time the survival AI gets to think about each move every round, in milliseconds
+pub const BRUTE_FORCE_SURVIVAL_TIME_INCREMENT_MS: u32 = 250;
+
+/// Returns the time in milliseconds since the epoch, on the page or in a web worker
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Downloads a file the app serves, logging why if it could not be
+/// The name says what the file is in the log
+pub async fn fetch_bytes(url: &str, name: &str) -> Option<Vec<u8>> {
+    let response = match Request::get(url).send().await {
+        Ok(response) if response.ok() => response,
+        Ok(response) => {
+            error!(format!("Failed to fetch {}: {}", name, response.status()));
+            return None;
+        }
+        Err(e) => {
+            error!(format!("Failed to fetch {}: {}", name, e));
+            return None;
+        }
+    };
+    match response.binary().await {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            error!(format!("Failed to fetch {}: {}", name, e));
+            None
+        }
+    }
+}
 
 /// Given a list of columns to choose from, return one at random.
 pub fn random_col_from_options(options: &Vec<u8>) -> Option<&u8> {
@@ -1,3 +1,6 @@
+//! ai_worker is the Web Worker the app's AIs search in, so the page keeps responding while
+//! they think
+
 /*
  * This file is part of Rust-Connect-Four
  * Copyright (C) 2022 Alexander Broihier <alexanderbroihier@gmail.com>
@@ -17,14 +20,8 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-extern crate cc;
+use yew_app::ai::impls::worker::AIWorker;
 
 fn main() {
-    println!("cargo:rerun-if-changed=cpplib/board.cpp");
-    cc::Build::new()
-        .cpp(true)
-        .file("cpplib/board.cc")
-        .file("cpplib/safe_board.cc")
-        // .flag("-std=c++20")
-        .compile("board");
+    AIWorker::run();
 }
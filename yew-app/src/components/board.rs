@@ -32,7 +32,6 @@ use crate::{
         util::{GameUpdateMessage, SecondPlayerAIMode, SecondPlayerSurvivalAIMode},
     },
 };
-use constants::*;
 use std::{cell::RefCell, rc::Rc};
 use yew::{html, Component, Context, Html};
 use yew_router::prelude::*;
@@ -76,11 +75,15 @@ impl Component for Board {
     fn view(&self, ctx: &Context<Self>) -> Html {
         let rerender_board_callback = ctx.link().callback(|msg: BoardMessage| msg);
         let route = ctx.link().route::<Route>().unwrap_or(Route::Home);
+        let (width, height) = self.board.borrow().disks.size();
 
         html! {
             <>
-                <div class={ "board-background" }>
-                    {(0..(BOARD_WIDTH as u8)).into_iter().map(|num| { // Create Columns for the Board
+                <div class={ "board-background" } style={ format!(
+                    "grid-template-columns: repeat({}, 1fr); grid-template-rows: repeat({}, 1fr);",
+                    width, height
+                )}>
+                    {(0..width).into_iter().map(|num| { // Create Columns for the Board
                         html! {
                             <Column col_num={ num } disks={ Rc::clone(&self.board) } in_game={ // Accept input if in game
                                 match route {
@@ -152,6 +155,15 @@ impl Board {
                             AIRoute::BruteForce => {
                                 board.borrow_mut().init_ai(SecondPlayerAIMode::BruteForce)
                             }
+                            AIRoute::Perfect => {
+                                board.borrow_mut().init_ai(SecondPlayerAIMode::Perfect)
+                            }
+                            AIRoute::MCTS => {
+                                board.borrow_mut().init_ai(SecondPlayerAIMode::MCTS)
+                            }
+                            AIRoute::Tabular => {
+                                board.borrow_mut().init_ai(SecondPlayerAIMode::Tabular)
+                            }
                             AIRoute::Survival => board
                                 .borrow_mut()
                                 .init_survival(SecondPlayerSurvivalAIMode::BruteForce),
@@ -26,7 +26,6 @@
  */
 
 use crate::util::{board_state::BoardState, util::DiskColor};
-use constants::*;
 use gloo::events::EventListener;
 use std::{cell::RefCell, rc::Rc};
 use wasm_bindgen::JsCast;
@@ -89,6 +88,7 @@ impl Component for Column {
     /// Renders the Column and the related disks in the Board
     /// If in the middle of a game, allows for user input
     fn view(&self, ctx: &Context<Self>) -> Html {
+        let (width, height) = ctx.props().disks.borrow().disks.size();
         html! {
             <>
                 // If player can make a move in this column
@@ -113,10 +113,10 @@ impl Component for Column {
                     html!{<button
                         class={ match ctx.props().col_num {
                             0 => classes!("column-btn-leftmost"),
-                            6 => classes!("column-btn-rightmost"),
+                            col if col == width - 1 => classes!("column-btn-rightmost"),
                             _ => classes!("column-btn"),
                         }} // Make column clickable
-                        style={format!("grid-column-start: {}", ctx.props().col_num + 1)}
+                        style={format!("grid-column-start: {}; grid-row-end: {}", ctx.props().col_num + 1, height + 1)}
                         onclick={ self.onclick.clone() }
                     />}
                 } else {
@@ -124,10 +124,10 @@ impl Component for Column {
                     *self.global_keyboard_listener.borrow_mut() = None;
                     html!{}
                 }}
-                {(0..(BOARD_HEIGHT as u8)).into_iter().map(|row_num| html! { // Display all disks in the Column
+                {(0..height).into_iter().map(|row_num| html! { // Display all disks in the Column
                     <div
                         class={classes!(ctx.props().style_of_disk(row_num))}
-                        style={format!("grid-column-start: {}; grid-row-start: {};", ctx.props().col_num + 1, BOARD_HEIGHT - row_num)}
+                        style={format!("grid-column-start: {}; grid-row-start: {};", ctx.props().col_num + 1, height - row_num)}
                     />
                 }).collect::<Html>()}
             </>
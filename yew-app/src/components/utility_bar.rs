@@ -148,6 +148,17 @@ impl Component for UtilityBar {
                                                 html!{}
                                             }
                                         }}
+                                        {{
+                                            if let Some(notice) = &ctx.props().board.borrow().server_notice {
+                                                html!{
+                                                    <div style={"padding-top:10px"} class={classes!("utility-text-plain")}>
+                                                        { notice.clone() }
+                                                    </div>
+                                                }
+                                            } else {
+                                                html!{}
+                                            }
+                                        }}
                                     </span>
                                 </>
                             }
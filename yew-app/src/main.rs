@@ -26,14 +26,9 @@
  */
 
 use yew::prelude::*;
+use yew_app::{components, router};
 use yew_router::prelude::*;
 
-pub mod ai;
-pub mod components;
-pub mod pages;
-pub mod router;
-pub mod util;
-
 /// Main application
 /// Some logic is handled both by the individual page, as determined by the router
 /// The Board and BackButton components also read the current route to determine state, and act accordingly
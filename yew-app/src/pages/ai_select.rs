@@ -37,6 +37,9 @@ pub fn ai_select() -> Html {
                 <p class="menu-txt">{"Select AI"}</p>
                 <GameButton<AIRoute> text={"Random"} route={AIRoute::Random} />
                 <GameButton<AIRoute> text={"Brute Force"} route={AIRoute::BruteForce} />
+                <GameButton<AIRoute> text={"Perfect"} route={AIRoute::Perfect} />
+                <GameButton<AIRoute> text={"Monte Carlo"} route={AIRoute::MCTS} />
+                <GameButton<AIRoute> text={"Learned Table"} route={AIRoute::Tabular} />
                 <GameButton<AIRoute> text={"Survival"} route={AIRoute::Survival} />
                 <GameButton<Route> text={"Back"} route={Route::Home} />
             </div>
@@ -21,6 +21,7 @@
  */
 
 use crate::{components::game_button::GameButton, router::Route};
+use constants::ConnectionProtocol;
 use gloo::{console::error, utils::document};
 use yew::prelude::*;
 
@@ -45,7 +46,8 @@ pub fn lobby_select() -> Html {
                             for="lobby">{"Lobby Name: "}</label>
                     <input type="text" name="lobby" id="lobby-input"
                             style={"text-align:center;"}
-                            placeholder={"(optional)"} maxlength={"16"}/>
+                            placeholder={"(optional)"} maxlength={ConnectionProtocol::MAX_LOBBY_NAME_LENGTH.to_string()} pattern={"[A-Za-z0-9_\\-]*"}
+                            title={"Letters, digits, - and _"}/>
                     <input type="submit" value="Join" class="menu-btn"/>
                 </form>
                 <GameButton<Route> text={"Back"} route={Route::Home} />
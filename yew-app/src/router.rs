@@ -48,6 +48,9 @@ pub fn switch_ai_route(route: AIRoute) -> Html {
     match route {
         AIRoute::Random => html! {},
         AIRoute::BruteForce => html! {},
+        AIRoute::Perfect => html! {},
+        AIRoute::MCTS => html! {},
+        AIRoute::Tabular => html! {},
         AIRoute::Survival => html! {},
     }
 }
@@ -81,6 +84,12 @@ pub enum AIRoute {
     Random,
     #[at("/versus-bot/brute-force")]
     BruteForce,
+    #[at("/versus-bot/perfect")]
+    Perfect,
+    #[at("/versus-bot/mcts")]
+    MCTS,
+    #[at("/versus-bot/tabular")]
+    Tabular,
     #[at("/versus-bot/survival")]
     Survival,
 }
@@ -26,7 +26,10 @@ use crate::{
         second_player_extension::SecondPlayerExtension,
         util::{
             DiskColor,
-            GameUpdateMessage::{self, BoardState as BoardStateMessage, SimpleMessage},
+            GameUpdateMessage::{
+                self, BoardState as BoardStateMessage, Disks as DisksMessage, ServerNotice,
+                SimpleMessage,
+            },
             RequestMoveResult, SecondPlayerAIMode, SecondPlayerSurvivalAIMode,
         },
     },
@@ -62,6 +65,7 @@ pub struct BoardState {
     pub num_moves: u8,
     pub second_player_extension: SecondPlayerExtension,
     pub info_message: InfoMessage,
+    pub server_notice: Option<String>, // latest notice sent by the server, such as maintenance warnings
 }
 
 /// Implements functions to check if the game has been won
@@ -76,6 +80,7 @@ impl BoardState {
             num_moves: 0,
             second_player_extension: SecondPlayerExtension::new(rerender_board_callback),
             info_message: InfoMessage::NoMessage,
+            server_notice: None,
         }
     }
 
@@ -88,6 +93,7 @@ impl BoardState {
         self.num_moves = 0;
         self.second_player_extension.remove_extension();
         self.info_message = InfoMessage::P1Turn;
+        self.server_notice = None;
     }
 
     /// Given the desired move of the current player, update the board state. If
@@ -212,12 +218,19 @@ impl BoardState {
                         }
                     }
                     // second player joined, first player can now move
+                    // (a player returning to a restored game already got the board state instead)
                     ConnectionProtocol::SECOND_PLAYER_CONNECTED => {
-                        if self.current_player == DiskColor::P1 {
+                        if self.current_player == DiskColor::P1
+                            && self.info_message == InfoMessage::WaitingForOpponent
+                        {
                             self.can_move = true;
                             self.info_message = InfoMessage::P1Turn;
                         }
                     }
+                    // an update from the server was missed, so ask it for the whole board
+                    ConnectionProtocol::RESYNC => {
+                        self.second_player_extension.request_resync();
+                    }
                     // connection terminated or failed to connect
                     ConnectionProtocol::CONNECTION_FAILURE => {
                         self.info_message = InfoMessage::ConnectionFailed;
@@ -234,6 +247,18 @@ impl BoardState {
                 }
             }
 
+            ServerNotice(notice) => {
+                self.server_notice = Some(notice);
+            }
+
+            // the board the AI plays on, which the game waits for
+            DisksMessage(disks) => {
+                if self.num_moves == 0 {
+                    self.disks = disks;
+                    self.can_move = true;
+                }
+            }
+
             _ => panic!("Received invalid update message from the task reading from the server or AI."),
         }
     }
@@ -252,6 +277,8 @@ impl BoardState {
     /// Resets the board, and extends with an AI as the second player.
     pub fn init_ai(&mut self, ai_type: SecondPlayerAIMode) {
         self.reset(); // reset board data
+        // The learned table AI plays on a board the size of its table, which it sends once loaded
+        self.can_move = !matches!(ai_type, SecondPlayerAIMode::Tabular);
         self.second_player_extension.init_ai(ai_type); // set the second player to be an AI
     }
 
@@ -300,7 +327,7 @@ impl BoardState {
     fn update_game_history(&mut self, selected_col: u8) {
         self.game_history[self.num_moves as usize] = selected_col;
         self.num_moves += 1;
-        if self.num_moves == BOARD_WIDTH * BOARD_HEIGHT { // End of game
+        if self.disks.is_full() { // End of game
             self.can_move = false;
         }
     }
@@ -308,7 +335,7 @@ impl BoardState {
     /// Update the info message based off of the variant of move that was made
     fn update_info_message(&mut self, variant: UpdateInfoMessageVariant) {
         let num_moves = self.disks.get_num_disks();
-        self.info_message = if num_moves == BOARD_WIDTH * BOARD_HEIGHT {
+        self.info_message = if self.disks.is_full() {
             InfoMessage::Draw
         } else if num_moves % 2 == 0 {
             if variant == UpdateInfoMessageVariant::GameWon {
@@ -27,13 +27,18 @@
 
 use super::util::DiskColor;
 use constants::{GameUpdate, BOARD_HEIGHT, BOARD_WIDTH};
+use serde::{Deserialize, Serialize};
 
 /// Internal storage of the entire board
-#[derive(PartialEq, Clone, Debug)]
+/// A board smaller than BOARD_WIDTH x BOARD_HEIGHT only uses the leftmost columns and the
+/// bottom rows, so its disks are laid out just as on a full board
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Disks {
     position: u64, // records the location of disks for the current player as 1s
     mask: u64,     // records the location of all disks as 1s
     is_p1_turn: bool,
+    width: u8,
+    height: u8,
 }
 
 /// Create the board as it would be at the very start of the match
@@ -43,6 +48,8 @@ impl Default for Disks {
             position: 0,
             mask: 0,
             is_p1_turn: true,
+            width: BOARD_WIDTH,
+            height: BOARD_HEIGHT,
         }
     }
 }
@@ -54,6 +61,8 @@ impl From<GameUpdate> for Disks {
             position: game.position,
             mask: game.mask,
             is_p1_turn: game.is_p1_turn,
+            width: BOARD_WIDTH,
+            height: BOARD_HEIGHT,
         }
     }
 }
@@ -63,6 +72,21 @@ impl From<GameUpdate> for Disks {
 /// there are etc. This way it is easy for us to make optimizations later without
 /// needing to change how the interface is used.
 impl Disks {
+    /// Creates an empty board with the given number of columns and rows, which must be at most
+    /// BOARD_WIDTH and BOARD_HEIGHT
+    pub fn with_size(width: u8, height: u8) -> Self {
+        Self {
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of columns and rows of the board
+    pub fn size(&self) -> (u8, u8) {
+        (self.width, self.height)
+    }
+
     /// Returns if the other player has won
     pub fn check_last_drop_won(&self) -> bool {
         let other_player_position = self.position ^ self.mask;
@@ -122,20 +146,21 @@ impl Disks {
     /// Returns the total number of disks on the board
     pub fn get_num_disks(&self) -> u8 {
         let mut num_disks = 0u8;
-        for col in 0..(BOARD_WIDTH as u8) {
+        for col in 0..self.width {
             num_disks += self.first_opening_in_col(col);
         }
         num_disks
     }
 
-    /// Returns whether the given column is is full (has no open slots)
+    /// Returns whether the given column is is full (has no open slots), or is not on the board
     pub fn is_col_full(&self, col: u8) -> bool {
-        self.mask & ((1 << BOARD_HEIGHT - 1) << (col * (BOARD_HEIGHT + 1))) != 0
+        col >= self.width
+            || self.mask & ((1 << (self.height - 1)) << (col * (BOARD_HEIGHT + 1))) != 0
     }
 
     /// Returns whether the entire board is full (has no open slots)
     pub fn is_full(&self) -> bool {
-        for col in 0..(BOARD_WIDTH as u8) {
+        for col in 0..self.width {
             if !self.is_col_full(col) {
                 return false;
             }
@@ -146,7 +171,7 @@ impl Disks {
     /// Gets the number of columns that are not full
     pub fn num_open_cols(&self) -> u8 {
         let mut num_open_cols = 0u8;
-        for col in 0..(BOARD_WIDTH as u8) {
+        for col in 0..self.width {
             if !self.is_col_full(col) {
                 num_open_cols += 1;
             }
@@ -187,15 +212,15 @@ impl Disks {
 
     ///// PRIVATE METHODS /////
 
-    /// Returns the first empty row in the column, or BOARD_HEIGHT if the column is full
+    /// Returns the first empty row in the column, or the height of the board if the column is full
     fn first_opening_in_col(&self, col: u8) -> u8 {
         let mut idx = 1 << (col * (BOARD_HEIGHT + 1));
-        for row in 0..BOARD_HEIGHT {
+        for row in 0..self.height {
             if self.mask & idx == 0 {
                 return row;
             }
             idx <<= 1;
         }
-        BOARD_HEIGHT
+        self.height
     }
 }
@@ -20,12 +20,15 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use crate::util::util::GameUpdateMessage::{self, BoardState, SimpleMessage, UndoMove};
-use constants::ConnectionProtocol;
+use crate::util::util::GameUpdateMessage::{
+    self, BoardState, ServerNotice, SimpleMessage, UndoMove,
+};
+use constants::{ConnectionProtocol, GameUpdate, SequencedUpdate};
 use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
+use gloo::storage::{SessionStorage, Storage};
 use gloo::net::websocket::{
     futures::WebSocket,
     Message::{self, Bytes, Text},
@@ -54,6 +57,11 @@ impl From<GameUpdateMessage> for Message {
     }
 }
 
+/// Key the seat token for the given lobby is stored under in session storage
+fn seat_token_key(lobby: &str) -> String {
+    format!("seat-token:{}", lobby)
+}
+
 /// Spawns reader and writer tasks to communicate with the server
 /// On success, returns:
 ///     an UnboundedSender to sent messages to the writer thread, which will then write to the server
@@ -78,18 +86,72 @@ pub fn spawn_connection_tasks(
         callback,
         connection_est_sender,
         Rc::clone(&send_update_as_col_num),
+        seat_token_key(&lobby),
     );
     spawn_writer_task(writer, receiver, connection_est_receiver, lobby);
 
     Ok((sender, send_update_as_col_num))
 }
 
+/// The board as built up from the sequenced updates sent by the server
+struct ReceivedBoard {
+    board: GameUpdate,
+    /// Sequence number the next update should have
+    next_seq: u16,
+    /// Whether a snapshot was asked for, moves are ignored until it arrives
+    resyncing: bool,
+}
+
+impl ReceivedBoard {
+    /// Starts from an empty board, as the server does for each client
+    fn new() -> Self {
+        Self {
+            board: GameUpdate::default(),
+            next_seq: 1,
+            resyncing: false,
+        }
+    }
+
+    /// Applies the update, returning the board if it changed
+    /// Returns Err(()) if an update was missed, or the move could not be played, so a snapshot is needed
+    fn receive(&mut self, update: SequencedUpdate) -> Result<Option<GameUpdate>, ()> {
+        match update {
+            // A snapshot replaces the board, however it was built up
+            SequencedUpdate::Snapshot { seq, update } => {
+                if !self.resyncing && seq == self.next_seq && update != self.board {
+                    log!("Board did not match snapshot from server.");
+                }
+                self.board = update;
+                self.next_seq = seq.wrapping_add(1);
+                self.resyncing = false;
+            }
+            SequencedUpdate::Move { .. } if self.resyncing => return Ok(None),
+            SequencedUpdate::Move { seq, delta } => {
+                if seq != self.next_seq {
+                    error!(format!("Expected update {} from server, got {}.", self.next_seq, seq));
+                    return Err(());
+                }
+                if let Err(e) = self.board.apply(delta) {
+                    error!(format!("Could not apply update {} from server: {}", seq, e));
+                    return Err(());
+                }
+                self.next_seq = seq.wrapping_add(1);
+            }
+        }
+        Ok(Some(self.board.clone()))
+    }
+}
+
 /// Task to read data sent from the server
+/// Seat tokens sent by the server are kept in session storage under seat_token_key,
+/// so the player can take back their seat if the server restarts
+/// Game updates are applied to the board in order, and a snapshot is asked for if one was missed
 fn spawn_reader_task(
     mut reader: SplitStream<WebSocket>,
     callback: Callback<GameUpdateMessage>,
     connection_est_sender: OneshotSender<()>,
     send_update_as_col_num: Rc<RefCell<bool>>,
+    seat_token_key: String,
 ) {
     spawn_local(async move {
         log!("Entered reader thread.");
@@ -106,19 +168,35 @@ fn spawn_reader_task(
             }
         }
         // Read all server messages and use a callback to update the main task with new messages
+        let mut received = ReceivedBoard::new();
         while let Some(Ok(msg)) = reader.next().await {
             match msg {
                 Bytes(bytes) => {
                     if bytes.len() == 1 {
                         callback.emit(SimpleMessage(bytes[0]));
-                    } else if let Ok(update) = ConnectionProtocol::decode_message(bytes) {
-                        callback.emit(BoardState(update));
+                    } else if let Some(token) = ConnectionProtocol::decode_seat_token(&bytes) {
+                        SessionStorage::set(&seat_token_key, token).unwrap_or_default();
+                    } else if matches!(
+                        bytes.first(),
+                        Some(&ConnectionProtocol::MOVE | &ConnectionProtocol::SNAPSHOT)
+                    ) {
+                        // A snapshot with a bad checksum is as good as missed
+                        let update = ConnectionProtocol::decode_sequenced(&bytes).ok_or(());
+                        match update.and_then(|update| received.receive(update)) {
+                            Ok(Some(board)) => callback.emit(BoardState(board)),
+                            Ok(None) => {}
+                            Err(()) => {
+                                received.resyncing = true;
+                                callback.emit(SimpleMessage(ConnectionProtocol::RESYNC));
+                            }
+                        }
                     } else {
                         error!("Received unrecognizable message from server.");
                     }
                 }
-                _ => {
-                    error!("Expected bytes but recieved text from server.")
+                // Text is a notice from the server to display to the player
+                Text(notice) => {
+                    callback.emit(ServerNotice(notice));
                 }
             }
         }
@@ -138,9 +216,14 @@ fn spawn_writer_task(
 ) {
     spawn_local(async move {
         log!("Entered writer thread.");
-        // Wait until it is confirmed that a connection is established with the server before sending the lobby name
+        // Wait until it is confirmed that a connection is established with the server before sending the lobby name,
+        // along with the seat token from the last time this lobby was joined, if any
         if let Ok(_) = connection_est_receiver.await {
-            writer.send(Text(lobby)).await.unwrap();
+            let seat_token = SessionStorage::get::<u64>(&seat_token_key(&lobby)).ok();
+            writer
+                .send(Text(ConnectionProtocol::encode_join_request(&lobby, seat_token)))
+                .await
+                .unwrap();
         } else {
             log!("Connection to server failed, exiting writer thread.");
             return;
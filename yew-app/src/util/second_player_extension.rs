@@ -29,7 +29,13 @@ use super::{
     util::{DiskColor, SecondPlayerAIMode, SecondPlayerSurvivalAIMode},
 };
 use crate::{
-    ai::impls::{brute_force::BruteForceAI, random::RandomAI},
+    ai::impls::{
+        brute_force::{BruteForceAI, SearchBudget},
+        mcts::{MCTSConfig, MCTSAI},
+        tabular::TabularAI,
+        perfect::PerfectAI,
+        random::RandomAI,
+    },
     util::{
         net,
         util::{
@@ -81,7 +87,21 @@ impl SecondPlayerExtension {
             ai: match ai_type {
                 SecondPlayerAIMode::Random => Box::new(RandomAI),
                 SecondPlayerAIMode::BruteForce => {
-                    Box::new(BruteForceAI::new(20, self.rerender_board_callback.clone()))
+                    Box::new(BruteForceAI::new(
+                        SearchBudget::VERSUS_BOT,
+                        true,
+                        self.rerender_board_callback.clone(),
+                    ))
+                }
+                SecondPlayerAIMode::Perfect => {
+                    Box::new(PerfectAI::new(self.rerender_board_callback.clone()))
+                }
+                SecondPlayerAIMode::MCTS => Box::new(MCTSAI::new(
+                    MCTSConfig::VERSUS_BOT,
+                    self.rerender_board_callback.clone(),
+                )),
+                SecondPlayerAIMode::Tabular => {
+                    Box::new(TabularAI::new(self.rerender_board_callback.clone()))
                 }
             },
             ai_color: DiskColor::P2,
@@ -93,7 +113,11 @@ impl SecondPlayerExtension {
         self.mode = SurvivalMode {
             ai: match ai_type {
                 SecondPlayerSurvivalAIMode::BruteForce => {
-                    Box::new(BruteForceAI::new(1, self.rerender_board_callback.clone()))
+                    Box::new(BruteForceAI::new(
+                        SearchBudget::survival(1),
+                        false,
+                        self.rerender_board_callback.clone(),
+                    ))
                 }
             },
             ai_color: DiskColor::P2,
@@ -122,6 +146,9 @@ impl SecondPlayerExtension {
                 )?; // Send the server the updated board state / pass off to online opponent
             }
             AI { ai, .. } => {
+                if selected_col == ConnectionProtocol::UNDO {
+                    ai.cancel(); // The AI may be searching for a reply to the undone move
+                }
                 if selected_col != ConnectionProtocol::UNDO && board_state.can_move { // Don't run AI if a move was undone
                     let res = ai.request_move(&board_state.disks);
                     return Ok(if res < BOARD_WIDTH {
@@ -132,6 +159,9 @@ impl SecondPlayerExtension {
                 }
             }
             SurvivalMode { ai, .. } => {
+                if selected_col == ConnectionProtocol::UNDO {
+                    ai.cancel(); // The AI may be searching for a reply to the undone move
+                }
                 if selected_col != ConnectionProtocol::UNDO && board_state.can_move { // Don't run AI if a move was undone
                     let res = ai.request_move(&board_state.disks);
                     return Ok(if res < BOARD_WIDTH {
@@ -146,6 +176,16 @@ impl SecondPlayerExtension {
         Ok(RequestMoveResult::WillRerenderLater) // Request for move made
     }
 
+    /// Asks the server for the whole board, after an update from it was missed
+    /// Does nothing if the SecondPlayerExtension is not an online player
+    pub fn request_resync(&self) {
+        if let OnlinePlayer { sender, .. } = &self.mode {
+            sender
+                .send(SimpleMessage(ConnectionProtocol::RESYNC))
+                .unwrap_or_default();
+        }
+    }
+
     /// Returns whether the SecondPlayerExtension is an online player and undo is enabled for this match
     pub fn undo_enabled_for_online(&self) -> bool {
         if let OnlinePlayer {
@@ -52,6 +52,7 @@ pub enum GameUpdateMessage {
     Disks(Disks),
     SimpleMessage(u8),
     UndoMove(GameUpdate),
+    ServerNotice(String),
 }
 
 /// Enum that represents the result of a move requested by the second player extension
@@ -66,6 +67,9 @@ pub enum RequestMoveResult {
 pub enum SecondPlayerAIMode {
     Random,
     BruteForce,
+    Perfect,
+    MCTS,
+    Tabular,
 }
 
 /// Enum that represents a SurvivalAI implementation to use